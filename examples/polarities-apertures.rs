@@ -6,8 +6,6 @@ use std::convert::TryFrom;
 
 use gerber_types::*;
 
-const VERSION: &'static str = env!("CARGO_PKG_VERSION");
-
 fn main() {
     let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 6);
     let commands: Vec<Command> = vec![
@@ -17,10 +15,9 @@ fn main() {
         .into(),
         ExtendedCode::CoordinateFormat(cf).into(),
         ExtendedCode::Unit(Unit::Inches).into(),
-        ExtendedCode::FileAttribute(FileAttribute::GenerationSoftware(GenerationSoftware::new(
+        ExtendedCode::FileAttribute(FileAttribute::GenerationSoftware(generation_software!(
             "Rust Gerber",
-            "gerber-types",
-            Some(VERSION),
+            "gerber-types"
         )))
         .into(),
         ExtendedCode::FileAttribute(FileAttribute::Part(Part::Other(
@@ -274,7 +271,7 @@ fn main() {
         )))))
         .into(),
         FunctionCode::DCode(DCode::SelectAperture(10)).into(),
-        FunctionCode::GCode(GCode::RegionMode(true)).into(),
+        FunctionCode::GCode(GCode::RegionMode(RegionMode::On)).into(),
         FunctionCode::DCode(DCode::Operation(Operation::Move(Some(Coordinates::new(
             CoordinateNumber::try_from(0.5).unwrap(),
             2,
@@ -311,7 +308,7 @@ fn main() {
             None,
         )))
         .into(),
-        FunctionCode::GCode(GCode::RegionMode(false)).into(),
+        FunctionCode::GCode(GCode::RegionMode(RegionMode::Off)).into(),
         FunctionCode::DCode(DCode::SelectAperture(18)).into(),
         FunctionCode::DCode(DCode::Operation(Operation::Flash(Some(Coordinates::new(
             0,
@@ -326,7 +323,7 @@ fn main() {
         )))))
         .into(),
         ExtendedCode::LoadPolarity(Polarity::Clear).into(),
-        FunctionCode::GCode(GCode::RegionMode(true)).into(),
+        FunctionCode::GCode(GCode::RegionMode(RegionMode::On)).into(),
         FunctionCode::DCode(DCode::Operation(Operation::Move(Some(Coordinates::new(
             1,
             CoordinateNumber::try_from(2.5).unwrap(),
@@ -386,7 +383,7 @@ fn main() {
             None,
         )))
         .into(),
-        FunctionCode::GCode(GCode::RegionMode(false)).into(),
+        FunctionCode::GCode(GCode::RegionMode(RegionMode::Off)).into(),
         ExtendedCode::LoadPolarity(Polarity::Dark).into(),
         FunctionCode::DCode(DCode::SelectAperture(10)).into(),
         FunctionCode::DCode(DCode::Operation(Operation::Move(Some(Coordinates::new(
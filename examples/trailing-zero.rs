@@ -0,0 +1,78 @@
+//! A complete file with trailing zero omission (`%FST...*%`).
+//!
+//! Trailing zero omission is deprecated, but some very old readers require it. The coordinates
+//! are padded to the full number of integer digits and the trailing zeros of the decimal part
+//! are omitted, e.g. `X1.5` in the 2.4 format is written as `X015`.
+
+use std::convert::TryFrom;
+use std::io::stdout;
+
+use gerber_types::{
+    generation_software, Aperture, ApertureDefinition, Circle, Command, CommentContent,
+    CoordinateFormat, CoordinateMode, CoordinateNumber, Coordinates, DCode, ExtendedCode,
+    FileAttribute, FunctionCode, GCode, GerberCode, InterpolationMode, MCode, Operation, Polarity,
+    Unit, ZeroOmission,
+};
+
+fn main() {
+    let cf = CoordinateFormat::new(ZeroOmission::Trailing, CoordinateMode::Absolute, 2, 4);
+    let coordinates = |x: f64, y: f64| {
+        Coordinates::new(
+            CoordinateNumber::try_from(x).unwrap(),
+            CoordinateNumber::try_from(y).unwrap(),
+            cf,
+        )
+    };
+    let commands: Vec<Command> = vec![
+        FunctionCode::GCode(GCode::Comment(CommentContent::String(
+            "Trailing zero omission".to_string(),
+        )))
+        .into(),
+        ExtendedCode::CoordinateFormat(cf).into(),
+        ExtendedCode::Unit(Unit::Millimeters).into(),
+        ExtendedCode::FileAttribute(FileAttribute::GenerationSoftware(generation_software!(
+            "MakerPnP",
+            "gerber-types"
+        )))
+        .into(),
+        ExtendedCode::LoadPolarity(Polarity::Dark).into(),
+        ExtendedCode::ApertureDefinition(ApertureDefinition::new(
+            10,
+            Aperture::Circle(Circle::new(0.1)),
+        ))
+        .into(),
+        FunctionCode::DCode(DCode::SelectAperture(10)).into(),
+        FunctionCode::DCode(DCode::Operation(Operation::Move(Some(coordinates(
+            0.0, 0.0,
+        )))))
+        .into(),
+        FunctionCode::GCode(GCode::InterpolationMode(InterpolationMode::Linear)).into(),
+        FunctionCode::DCode(DCode::Operation(Operation::Interpolate(
+            Some(coordinates(12.5, 0.0)),
+            None,
+        )))
+        .into(),
+        FunctionCode::DCode(DCode::Operation(Operation::Interpolate(
+            Some(coordinates(12.5, 1.25)),
+            None,
+        )))
+        .into(),
+        FunctionCode::DCode(DCode::Operation(Operation::Interpolate(
+            Some(coordinates(0.0, 1.25)),
+            None,
+        )))
+        .into(),
+        FunctionCode::DCode(DCode::Operation(Operation::Interpolate(
+            Some(coordinates(0.0, 0.0)),
+            None,
+        )))
+        .into(),
+        FunctionCode::DCode(DCode::Operation(Operation::Flash(Some(coordinates(
+            6.25, 0.625,
+        )))))
+        .into(),
+        FunctionCode::MCode(MCode::EndOfFile).into(),
+    ];
+    let mut stdout = stdout();
+    commands.serialize(&mut stdout).unwrap();
+}
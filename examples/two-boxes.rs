@@ -4,14 +4,12 @@
 use std::io::stdout;
 
 use gerber_types::{
-    Aperture, ApertureDefinition, Circle, Command, CommentContent, CoordinateFormat,
-    CoordinateMode, Coordinates, DCode, ExtendedCode, FileAttribute, FunctionCode, GCode,
-    GenerationSoftware, GerberCode, InterpolationMode, MCode, Operation, Part, Polarity, Unit,
+    generation_software, Aperture, ApertureDefinition, Circle, Command, CommentContent,
+    CoordinateFormat, CoordinateMode, Coordinates, DCode, ExtendedCode, FileAttribute,
+    FunctionCode, GCode, GerberCode, InterpolationMode, MCode, Operation, Part, Polarity, Unit,
     ZeroOmission,
 };
 
-const VERSION: &'static str = env!("CARGO_PKG_VERSION");
-
 fn main() {
     let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 6);
     let commands: Vec<Command> = vec![
@@ -21,10 +19,9 @@ fn main() {
         .into(),
         ExtendedCode::Unit(Unit::Millimeters).into(),
         ExtendedCode::CoordinateFormat(cf).into(),
-        ExtendedCode::FileAttribute(FileAttribute::GenerationSoftware(GenerationSoftware::new(
+        ExtendedCode::FileAttribute(FileAttribute::GenerationSoftware(generation_software!(
             "MakerPnP",
-            "gerber-types",
-            Some(VERSION),
+            "gerber-types"
         )))
         .into(),
         ExtendedCode::FileAttribute(FileAttribute::Part(Part::Other("example".to_string()))).into(),
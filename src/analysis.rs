@@ -0,0 +1,521 @@
+//! Analysis of command streams.
+
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+
+use crate::attributes::{FileFunction, Net, ObjectAttribute};
+use crate::coordinates::{CoordinateNumber, CoordinateOffset, Coordinates};
+use crate::extended_codes::{Aperture, ApertureDefinition, Circle, Polygon, Rectangular};
+use crate::function_codes::{CommentContent, DCode, GCode, Operation, StandardComment};
+use crate::traits::PartialGerberCode;
+use crate::types::{Command, ExtendedCode, FunctionCode};
+use crate::validation::file_function;
+
+fn hole(hole_diameter: Option<f64>) -> String {
+    match hole_diameter {
+        Some(hole_diameter) => format!(", hole {}", hole_diameter),
+        None => String::new(),
+    }
+}
+
+fn circle_dimensions(circle: &Circle) -> String {
+    format!("diameter {}{}", circle.diameter, hole(circle.hole_diameter))
+}
+
+fn rectangular_dimensions(rectangular: &Rectangular) -> String {
+    format!(
+        "{} x {}{}",
+        rectangular.x,
+        rectangular.y,
+        hole(rectangular.hole_diameter)
+    )
+}
+
+fn polygon_dimensions(polygon: &Polygon) -> String {
+    let mut dimensions = format!(
+        "diameter {}, {} vertices",
+        polygon.diameter, polygon.vertices
+    );
+    if let Some(rotation) = polygon.rotation {
+        let _ = write!(dimensions, ", rotation {}", rotation);
+    }
+    dimensions.push_str(&hole(polygon.hole_diameter));
+    dimensions
+}
+
+fn shape_and_dimensions(aperture: &Aperture) -> (String, String) {
+    match aperture {
+        Aperture::Circle(circle) => ("Circle".to_string(), circle_dimensions(circle)),
+        Aperture::Rectangle(rectangular) => {
+            ("Rectangle".to_string(), rectangular_dimensions(rectangular))
+        }
+        Aperture::Obround(rectangular) => {
+            ("Obround".to_string(), rectangular_dimensions(rectangular))
+        }
+        Aperture::Polygon(polygon) => ("Polygon".to_string(), polygon_dimensions(polygon)),
+        Aperture::Macro(name, args) => {
+            let args = args
+                .iter()
+                .flatten()
+                .map(|arg| {
+                    let mut buf = Vec::new();
+                    arg.serialize_partial(&mut buf)
+                        .expect("writing to a Vec cannot fail");
+                    String::from_utf8_lossy(&buf).into_owned()
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            (format!("Macro {}", name), args)
+        }
+    }
+}
+
+/// Generates a human-readable table of the aperture definitions (`%ADD`) in the stream.
+///
+/// The apertures are listed in the order they are defined, one per line, with their code,
+/// shape and dimensions. Dimensions are in the unit of the file.
+pub fn aperture_report(commands: &[Command]) -> String {
+    let rows: Vec<(String, String, String)> = commands
+        .iter()
+        .filter_map(|command| match command {
+            Command::ExtendedCode(ExtendedCode::ApertureDefinition(ApertureDefinition {
+                code,
+                aperture,
+            })) => {
+                let (shape, dimensions) = shape_and_dimensions(aperture);
+                Some((format!("D{}", code), shape, dimensions))
+            }
+            _ => None,
+        })
+        .collect();
+
+    let code_width = rows
+        .iter()
+        .map(|(code, _, _)| code.len())
+        .fold("Code".len(), usize::max);
+    let shape_width = rows
+        .iter()
+        .map(|(_, shape, _)| shape.len())
+        .fold("Shape".len(), usize::max);
+
+    let mut report = String::new();
+    let _ = writeln!(
+        report,
+        "{:code_width$}  {:shape_width$}  Dimensions",
+        "Code",
+        "Shape",
+        code_width = code_width,
+        shape_width = shape_width
+    );
+    for (code, shape, dimensions) in rows {
+        let _ = writeln!(
+            report,
+            "{:code_width$}  {:shape_width$}  {}",
+            code,
+            shape,
+            dimensions,
+            code_width = code_width,
+            shape_width = shape_width
+        );
+    }
+    report
+}
+
+/// Collects the distinct net names of all `.N` object attributes in the stream.
+///
+/// Attributes in standard comments (`G04 #@! TO.N,...`) are included. The empty net name, used for
+/// objects that are not connected to any net, is not.
+pub fn collect_net_names(commands: &[Command]) -> BTreeSet<String> {
+    commands
+        .iter()
+        .filter_map(|command| match command {
+            Command::ExtendedCode(ExtendedCode::ObjectAttribute(ObjectAttribute::Net(net))) => {
+                Some(net)
+            }
+            Command::FunctionCode(FunctionCode::GCode(GCode::Comment(
+                CommentContent::Standard(StandardComment::ObjectAttribute(ObjectAttribute::Net(
+                    net,
+                ))),
+            ))) => Some(net),
+            _ => None,
+        })
+        .flat_map(Net::names)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Collects the file functions (`.FileFunction` attributes) in the stream, in order.
+///
+/// Attributes in standard comments (`G04 #@! TF.FileFunction,...`) are included. A file should
+/// have a single file function, see [`validate_single_file_function`](crate::validate_single_file_function).
+pub fn collect_file_functions(commands: &[Command]) -> Vec<FileFunction> {
+    commands.iter().filter_map(file_function).cloned().collect()
+}
+
+fn dump_axis(dump: &mut String, axis: &str, value: Option<CoordinateNumber>) {
+    if let Some(value) = value {
+        let _ = write!(dump, " {}={}", axis, f64::from(value));
+    }
+}
+
+fn dump_operation(
+    code: &str,
+    coordinates: &Option<Coordinates>,
+    offset: Option<&CoordinateOffset>,
+) -> String {
+    let mut dump = code.to_string();
+    if let Some(coordinates) = coordinates {
+        dump_axis(&mut dump, "X", coordinates.x);
+        dump_axis(&mut dump, "Y", coordinates.y);
+    }
+    if let Some(offset) = offset {
+        dump_axis(&mut dump, "I", offset.x);
+        dump_axis(&mut dump, "J", offset.y);
+    }
+    dump
+}
+
+/// Generates a compact dump of the command structure, one line per command.
+///
+/// Unlike the Gerber code this shows the Rust types, and unlike the derived `Debug` output it
+/// leaves out the `Command`/`FunctionCode`/`ExtendedCode` wrappers and the coordinate formats.
+/// Operations are written as the D code followed by their coordinates in the unit of the file,
+/// e.g. `D01 X=5 Y=0`. Useful for bug reports.
+pub fn dump_tree(commands: &[Command]) -> String {
+    let mut dump = String::new();
+    for command in commands {
+        let line = match command {
+            Command::FunctionCode(FunctionCode::DCode(DCode::Operation(operation))) => {
+                match operation {
+                    Operation::Interpolate(coordinates, offset) => {
+                        dump_operation("D01", coordinates, offset.as_ref())
+                    }
+                    Operation::Move(coordinates) => dump_operation("D02", coordinates, None),
+                    Operation::Flash(coordinates) => dump_operation("D03", coordinates, None),
+                }
+            }
+            Command::FunctionCode(FunctionCode::DCode(code)) => format!("D {:?}", code),
+            Command::FunctionCode(FunctionCode::GCode(code)) => format!("G {:?}", code),
+            Command::FunctionCode(FunctionCode::MCode(code)) => format!("M {:?}", code),
+            Command::ExtendedCode(code) => format!("% {:?}", code),
+        };
+        dump.push_str(&line);
+        dump.push('\n');
+    }
+    dump
+}
+
+fn orientation(a: (f64, f64), b: (f64, f64), c: (f64, f64)) -> f64 {
+    (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)
+}
+
+/// Whether `p`, which is collinear with `a` and `b`, lies on the segment `a`-`b`.
+fn on_segment(a: (f64, f64), b: (f64, f64), p: (f64, f64)) -> bool {
+    p.0 >= a.0.min(b.0) && p.0 <= a.0.max(b.0) && p.1 >= a.1.min(b.1) && p.1 <= a.1.max(b.1)
+}
+
+fn segments_intersect(a: ((f64, f64), (f64, f64)), b: ((f64, f64), (f64, f64))) -> bool {
+    let d1 = orientation(b.0, b.1, a.0);
+    let d2 = orientation(b.0, b.1, a.1);
+    let d3 = orientation(a.0, a.1, b.0);
+    let d4 = orientation(a.0, a.1, b.1);
+    if ((d1 > 0.0 && d2 < 0.0) || (d1 < 0.0 && d2 > 0.0))
+        && ((d3 > 0.0 && d4 < 0.0) || (d3 < 0.0 && d4 > 0.0))
+    {
+        return true;
+    }
+    (d1 == 0.0 && on_segment(b.0, b.1, a.0))
+        || (d2 == 0.0 && on_segment(b.0, b.1, a.1))
+        || (d3 == 0.0 && on_segment(a.0, a.1, b.0))
+        || (d4 == 0.0 && on_segment(a.0, a.1, b.1))
+}
+
+/// Checks whether a closed contour, e.g. the points of a region, intersects itself.
+///
+/// The contour is closed from the last point back to the first, repeating the first point at
+/// the end is optional. Edges touching anywhere except at their shared vertex count as an
+/// intersection, including a contour that doubles back on itself.
+pub fn contour_self_intersects(points: &[(f64, f64)]) -> bool {
+    let points = match points.split_last() {
+        Some((last, rest)) if points.len() > 1 && *last == points[0] => rest,
+        _ => points,
+    };
+    let count = points.len();
+    if count < 3 {
+        return false;
+    }
+    let edge = |index: usize| (points[index], points[(index + 1) % count]);
+    for i in 0..count {
+        for j in (i + 1)..count {
+            let (a, b) = (edge(i), edge(j));
+            let adjacent = j == i + 1 || (i == 0 && j == count - 1);
+            if adjacent {
+                // Adjacent edges share a vertex, they only intersect if one folds back onto
+                // the other.
+                let (shared, a_other, b_other) = if j == i + 1 {
+                    (a.1, a.0, b.1)
+                } else {
+                    (a.0, a.1, b.0)
+                };
+                if (orientation(shared, a_other, b_other) == 0.0
+                    && on_segment(shared, a_other, b_other))
+                    || (orientation(shared, b_other, a_other) == 0.0
+                        && on_segment(shared, b_other, a_other))
+                {
+                    return true;
+                }
+            } else if segments_intersect(a, b) {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Computes the convex hull of all drawn points in the stream.
+///
+/// The points are the start and end points of interpolations and the positions of flashes, with
+/// missing X or Y coordinates taken from the current point. Arcs only contribute their end points
+/// and the size of the apertures is not taken into account. Only absolute coordinates are
+/// supported.
+///
+/// The hull is returned counterclockwise, starting at the point with the lowest X (and lowest Y),
+/// without repeating the first point and without collinear points.
+pub fn convex_hull(commands: &[Command]) -> Vec<(f64, f64)> {
+    let mut points = Vec::new();
+    let mut current: (Option<f64>, Option<f64>) = (None, None);
+    for command in commands {
+        let operation = match command {
+            Command::FunctionCode(FunctionCode::DCode(DCode::Operation(operation))) => operation,
+            _ => continue,
+        };
+        let (Operation::Interpolate(coordinates, _)
+        | Operation::Move(coordinates)
+        | Operation::Flash(coordinates)) = operation;
+        let previous = current;
+        if let Some(coordinates) = coordinates {
+            current = (
+                coordinates.x.map(f64::from).or(current.0),
+                coordinates.y.map(f64::from).or(current.1),
+            );
+        }
+        let drawn: &[(Option<f64>, Option<f64>)] = match operation {
+            Operation::Interpolate(..) => &[previous, current],
+            Operation::Move(_) => &[],
+            Operation::Flash(_) => &[current],
+        };
+        points.extend(drawn.iter().filter_map(|point| match *point {
+            (Some(x), Some(y)) => Some((x, y)),
+            _ => None,
+        }));
+    }
+
+    // Andrew's monotone chain
+    points.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+    let mut hull = half_hull(points.iter());
+    hull.extend(half_hull(points.iter().rev()));
+    hull
+}
+
+/// The lower (or, for points in reverse order, upper) half of the convex hull of sorted points,
+/// without its last point, which is the first point of the other half.
+fn half_hull<'a>(points: impl Iterator<Item = &'a (f64, f64)>) -> Vec<(f64, f64)> {
+    let mut chain: Vec<(f64, f64)> = Vec::new();
+    for &point in points {
+        while chain.len() >= 2
+            && orientation(chain[chain.len() - 2], chain[chain.len() - 1], point) <= 0.0
+        {
+            chain.pop();
+        }
+        chain.push(point);
+    }
+    chain.pop();
+    chain
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{
+        CoordinateFormat, CoordinateMode, FileAttribute, InterpolationMode, MCode, Polarity,
+        Position, Unit, ZeroOmission,
+    };
+
+    #[test]
+    fn test_aperture_report() {
+        let commands: Vec<Command> = vec![
+            ExtendedCode::Unit(Unit::Millimeters).into(),
+            ExtendedCode::ApertureDefinition(ApertureDefinition::new(
+                10,
+                Aperture::Circle(Circle::new(0.5)),
+            ))
+            .into(),
+            ExtendedCode::ApertureDefinition(ApertureDefinition::new(
+                11,
+                Aperture::Rectangle(Rectangular::with_hole(1.5, 0.8, 0.3)),
+            ))
+            .into(),
+            DCode::SelectAperture(10).into(),
+        ];
+
+        assert_eq!(
+            aperture_report(&commands),
+            "Code  Shape      Dimensions\n\
+             D10   Circle     diameter 0.5\n\
+             D11   Rectangle  1.5 x 0.8, hole 0.3\n"
+        );
+    }
+
+    #[test]
+    fn test_collect_net_names() {
+        let net = |names: &[&str]| -> Command {
+            ExtendedCode::ObjectAttribute(ObjectAttribute::Net(Net::Connected(
+                names.iter().map(|name| name.to_string()).collect(),
+            )))
+            .into()
+        };
+        let commands = vec![
+            net(&["GND"]),
+            ExtendedCode::ObjectAttribute(ObjectAttribute::Net(Net::NotConnected)).into(),
+            net(&["VCC", "GND"]),
+        ];
+
+        let expected: BTreeSet<String> = ["GND", "VCC"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(collect_net_names(&commands), expected);
+    }
+
+    #[test]
+    fn test_contour_self_intersects() {
+        let square = [(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)];
+        assert!(!contour_self_intersects(&square));
+        let closed_square = [
+            (0.0, 0.0),
+            (10.0, 0.0),
+            (10.0, 10.0),
+            (0.0, 10.0),
+            (0.0, 0.0),
+        ];
+        assert!(!contour_self_intersects(&closed_square));
+
+        let bowtie = [(0.0, 0.0), (10.0, 10.0), (10.0, 0.0), (0.0, 10.0)];
+        assert!(contour_self_intersects(&bowtie));
+
+        let doubling_back = [(0.0, 0.0), (10.0, 0.0), (5.0, 0.0), (5.0, 5.0)];
+        assert!(contour_self_intersects(&doubling_back));
+    }
+
+    #[test]
+    fn test_dump_tree() {
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 6);
+        let interpolate = |x: i32, y: i32| -> Command {
+            DCode::Operation(Operation::Interpolate(
+                Some(Coordinates::new(x, y, cf)),
+                None,
+            ))
+            .into()
+        };
+        let commands: Vec<Command> = vec![
+            GCode::Comment(CommentContent::String("Two square boxes".to_string())).into(),
+            ExtendedCode::Unit(Unit::Millimeters).into(),
+            ExtendedCode::CoordinateFormat(cf).into(),
+            ExtendedCode::LoadPolarity(Polarity::Dark).into(),
+            ApertureDefinition::new(10, Aperture::Circle(Circle::new(0.01))).into(),
+            DCode::SelectAperture(10).into(),
+            DCode::Operation(Operation::Move(Some(Coordinates::new(0, 0, cf)))).into(),
+            GCode::InterpolationMode(InterpolationMode::Linear).into(),
+            interpolate(5, 0),
+            interpolate(5, 5),
+            interpolate(0, 5),
+            interpolate(0, 0),
+            DCode::Operation(Operation::Move(Some(Coordinates::at_x(6, cf)))).into(),
+            interpolate(11, 0),
+            interpolate(11, 5),
+            interpolate(6, 5),
+            interpolate(6, 0),
+            MCode::EndOfFile.into(),
+        ];
+
+        let dump = dump_tree(&commands);
+        assert_eq!(dump.lines().count(), commands.len());
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines[1], "% Unit(Millimeters)");
+        assert_eq!(lines[5], "D SelectAperture(10)");
+        assert_eq!(lines[7], "G InterpolationMode(Linear)");
+        assert_eq!(lines[8], "D01 X=5 Y=0");
+        assert_eq!(lines[12], "D02 X=6");
+        assert_eq!(lines[17], "M EndOfFile");
+    }
+
+    #[test]
+    fn test_convex_hull() {
+        // two square boxes, with modal coordinates
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 6);
+        let interpolate = |coordinates: Coordinates| -> Command {
+            DCode::Operation(Operation::Interpolate(Some(coordinates), None)).into()
+        };
+        let commands: Vec<Command> = vec![
+            DCode::Operation(Operation::Move(Some(Coordinates::new(0, 0, cf)))).into(),
+            interpolate(Coordinates::new(5, 0, cf)),
+            interpolate(Coordinates::at_y(5, cf)),
+            interpolate(Coordinates::at_x(0, cf)),
+            interpolate(Coordinates::at_y(0, cf)),
+            DCode::Operation(Operation::Move(Some(Coordinates::at_x(6, cf)))).into(),
+            interpolate(Coordinates::at_x(11, cf)),
+            interpolate(Coordinates::at_y(5, cf)),
+            interpolate(Coordinates::at_x(6, cf)),
+            interpolate(Coordinates::at_y(0, cf)),
+            MCode::EndOfFile.into(),
+        ];
+        assert_eq!(
+            convex_hull(&commands),
+            vec![(0.0, 0.0), (11.0, 0.0), (11.0, 5.0), (0.0, 5.0)]
+        );
+
+        // moves are not drawn, flashes are
+        let commands: Vec<Command> = vec![
+            DCode::Operation(Operation::Move(Some(Coordinates::new(-10, -10, cf)))).into(),
+            DCode::Operation(Operation::Flash(Some(Coordinates::new(0, 0, cf)))).into(),
+            DCode::Operation(Operation::Flash(Some(Coordinates::new(2, 1, cf)))).into(),
+            DCode::Operation(Operation::Flash(Some(Coordinates::new(4, 0, cf)))).into(),
+            DCode::Operation(Operation::Flash(Some(Coordinates::new(2, 3, cf)))).into(),
+        ];
+        assert_eq!(
+            convex_hull(&commands),
+            vec![(0.0, 0.0), (4.0, 0.0), (2.0, 3.0)]
+        );
+
+        assert!(convex_hull(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_collect_file_functions() {
+        let commands: Vec<Command> = vec![
+            ExtendedCode::FileAttribute(FileAttribute::FileFunction(FileFunction::Legend {
+                pos: Position::Top,
+                index: None,
+            }))
+            .into(),
+            ExtendedCode::Unit(Unit::Millimeters).into(),
+            MCode::EndOfFile.into(),
+        ];
+        assert_eq!(
+            collect_file_functions(&commands),
+            vec![FileFunction::Legend {
+                pos: Position::Top,
+                index: None,
+            }]
+        );
+        assert!(collect_file_functions(&commands[1..]).is_empty());
+    }
+
+    #[test]
+    fn test_aperture_report_empty() {
+        assert_eq!(aperture_report(&[]), "Code  Shape  Dimensions\n");
+    }
+}
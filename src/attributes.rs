@@ -1,28 +1,34 @@
 //! Attributes.
 
-use std::io::Write;
+use core::convert::TryFrom;
 use strum_macros::{IntoStaticStr, VariantArray, VariantNames};
 use uuid::Uuid;
 
-use crate::errors::GerberResult;
-use crate::traits::PartialGerberCode;
+use crate::errors::{GerberError, GerberResult};
+use crate::function_codes::{CommentContent, GCode};
+use crate::io::Write;
+use crate::prelude::*;
+use crate::traits::{PartialAttributeCode, PartialGerberCode};
+use crate::types::Command;
+use crate::validation::check_field;
 use crate::GerberDate;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Ident {
     // Aka 'Guid'
     Uuid(Uuid),
     Name(String),
 }
 
-impl<W: Write> PartialGerberCode<W> for Ident {
-    fn serialize_partial(&self, writer: &mut W) -> GerberResult<()> {
+impl<W: Write> PartialAttributeCode<W> for Ident {
+    fn serialize_fields(&self, writer: &mut W, raw: bool) -> GerberResult<()> {
         match self {
             Ident::Uuid(guid) => {
                 write!(writer, "{}", guid)?;
             }
             Ident::Name(value) => {
-                write!(writer, "{}", value)?;
+                write_field(writer, value, raw)?;
             }
         }
 
@@ -30,9 +36,84 @@ impl<W: Write> PartialGerberCode<W> for Ident {
     }
 }
 
+impl_partial_gerber_code_via_fields!(Ident);
+
+/// Parses a `.SameCoordinates` identifier like the parser does: a UUID if the value is one, a
+/// name otherwise.
+///
+/// Names are checked for the reserved characters `,`, `*`, `%` and line breaks, which would
+/// break the attribute.
+impl TryFrom<&str> for Ident {
+    type Error = GerberError;
+
+    fn try_from(value: &str) -> GerberResult<Self> {
+        match Uuid::parse_str(value) {
+            Ok(uuid) => Ok(Ident::Uuid(uuid)),
+            Err(_) => {
+                check_field("Ident", value)?;
+                Ok(Ident::Name(value.to_string()))
+            }
+        }
+    }
+}
+
+// Fields
+
+/// Writes a free-text attribute field. The characters that would break the attribute are
+/// escaped as `\uXXXX`, see spec 2024.05 section 3.4.3: the field separator `,`, the delimiters
+/// `*` and `%`, and the escape character `\` itself.
+///
+/// The constructors accept these characters, only strict validation rejects `,`, `*` and `%`
+/// in fields, for readers that do not decode escapes. With `raw` the field is written verbatim,
+/// see [`SerializationOptions::raw_attribute_fields`](crate::SerializationOptions).
+pub(crate) fn write_field<W: Write>(writer: &mut W, value: &str, raw: bool) -> GerberResult<()> {
+    if raw {
+        write!(writer, "{}", value)?;
+        return Ok(());
+    }
+    let mut rest = value;
+    while let Some(index) = rest.find([',', '*', '%', '\\']) {
+        let c = rest[index..].chars().next().unwrap_or_default();
+        write!(writer, "{}\\u{:04X}", &rest[..index], c as u32)?;
+        rest = &rest[index + c.len_utf8()..];
+    }
+    write!(writer, "{}", rest)?;
+    Ok(())
+}
+
+/// Decodes the `\uXXXX` escapes of attribute fields, see [`write_field`]. Backslashes that do
+/// not start a valid escape are kept.
+#[cfg(feature = "std")]
+pub(crate) fn unescape_field(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(index) = rest.find('\\') {
+        result.push_str(&rest[..index]);
+        rest = &rest[index..];
+        let decoded = rest
+            .get(2..6)
+            .filter(|_| rest[1..].starts_with('u'))
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .and_then(char::from_u32);
+        match decoded {
+            Some(c) => {
+                result.push(c);
+                rest = &rest[6..];
+            }
+            None => {
+                result.push('\\');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
 // FileAttribute
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FileAttribute {
     /// "%TF.Part,(Single|Array|FabricationPanel|Coupon|Other,<mandatory description>)>*%
     Part(Part),
@@ -60,12 +141,35 @@ pub enum FileAttribute {
     },
 }
 
-impl<W: Write> PartialGerberCode<W> for FileAttribute {
-    fn serialize_partial(&self, writer: &mut W) -> GerberResult<()> {
+impl FileAttribute {
+    /// A `.ProjectId` attribute with a caller supplied UUID, e.g. a fixed UUID in tests so the
+    /// output is deterministic.
+    pub fn project_id_with_uuid(id: &str, uuid: Uuid, revision: &str) -> Self {
+        FileAttribute::ProjectId {
+            id: id.to_string(),
+            uuid,
+            revision: revision.to_string(),
+        }
+    }
+
+    /// A `.SameCoordinates` attribute with a caller supplied UUID.
+    pub fn same_coordinates_with_uuid(uuid: Uuid) -> Self {
+        FileAttribute::SameCoordinates(Some(Ident::Uuid(uuid)))
+    }
+
+    /// A `.CreationDate` attribute with a caller supplied date and time, e.g. from a custom
+    /// clock or a fixed date in tests.
+    pub fn creation_date_from(dt: GerberDate) -> Self {
+        FileAttribute::CreationDate(dt)
+    }
+}
+
+impl<W: Write> PartialAttributeCode<W> for FileAttribute {
+    fn serialize_fields(&self, writer: &mut W, raw: bool) -> GerberResult<()> {
         match self {
             FileAttribute::Part(ref part) => {
                 write!(writer, ".Part,")?;
-                part.serialize_partial(writer)?;
+                part.serialize_fields(writer, raw)?;
             }
             FileAttribute::FileFunction(ref function) => {
                 write!(writer, ".FileFunction,")?;
@@ -206,7 +310,8 @@ impl<W: Write> PartialGerberCode<W> for FileAttribute {
                         pos.serialize_partial(writer)?;
                     }
                     FileFunction::Other(value) => {
-                        write!(writer, "Other,{}", value)?;
+                        write!(writer, "Other,")?;
+                        write_field(writer, value, raw)?;
                     }
 
                     // "Drawing layers"
@@ -227,7 +332,8 @@ impl<W: Write> PartialGerberCode<W> for FileAttribute {
                         write!(writer, "ArrayDrawing")?;
                     }
                     FileFunction::OtherDrawing(value) => {
-                        write!(writer, "OtherDrawing,{}", value)?;
+                        write!(writer, "OtherDrawing,")?;
+                        write_field(writer, value, raw)?;
                     }
                 }
             }
@@ -239,7 +345,7 @@ impl<W: Write> PartialGerberCode<W> for FileAttribute {
                 write!(writer, ".SameCoordinates")?;
                 if let Some(ident) = ident {
                     write!(writer, ",")?;
-                    ident.serialize_partial(writer)?;
+                    ident.serialize_fields(writer, raw)?;
                 }
             }
             FileAttribute::CreationDate(date) => {
@@ -247,16 +353,20 @@ impl<W: Write> PartialGerberCode<W> for FileAttribute {
             }
             FileAttribute::GenerationSoftware(ref gs) => {
                 write!(writer, ".GenerationSoftware,")?;
-                gs.serialize_partial(writer)?;
+                gs.serialize_fields(writer, raw)?;
             }
             FileAttribute::ProjectId { id, uuid, revision } => {
-                write!(writer, ".ProjectId,{},{},{}", id, uuid, revision)?;
+                write!(writer, ".ProjectId,")?;
+                write_field(writer, id, raw)?;
+                write!(writer, ",{},", uuid)?;
+                write_field(writer, revision, raw)?;
             }
             FileAttribute::Md5(ref hash) => write!(writer, ".MD5,{}", hash)?,
             FileAttribute::UserDefined { name, values } => {
                 write!(writer, "{}", name)?;
                 for value in values {
-                    write!(writer, ",{}", value)?;
+                    write!(writer, ",")?;
+                    write_field(writer, value, raw)?;
                 }
             }
         };
@@ -264,8 +374,11 @@ impl<W: Write> PartialGerberCode<W> for FileAttribute {
     }
 }
 
+impl_partial_gerber_code_via_fields!(FileAttribute);
+
 // TextMode
 #[derive(Debug, Copy, Clone, PartialEq, Hash, IntoStaticStr, VariantNames, VariantArray)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[strum(serialize_all = "UPPERCASE")]
 pub enum TextMode {
     #[strum(serialize = "B")]
@@ -275,9 +388,11 @@ pub enum TextMode {
 }
 
 impl_partial_gerber_code_via_strum!(TextMode);
+impl_from_str_via_strum!(TextMode);
 
 // TextMirroring
 #[derive(Debug, Copy, Clone, PartialEq, Hash, IntoStaticStr, VariantNames, VariantArray)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[strum(serialize_all = "UPPERCASE")]
 pub enum TextMirroring {
     #[strum(serialize = "R")]
@@ -287,10 +402,12 @@ pub enum TextMirroring {
 }
 
 impl_partial_gerber_code_via_strum!(TextMirroring);
+impl_from_str_via_strum!(TextMirroring);
 
 // ApertureAttribute
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ApertureAttribute {
     ApertureFunction(ApertureFunction),
     DrillTolerance {
@@ -311,8 +428,8 @@ pub enum ApertureAttribute {
     },
 }
 
-impl<W: Write> PartialGerberCode<W> for ApertureAttribute {
-    fn serialize_partial(&self, writer: &mut W) -> GerberResult<()> {
+impl<W: Write> PartialAttributeCode<W> for ApertureAttribute {
+    fn serialize_fields(&self, writer: &mut W, raw: bool) -> GerberResult<()> {
         match self {
             ApertureAttribute::ApertureFunction(ref af) => {
                 write!(writer, ".AperFunction,")?;
@@ -346,7 +463,8 @@ impl<W: Write> PartialGerberCode<W> for ApertureAttribute {
                         write!(writer, "CastellatedDrill")?;
                     }
                     ApertureFunction::OtherDrill(ref value) => {
-                        write!(writer, "OtherDrill,{}", value)?;
+                        write!(writer, "OtherDrill,")?;
+                        write_field(writer, value, raw)?;
                     }
 
                     // "Copper layers"
@@ -390,7 +508,8 @@ impl<W: Write> PartialGerberCode<W> for ApertureAttribute {
                         write!(writer, "AntiPad")?;
                     }
                     ApertureFunction::OtherPad(ref value) => {
-                        write!(writer, "OtherPad,{}", value)?;
+                        write!(writer, "OtherPad,")?;
+                        write_field(writer, value, raw)?;
                     }
                     ApertureFunction::Conductor => {
                         write!(writer, "Conductor")?;
@@ -408,7 +527,8 @@ impl<W: Write> PartialGerberCode<W> for ApertureAttribute {
                         write!(writer, "Border")?;
                     }
                     ApertureFunction::OtherCopper(ref value) => {
-                        write!(writer, "OtherCopper,{}", value)?;
+                        write!(writer, "OtherCopper,")?;
+                        write_field(writer, value, raw)?;
                     }
 
                     // "Component layers"
@@ -434,7 +554,8 @@ impl<W: Write> PartialGerberCode<W> for ApertureAttribute {
                         write!(writer, "Material")?;
                     }
                     ApertureFunction::Other(value) => {
-                        write!(writer, "Other,{}", value)?;
+                        write!(writer, "Other,")?;
+                        write_field(writer, value, raw)?;
                     }
 
                     // 2024.05 - 8.4 - "Deprecated attribute values"
@@ -463,13 +584,15 @@ impl<W: Write> PartialGerberCode<W> for ApertureAttribute {
                 size,
                 comment,
             } => {
-                write!(writer, ".FlashText,{},", text)?;
+                write!(writer, ".FlashText,")?;
+                write_field(writer, text, raw)?;
+                write!(writer, ",")?;
                 mode.serialize_partial(writer)?;
                 write!(writer, ",")?;
                 mirroring.serialize_partial(writer)?;
                 write!(writer, ",")?;
                 if let Some(font) = font {
-                    write!(writer, "{}", font)?;
+                    write_field(writer, font, raw)?;
                 }
                 write!(writer, ",")?;
                 if let Some(size) = size {
@@ -477,13 +600,14 @@ impl<W: Write> PartialGerberCode<W> for ApertureAttribute {
                 }
                 write!(writer, ",")?;
                 if let Some(comment) = comment {
-                    write!(writer, "{}", comment)?;
+                    write_field(writer, comment, raw)?;
                 }
             }
             ApertureAttribute::UserDefined { name, values } => {
                 write!(writer, "{}", name)?;
                 for value in values {
-                    write!(writer, ",{}", value)?;
+                    write!(writer, ",")?;
+                    write_field(writer, value, raw)?;
                 }
             }
         }
@@ -491,9 +615,12 @@ impl<W: Write> PartialGerberCode<W> for ApertureAttribute {
     }
 }
 
+impl_partial_gerber_code_via_fields!(ApertureAttribute);
+
 // Part
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Part {
     /// Single PCB
     Single,
@@ -507,19 +634,24 @@ pub enum Part {
     Other(String),
 }
 
-impl<W: Write> PartialGerberCode<W> for Part {
-    fn serialize_partial(&self, writer: &mut W) -> GerberResult<()> {
+impl<W: Write> PartialAttributeCode<W> for Part {
+    fn serialize_fields(&self, writer: &mut W, raw: bool) -> GerberResult<()> {
         match *self {
             Part::Single => write!(writer, "Single")?,
             Part::Array => write!(writer, "Array")?,
             Part::FabricationPanel => write!(writer, "FabricationPanel")?,
             Part::Coupon => write!(writer, "Coupon")?,
-            Part::Other(ref description) => write!(writer, "Other,{}", description)?,
+            Part::Other(ref description) => {
+                write!(writer, "Other,")?;
+                write_field(writer, description, raw)?;
+            }
         };
         Ok(())
     }
 }
 
+impl_partial_gerber_code_via_fields!(Part);
+
 // Position
 
 #[derive(
@@ -534,6 +666,7 @@ impl<W: Write> PartialGerberCode<W> for Part {
     VariantNames,
     VariantArray,
 )]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[strum(serialize_all = "PascalCase")]
 pub enum Position {
     Top,
@@ -542,10 +675,12 @@ pub enum Position {
 }
 
 impl_partial_gerber_code_via_strum!(Position);
+impl_from_str_via_strum!(Position);
 
 // ExtendedPosition
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, IntoStaticStr, VariantNames, VariantArray)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[strum(serialize_all = "PascalCase")]
 pub enum ExtendedPosition {
     Top,
@@ -556,10 +691,12 @@ pub enum ExtendedPosition {
 }
 
 impl_partial_gerber_code_via_strum!(ExtendedPosition);
+impl_from_str_via_strum!(ExtendedPosition);
 
 // CopperType
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, IntoStaticStr, VariantNames, VariantArray)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[strum(serialize_all = "PascalCase")]
 pub enum CopperType {
     Plane,
@@ -569,10 +706,12 @@ pub enum CopperType {
 }
 
 impl_partial_gerber_code_via_strum!(CopperType);
+impl_from_str_via_strum!(CopperType);
 
 // PlatedDrill
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, IntoStaticStr, VariantNames, VariantArray)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[strum(serialize_all = "PascalCase")]
 pub enum PlatedDrill {
     #[strum(serialize = "PTH")]
@@ -582,10 +721,12 @@ pub enum PlatedDrill {
 }
 
 impl_partial_gerber_code_via_strum!(PlatedDrill);
+impl_from_str_via_strum!(PlatedDrill);
 
 // NonPlatedDrill
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, IntoStaticStr, VariantNames, VariantArray)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[strum(serialize_all = "PascalCase")]
 pub enum NonPlatedDrill {
     #[strum(serialize = "NPTH")]
@@ -595,10 +736,12 @@ pub enum NonPlatedDrill {
 }
 
 impl_partial_gerber_code_via_strum!(NonPlatedDrill);
+impl_from_str_via_strum!(NonPlatedDrill);
 
 // DrillRouteType
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, IntoStaticStr, VariantNames, VariantArray)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[strum(serialize_all = "PascalCase")]
 pub enum DrillRouteType {
     Drill,
@@ -608,10 +751,12 @@ pub enum DrillRouteType {
 }
 
 impl_partial_gerber_code_via_strum!(DrillRouteType);
+impl_from_str_via_strum!(DrillRouteType);
 
 // Profile
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, IntoStaticStr, VariantNames, VariantArray)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Profile {
     #[strum(serialize = "P")]
     Plated,
@@ -620,10 +765,12 @@ pub enum Profile {
 }
 
 impl_partial_gerber_code_via_strum!(Profile);
+impl_from_str_via_strum!(Profile);
 
 // FileFunction
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FileFunction {
     //
     // "Data layers"
@@ -658,9 +805,10 @@ pub enum FileFunction {
         pos: Position,
         index: Option<i32>,
     },
+    /// `pos` is `Inner` for components embedded in an inner layer.
     Component {
         layer: i32,
-        pos: Position,
+        pos: ExtendedPosition,
     },
     Paste(Position),
     Glue(Position),
@@ -704,9 +852,77 @@ pub enum FileFunction {
     OtherDrawing(String),
 }
 
+impl FileFunction {
+    /// The file function names defined by the specification.
+    pub const STANDARD_NAMES: &'static [&'static str] = &[
+        "Copper",
+        "Plated",
+        "NonPlated",
+        "Profile",
+        "Keepout",
+        "Soldermask",
+        "Legend",
+        "Component",
+        "Paste",
+        "Glue",
+        "Carbonmask",
+        "Goldmask",
+        "Heatsinkmask",
+        "Peelablemask",
+        "Silvermask",
+        "Tinmask",
+        "Depthrout",
+        "Vcut",
+        "Viafill",
+        "Pads",
+        "Other",
+        "Drillmap",
+        "FabricationDrawing",
+        "Vcutmap",
+        "AssemblyDrawing",
+        "ArrayDrawing",
+        "OtherDrawing",
+    ];
+
+    /// Creates a `FileFunction::Other`, see [`FileFunction::check_other_value`].
+    pub fn other(value: &str) -> GerberResult<Self> {
+        Self::check_other_value(value)?;
+        Ok(FileFunction::Other(value.to_string()))
+    }
+
+    /// Creates a `FileFunction::OtherDrawing`, see [`FileFunction::check_other_value`].
+    pub fn other_drawing(value: &str) -> GerberResult<Self> {
+        Self::check_other_value(value)?;
+        Ok(FileFunction::OtherDrawing(value.to_string()))
+    }
+
+    /// The value of `Other` and `OtherDrawing` must not be one of the standard file function
+    /// names (compared case-insensitively), since readers could mistake it for that function,
+    /// and must not contain a `,`.
+    pub fn check_other_value(value: &str) -> GerberResult<()> {
+        if let Some(name) = Self::STANDARD_NAMES
+            .iter()
+            .find(|name| name.eq_ignore_ascii_case(value))
+        {
+            return Err(GerberError::ValidationError(format!(
+                "'{}' is a standard file function, use the corresponding variant instead",
+                name
+            )));
+        }
+        if value.contains(',') {
+            return Err(GerberError::ValidationError(format!(
+                "File function value '{}' must not contain ','",
+                value
+            )));
+        }
+        Ok(())
+    }
+}
+
 // FilePolarity
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, IntoStaticStr, VariantNames, VariantArray)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[strum(serialize_all = "PascalCase")]
 pub enum FilePolarity {
     Positive,
@@ -714,10 +930,12 @@ pub enum FilePolarity {
 }
 
 impl_partial_gerber_code_via_strum!(FilePolarity);
+impl_from_str_via_strum!(FilePolarity);
 
 // GenerationSoftware
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GenerationSoftware {
     pub vendor: String,
     pub application: String,
@@ -732,22 +950,61 @@ impl GenerationSoftware {
             version: version.map(|s| s.into()),
         }
     }
+
+    /// Like `new`, but with a mandatory version.
+    ///
+    /// Use the [`generation_software!`](crate::generation_software) macro to take the version
+    /// from the `Cargo.toml` of the calling crate.
+    pub fn for_crate<S: Into<String>>(vendor: S, application: S, version: S) -> Self {
+        GenerationSoftware {
+            vendor: vendor.into(),
+            application: application.into(),
+            version: Some(version.into()),
+        }
+    }
+
+    /// This library, with its version.
+    pub fn gerber_types() -> Self {
+        Self::for_crate("MakerPnP", "gerber-types", env!("CARGO_PKG_VERSION"))
+    }
 }
 
-impl<W: Write> PartialGerberCode<W> for GenerationSoftware {
-    fn serialize_partial(&self, writer: &mut W) -> GerberResult<()> {
-        match self.version {
-            Some(ref v) => write!(writer, "{},{},{}", self.vendor, self.application, v)?,
-            None => write!(writer, "{},{}", self.vendor, self.application)?,
-        };
+/// Creates a [`GenerationSoftware`] with the given vendor and application and the version of
+/// the crate that invokes the macro, i.e. `CARGO_PKG_VERSION`.
+///
+/// ```
+/// use gerber_types::{generation_software, GenerationSoftware};
+///
+/// let software: GenerationSoftware = generation_software!("Vendor", "Application");
+/// assert_eq!(software.version.as_deref(), Some(env!("CARGO_PKG_VERSION")));
+/// ```
+#[macro_export]
+macro_rules! generation_software {
+    ($vendor:expr, $application:expr) => {
+        $crate::GenerationSoftware::for_crate($vendor, $application, env!("CARGO_PKG_VERSION"))
+    };
+}
+
+impl<W: Write> PartialAttributeCode<W> for GenerationSoftware {
+    fn serialize_fields(&self, writer: &mut W, raw: bool) -> GerberResult<()> {
+        write_field(writer, &self.vendor, raw)?;
+        write!(writer, ",")?;
+        write_field(writer, &self.application, raw)?;
+        if let Some(ref version) = self.version {
+            write!(writer, ",")?;
+            write_field(writer, version, raw)?;
+        }
         Ok(())
     }
 }
 
+impl_partial_gerber_code_via_fields!(GenerationSoftware);
+
 /// ApertureFunction
 ///
 /// 2024.05 - 5.6.10 ".AperFunction"
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ApertureFunction {
     // "Drill and rout layers"
     ViaDrill(Option<IPC4761ViaProtection>),
@@ -796,7 +1053,90 @@ pub enum ApertureFunction {
     Drawing,
 }
 
+impl ApertureFunction {
+    /// A description of the function in words, e.g. "Via pad".
+    pub fn description(&self) -> String {
+        match self {
+            ApertureFunction::ViaDrill(None) => "Via drill".to_string(),
+            ApertureFunction::ViaDrill(Some(protection)) => {
+                format!("Via drill, {}", protection.description())
+            }
+            ApertureFunction::BackDrill => "Back drill".to_string(),
+            ApertureFunction::ComponentDrill { function: None } => "Component drill".to_string(),
+            ApertureFunction::ComponentDrill {
+                function: Some(ComponentDrill::PressFit),
+            } => "Component drill, press-fit".to_string(),
+            ApertureFunction::MechanicalDrill { function: None } => "Mechanical drill".to_string(),
+            ApertureFunction::MechanicalDrill {
+                function: Some(function),
+            } => match function {
+                DrillFunction::BreakOut => "Mechanical drill, break-out".to_string(),
+                DrillFunction::Tooling => "Mechanical drill, tooling".to_string(),
+                DrillFunction::Other => "Mechanical drill, other".to_string(),
+            },
+            ApertureFunction::CastellatedDrill => "Castellated drill".to_string(),
+            ApertureFunction::OtherDrill(value) => format!("Other drill, {}", value),
+            ApertureFunction::ComponentPad => "Component pad".to_string(),
+            ApertureFunction::SmdPad(pad_type) => format!("SMD pad, {}", pad_type.description()),
+            ApertureFunction::BgaPad(pad_type) => format!("BGA pad, {}", pad_type.description()),
+            ApertureFunction::ConnectorPad => "Connector pad".to_string(),
+            ApertureFunction::HeatsinkPad => "Heatsink pad".to_string(),
+            ApertureFunction::ViaPad => "Via pad".to_string(),
+            ApertureFunction::TestPad => "Test pad".to_string(),
+            ApertureFunction::CastellatedPad => "Castellated pad".to_string(),
+            ApertureFunction::FiducialPad(scope) => {
+                let scope = match scope {
+                    FiducialScope::Local => "local",
+                    FiducialScope::Global => "global",
+                    FiducialScope::Panel => "panel",
+                };
+                format!("Fiducial pad, {}", scope)
+            }
+            ApertureFunction::ThermalReliefPad => "Thermal relief pad".to_string(),
+            ApertureFunction::WasherPad => "Washer pad".to_string(),
+            ApertureFunction::AntiPad => "Anti-pad".to_string(),
+            ApertureFunction::OtherPad(value) => format!("Other pad, {}", value),
+            ApertureFunction::Conductor => "Conductor".to_string(),
+            ApertureFunction::EtchedComponent => "Etched component".to_string(),
+            ApertureFunction::NonConductor => "Non-conductor".to_string(),
+            ApertureFunction::CopperBalancing => "Copper balancing".to_string(),
+            ApertureFunction::Border => "Border".to_string(),
+            ApertureFunction::OtherCopper(value) => format!("Other copper, {}", value),
+            ApertureFunction::Profile => "Profile".to_string(),
+            ApertureFunction::Material => "Material".to_string(),
+            ApertureFunction::NonMaterial => "Non-material".to_string(),
+            ApertureFunction::Other(value) => format!("Other, {}", value),
+            ApertureFunction::ComponentMain => "Component main".to_string(),
+            ApertureFunction::ComponentOutline(outline) => {
+                let outline = match outline {
+                    ComponentOutline::Body => "body",
+                    ComponentOutline::Lead2Lead => "lead to lead",
+                    ComponentOutline::Footprint => "footprint",
+                    ComponentOutline::Courtyard => "courtyard",
+                };
+                format!("Component outline, {}", outline)
+            }
+            ApertureFunction::ComponentPin => "Component pin".to_string(),
+            ApertureFunction::Slot => "Slot (deprecated)".to_string(),
+            ApertureFunction::CutOut => "Cut-out (deprecated)".to_string(),
+            ApertureFunction::Cavity => "Cavity (deprecated)".to_string(),
+            ApertureFunction::Drawing => "Drawing (deprecated)".to_string(),
+        }
+    }
+
+    /// A `G04` comment with the [`description`](ApertureFunction::description) of the function,
+    /// e.g. `G04 Aperture function: Via pad*`.
+    pub fn to_comment(&self) -> Command {
+        GCode::Comment(CommentContent::String(format!(
+            "Aperture function: {}",
+            self.description()
+        )))
+        .into()
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, IntoStaticStr, VariantNames, VariantArray)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[strum(serialize_all = "PascalCase")]
 pub enum IPC4761ViaProtection {
     Ia,
@@ -818,8 +1158,41 @@ pub enum IPC4761ViaProtection {
 }
 
 impl_partial_gerber_code_via_strum!(IPC4761ViaProtection);
+impl_from_str_via_strum!(IPC4761ViaProtection);
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, IntoStaticStr, VariantNames, VariantArray)]
+impl IPC4761ViaProtection {
+    /// The IPC-4761 type and its description, e.g. "Type Ia - Tented, one side".
+    pub fn description(&self) -> &'static str {
+        match self {
+            IPC4761ViaProtection::Ia => "Type Ia - Tented, one side",
+            IPC4761ViaProtection::Ib => "Type Ib - Tented, both sides",
+            IPC4761ViaProtection::IIa => "Type IIa - Tented and covered, one side",
+            IPC4761ViaProtection::IIb => "Type IIb - Tented and covered, both sides",
+            IPC4761ViaProtection::IIIa => "Type IIIa - Plugged, one side",
+            IPC4761ViaProtection::IIIb => "Type IIIb - Plugged, both sides",
+            IPC4761ViaProtection::IVa => "Type IVa - Plugged and covered, one side",
+            IPC4761ViaProtection::IVb => "Type IVb - Plugged and covered, both sides",
+            IPC4761ViaProtection::V => "Type V - Filled",
+            IPC4761ViaProtection::VI => "Type VI - Filled and covered",
+            IPC4761ViaProtection::VII => "Type VII - Filled and capped",
+            IPC4761ViaProtection::None => "No protection",
+        }
+    }
+}
+
+#[derive(
+    Debug,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    Hash,
+    strum_macros::Display,
+    IntoStaticStr,
+    VariantNames,
+    VariantArray,
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[strum(serialize_all = "PascalCase")]
 pub enum ComponentOutline {
     Body,
@@ -829,10 +1202,12 @@ pub enum ComponentOutline {
 }
 
 impl_partial_gerber_code_via_strum!(ComponentOutline);
+impl_from_str_via_strum!(ComponentOutline);
 
 // DrillFunction
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, IntoStaticStr, VariantNames, VariantArray)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[strum(serialize_all = "PascalCase")]
 pub enum DrillFunction {
     #[strum(serialize = "Breakout")]
@@ -842,21 +1217,25 @@ pub enum DrillFunction {
 }
 
 impl_partial_gerber_code_via_strum!(DrillFunction);
+impl_from_str_via_strum!(DrillFunction);
 
 // ComponentDrill
 
 /// 2024.05 spec mismatch warning: Aperture function ".AperFunction.ComponentDill" has "PressFit" (uppercase F) whereas Component Characteristics ".CMnt" has "Pressfit" (lowercase f)"
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, IntoStaticStr, VariantNames, VariantArray)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ComponentDrill {
     #[strum(serialize = "PressFit")]
     PressFit,
 }
 
 impl_partial_gerber_code_via_strum!(ComponentDrill);
+impl_from_str_via_strum!(ComponentDrill);
 
 // SmdPadType
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, IntoStaticStr, VariantNames, VariantArray)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[strum(serialize_all = "PascalCase")]
 pub enum SmdPadType {
     #[strum(serialize = "CuDef")]
@@ -866,10 +1245,21 @@ pub enum SmdPadType {
 }
 
 impl_partial_gerber_code_via_strum!(SmdPadType);
+impl_from_str_via_strum!(SmdPadType);
+
+impl SmdPadType {
+    pub fn description(&self) -> &'static str {
+        match self {
+            SmdPadType::CopperDefined => "copper defined",
+            SmdPadType::SoldermaskDefined => "solder mask defined",
+        }
+    }
+}
 
 // FiducialScope
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, IntoStaticStr, VariantNames, VariantArray)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[strum(serialize_all = "PascalCase")]
 pub enum FiducialScope {
     Local,
@@ -878,9 +1268,11 @@ pub enum FiducialScope {
 }
 
 impl_partial_gerber_code_via_strum!(FiducialScope);
+impl_from_str_via_strum!(FiducialScope);
 
 // ObjectAttribute
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ObjectAttribute {
     Net(Net),
     Pin(Pin),
@@ -893,25 +1285,47 @@ pub enum ObjectAttribute {
     },
 }
 
-impl<W: Write> PartialGerberCode<W> for ObjectAttribute {
-    fn serialize_partial(&self, writer: &mut W) -> GerberResult<()> {
+impl ObjectAttribute {
+    /// A `.C` (component reference designator) attribute, checked with
+    /// [`ObjectAttribute::check_refdes`].
+    pub fn component(refdes: &str) -> GerberResult<Self> {
+        Self::check_refdes(refdes)?;
+        Ok(ObjectAttribute::Component(refdes.to_string()))
+    }
+
+    /// A reference designator must not be empty. Reserved characters like `,` are allowed, they
+    /// are escaped as `\uXXXX` when the attribute is written.
+    pub fn check_refdes(refdes: &str) -> GerberResult<()> {
+        if refdes.is_empty() {
+            return Err(GerberError::ValidationError(
+                "The component reference designator must not be empty".into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> PartialAttributeCode<W> for ObjectAttribute {
+    fn serialize_fields(&self, writer: &mut W, raw: bool) -> GerberResult<()> {
         match self {
             ObjectAttribute::Net(net) => {
-                net.serialize_partial(writer)?;
+                net.serialize_fields(writer, raw)?;
             }
             ObjectAttribute::Pin(pin) => {
-                pin.serialize_partial(writer)?;
+                pin.serialize_fields(writer, raw)?;
             }
             ObjectAttribute::Component(ref_des) => {
-                write!(writer, ".C,{}", ref_des)?;
+                write!(writer, ".C,")?;
+                write_field(writer, ref_des, raw)?;
             }
             ObjectAttribute::ComponentCharacteristics(cc) => {
-                cc.serialize_partial(writer)?;
+                cc.serialize_fields(writer, raw)?;
             }
             ObjectAttribute::UserDefined { name, values } => {
                 write!(writer, "{}", name)?;
                 for value in values {
-                    write!(writer, ",{}", value)?;
+                    write!(writer, ",")?;
+                    write_field(writer, value, raw)?;
                 }
             }
         };
@@ -919,9 +1333,12 @@ impl<W: Write> PartialGerberCode<W> for ObjectAttribute {
     }
 }
 
+impl_partial_gerber_code_via_fields!(ObjectAttribute);
+
 /// ComponentCharacteristics
 /// 2024.05 - 5.6.1.6 "Cxxx (Component Characteristics)"
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ComponentCharacteristics {
     /// ".CRot,<decimal>"
     Rotation(f64),
@@ -950,48 +1367,78 @@ pub enum ComponentCharacteristics {
     Supplier(Vec<SupplierPart>),
 }
 
-impl<W: Write> PartialGerberCode<W> for ComponentCharacteristics {
-    fn serialize_partial(&self, writer: &mut W) -> GerberResult<()> {
+impl ComponentCharacteristics {
+    /// Creates a `Rotation` with the angle normalized into the range `[0, 360)`, as expected by
+    /// pick-and-place tools. e.g. `-90` becomes `270`.
+    pub fn rotation(degrees: f64) -> GerberResult<Self> {
+        if !degrees.is_finite() {
+            return Err(GerberError::RangeError(
+                "The rotation of a component must be finite".into(),
+            ));
+        }
+        let mut normalized = degrees % 360.0;
+        if normalized < 0.0 {
+            normalized += 360.0;
+        }
+        // very small negative values can round up to 360
+        if normalized >= 360.0 {
+            normalized = 0.0;
+        }
+        // avoid `-0`
+        Ok(ComponentCharacteristics::Rotation(normalized + 0.0))
+    }
+}
+
+impl<W: Write> PartialAttributeCode<W> for ComponentCharacteristics {
+    fn serialize_fields(&self, writer: &mut W, raw: bool) -> GerberResult<()> {
         match self {
             ComponentCharacteristics::Rotation(rotation) => {
                 write!(writer, ".CRot,{}", rotation)?;
             }
             ComponentCharacteristics::Manufacturer(manufacturer) => {
-                write!(writer, ".CMfr,{}", manufacturer)?;
+                write!(writer, ".CMfr,")?;
+                write_field(writer, manufacturer, raw)?;
             }
             ComponentCharacteristics::MPN(mpn) => {
-                write!(writer, ".CMPN,{}", mpn)?;
+                write!(writer, ".CMPN,")?;
+                write_field(writer, mpn, raw)?;
             }
             ComponentCharacteristics::Value(value) => {
-                write!(writer, ".CVal,{}", value)?;
+                write!(writer, ".CVal,")?;
+                write_field(writer, value, raw)?;
             }
             ComponentCharacteristics::Mount(mount) => {
                 write!(writer, ".CMnt,")?;
                 mount.serialize_partial(writer)?;
             }
             ComponentCharacteristics::Footprint(footprint) => {
-                write!(writer, ".CFtp,{}", footprint)?;
+                write!(writer, ".CFtp,")?;
+                write_field(writer, footprint, raw)?;
             }
             ComponentCharacteristics::PackageName(package_name) => {
-                write!(writer, ".CPgN,{}", package_name)?;
+                write!(writer, ".CPgN,")?;
+                write_field(writer, package_name, raw)?;
             }
             ComponentCharacteristics::PackageDescription(package_description) => {
-                write!(writer, ".CPgD,{}", package_description)?;
+                write!(writer, ".CPgD,")?;
+                write_field(writer, package_description, raw)?;
             }
             ComponentCharacteristics::Height(height) => {
                 write!(writer, ".CHgt,{}", height)?;
             }
             ComponentCharacteristics::LibraryName(library_name) => {
-                write!(writer, ".CLbN,{}", library_name)?;
+                write!(writer, ".CLbN,")?;
+                write_field(writer, library_name, raw)?;
             }
             ComponentCharacteristics::LibraryDescription(library_description) => {
-                write!(writer, ".CLbD,{}", library_description)?;
+                write!(writer, ".CLbD,")?;
+                write_field(writer, library_description, raw)?;
             }
             ComponentCharacteristics::Supplier(values) => {
                 write!(writer, ".CSup")?;
                 for value in values {
                     write!(writer, ",")?;
-                    value.serialize_partial(writer)?;
+                    value.serialize_fields(writer, raw)?;
                 }
             }
         }
@@ -999,8 +1446,11 @@ impl<W: Write> PartialGerberCode<W> for ComponentCharacteristics {
     }
 }
 
+impl_partial_gerber_code_via_fields!(ComponentCharacteristics);
+
 /// 2024.05 spec mismatch warning: Aperture function ".AperFunction.ComponentDill" has "PressFit" (uppercase F) whereas Component Characteristics ".CMnt" has "Pressfit" (lowercase f)"
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, IntoStaticStr, VariantNames, VariantArray)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ComponentMounting {
     #[strum(serialize = "TH")]
     ThroughHole,
@@ -1014,8 +1464,38 @@ pub enum ComponentMounting {
 }
 
 impl_partial_gerber_code_via_strum!(ComponentMounting);
+impl_from_str_via_strum!(ComponentMounting);
+
+impl ComponentMounting {
+    /// Converts from the terms used by placement systems, e.g. "SMT" or "THT".
+    ///
+    /// The comparison is case-insensitive and the Gerber terms ("SMD", "TH") are accepted too.
+    pub fn from_ipc(term: &str) -> GerberResult<Self> {
+        match term.trim().to_ascii_uppercase().as_str() {
+            "SMT" | "SMD" => Ok(ComponentMounting::SMD),
+            "THT" | "TH" | "PTH" => Ok(ComponentMounting::ThroughHole),
+            "PRESSFIT" | "PRESS-FIT" | "PRESS FIT" => Ok(ComponentMounting::PressFit),
+            "OTHER" => Ok(ComponentMounting::Other),
+            _ => Err(GerberError::ConversionError(format!(
+                "Unknown component mounting type: '{}'",
+                term
+            ))),
+        }
+    }
+
+    /// Converts to the terms used by placement systems, e.g. "SMT" or "THT".
+    pub fn to_ipc(&self) -> &'static str {
+        match self {
+            ComponentMounting::ThroughHole => "THT",
+            ComponentMounting::SMD => "SMT",
+            ComponentMounting::PressFit => "Press-fit",
+            ComponentMounting::Other => "Other",
+        }
+    }
+}
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SupplierPart {
     /// The name of the supplier, e.g. 'Mouser', 'Digikey', 'LCSC', etc.
     pub supplier_name: String,
@@ -1023,26 +1503,48 @@ pub struct SupplierPart {
     pub supplier_part_reference: String,
 }
 
-impl<W: Write> PartialGerberCode<W> for SupplierPart {
-    fn serialize_partial(&self, writer: &mut W) -> GerberResult<()> {
-        write!(
-            writer,
-            "{},{}",
-            self.supplier_name, self.supplier_part_reference
-        )?;
+impl<W: Write> PartialAttributeCode<W> for SupplierPart {
+    fn serialize_fields(&self, writer: &mut W, raw: bool) -> GerberResult<()> {
+        write_field(writer, &self.supplier_name, raw)?;
+        write!(writer, ",")?;
+        write_field(writer, &self.supplier_part_reference, raw)?;
         Ok(())
     }
 }
 
+impl_partial_gerber_code_via_fields!(SupplierPart);
+
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Net {
     None,
     NotConnected,
     Connected(Vec<String>),
 }
 
-impl<W: Write> PartialGerberCode<W> for Net {
-    fn serialize_partial(&self, writer: &mut W) -> GerberResult<()> {
+impl Net {
+    /// An object connected to the single net `name`.
+    pub fn single(name: impl Into<String>) -> Self {
+        Net::Connected(vec![name.into()])
+    }
+
+    /// The names of the nets the object is connected to, none for `None` and `NotConnected`.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        let names: &[String] = match self {
+            Net::Connected(names) => names,
+            Net::None | Net::NotConnected => &[],
+        };
+        names.iter().map(String::as_str)
+    }
+
+    /// Whether the attribute names no net, see [`Net::names`].
+    pub fn is_empty(&self) -> bool {
+        self.names().next().is_none()
+    }
+}
+
+impl<W: Write> PartialAttributeCode<W> for Net {
+    fn serialize_fields(&self, writer: &mut W, raw: bool) -> GerberResult<()> {
         write!(writer, ".N,")?;
         match self {
             Net::None => {}
@@ -1050,14 +1552,22 @@ impl<W: Write> PartialGerberCode<W> for Net {
                 write!(writer, "N/C")?;
             }
             Net::Connected(nets) => {
-                write!(writer, "{}", nets.join(","))?;
+                for (index, net) in nets.iter().enumerate() {
+                    if index > 0 {
+                        write!(writer, ",")?;
+                    }
+                    write_field(writer, net, raw)?;
+                }
             }
         }
         Ok(())
     }
 }
 
+impl_partial_gerber_code_via_fields!(Net);
+
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Pin {
     pub refdes: String,
     /// 2024.05 spec calls this 'number' but a) it's defined as a string, and b) pins like 'EP' (for exposed pad) are not numbers.
@@ -1065,16 +1575,22 @@ pub struct Pin {
     pub function: Option<String>,
 }
 
-impl<W: Write> PartialGerberCode<W> for Pin {
-    fn serialize_partial(&self, writer: &mut W) -> GerberResult<()> {
-        write!(writer, ".P,{},{}", self.refdes, self.name)?;
+impl<W: Write> PartialAttributeCode<W> for Pin {
+    fn serialize_fields(&self, writer: &mut W, raw: bool) -> GerberResult<()> {
+        write!(writer, ".P,")?;
+        write_field(writer, &self.refdes, raw)?;
+        write!(writer, ",")?;
+        write_field(writer, &self.name, raw)?;
         if let Some(function) = &self.function {
-            write!(writer, ",{}", function)?;
+            write!(writer, ",")?;
+            write_field(writer, function, raw)?;
         }
         Ok(())
     }
 }
 
+impl_partial_gerber_code_via_fields!(Pin);
+
 /// Gerber 2024.05 spec says:
 /// "The TD command deletes ONE or ALL aperture or object attributes from the attributes dictionary."
 /// and:
@@ -1085,6 +1601,7 @@ impl<W: Write> PartialGerberCode<W> for Pin {
 ///
 /// * Capitalization added for effect.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum AttributeDeletionCriterion {
     AllApertureAndObjectAttributes,
     SingleObjectAttribute(String),
@@ -1105,3 +1622,304 @@ impl<W: Write> PartialGerberCode<W> for AttributeDeletionCriterion {
         Ok(())
     }
 }
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+
+    use std::collections::HashSet;
+    use std::str::FromStr;
+
+    use chrono::{FixedOffset, TimeZone};
+    use strum::VariantArray;
+
+    use crate::traits::StrictValidation;
+
+    #[test]
+    fn test_net() {
+        let net = Net::single("GND");
+        assert_eq!(net, Net::Connected(vec!["GND".to_string()]));
+        assert_eq!(net.names().collect::<Vec<_>>(), vec!["GND"]);
+        assert!(!net.is_empty());
+
+        let net = Net::Connected(vec!["Net1".to_string(), "Net3".to_string()]);
+        assert_eq!(net.names().collect::<Vec<_>>(), vec!["Net1", "Net3"]);
+        assert!(Net::None.is_empty());
+        assert!(Net::NotConnected.is_empty());
+        assert_eq!(Net::NotConnected.names().count(), 0);
+    }
+
+    #[test]
+    fn test_write_field() {
+        let mut buf = Vec::new();
+        write_field(&mut buf, "a,b*c%d\\e", false).unwrap();
+        let escaped = String::from_utf8(buf).unwrap();
+        assert_eq!(escaped, "a\\u002Cb\\u002Ac\\u0025d\\u005Ce");
+        assert_eq!(unescape_field(&escaped), "a,b*c%d\\e");
+
+        assert_eq!(unescape_field("\\u00e9\\x\\u12"), "\u{e9}\\x\\u12");
+    }
+
+    #[test]
+    fn test_ident_try_from() {
+        assert_eq!(
+            Ident::try_from("PANEL_1").unwrap(),
+            Ident::Name("PANEL_1".to_string())
+        );
+        let uuid = "6ba7b810-9dad-11d1-80b4-00c04fd430c8";
+        assert_eq!(
+            Ident::try_from(uuid).unwrap(),
+            Ident::Uuid(Uuid::parse_str(uuid).unwrap())
+        );
+        for name in &["A,B", "A*", "100%", "A\nB"] {
+            assert!(matches!(
+                Ident::try_from(*name),
+                Err(GerberError::ValidationError(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!(Position::from_str("Bot").unwrap(), Position::Bottom);
+        assert_eq!(
+            "Inr".parse::<ExtendedPosition>().unwrap(),
+            ExtendedPosition::Inner
+        );
+        assert_eq!("Signal".parse::<CopperType>().unwrap(), CopperType::Signal);
+        assert_eq!(
+            "Negative".parse::<FilePolarity>().unwrap(),
+            FilePolarity::Negative
+        );
+        assert_eq!(
+            "SMDef".parse::<SmdPadType>().unwrap(),
+            SmdPadType::SoldermaskDefined
+        );
+
+        // the Gerber token, not the variant name
+        assert!(matches!(
+            "Bottom".parse::<Position>(),
+            Err(GerberError::ConversionError(_))
+        ));
+        assert!("top".parse::<Position>().is_err());
+
+        // every variant can be read back
+        for variant in IPC4761ViaProtection::VARIANTS {
+            let token: &'static str = variant.into();
+            assert_eq!(token.parse::<IPC4761ViaProtection>().unwrap(), *variant);
+        }
+    }
+
+    #[test]
+    fn test_object_attribute_component() {
+        assert_eq!(
+            ObjectAttribute::component("R1").unwrap(),
+            ObjectAttribute::Component("R1".to_string())
+        );
+        assert!(matches!(
+            ObjectAttribute::component(""),
+            Err(GerberError::ValidationError(_))
+        ));
+
+        // Reserved characters are escaped when written, but rejected by strict validation
+        let component = ObjectAttribute::component("R,1").unwrap();
+        let mut buf = Vec::new();
+        component.serialize_partial(&mut buf).unwrap();
+        assert_eq!(buf, b".C,R\\u002C1");
+        assert!(component.validate_strict().is_err());
+    }
+
+    #[test]
+    fn test_generation_software_version() {
+        let software = generation_software!("Vendor", "Application");
+        assert_eq!(software.vendor, "Vendor");
+        assert_eq!(software.application, "Application");
+        assert_eq!(software.version.as_deref(), Some(env!("CARGO_PKG_VERSION")));
+        assert!(!env!("CARGO_PKG_VERSION").is_empty());
+
+        let software = GenerationSoftware::gerber_types();
+        assert_eq!(software.application, "gerber-types");
+        assert_eq!(software.version.as_deref(), Some(env!("CARGO_PKG_VERSION")));
+    }
+
+    #[test]
+    fn test_component_rotation_normalized() {
+        assert_eq!(
+            ComponentCharacteristics::rotation(-90.0).unwrap(),
+            ComponentCharacteristics::Rotation(270.0)
+        );
+        assert_eq!(
+            ComponentCharacteristics::rotation(360.0).unwrap(),
+            ComponentCharacteristics::Rotation(0.0)
+        );
+        assert_eq!(
+            ComponentCharacteristics::rotation(450.5).unwrap(),
+            ComponentCharacteristics::Rotation(90.5)
+        );
+        assert_eq!(
+            ComponentCharacteristics::rotation(-0.0).unwrap(),
+            ComponentCharacteristics::Rotation(0.0)
+        );
+        assert_eq!(
+            ComponentCharacteristics::rotation(-1e-20).unwrap(),
+            ComponentCharacteristics::Rotation(0.0)
+        );
+    }
+
+    #[test]
+    fn test_component_rotation_rejects_non_finite() {
+        assert!(ComponentCharacteristics::rotation(f64::NAN).is_err());
+        assert!(ComponentCharacteristics::rotation(f64::INFINITY).is_err());
+    }
+
+    #[test]
+    fn test_via_protection_description() {
+        assert_eq!(
+            IPC4761ViaProtection::IIa.description(),
+            "Type IIa - Tented and covered, one side"
+        );
+        assert_eq!(
+            IPC4761ViaProtection::VII.description(),
+            "Type VII - Filled and capped"
+        );
+
+        let descriptions: HashSet<&str> = <IPC4761ViaProtection as VariantArray>::VARIANTS
+            .iter()
+            .map(|protection| protection.description())
+            .collect();
+        assert_eq!(
+            descriptions.len(),
+            <IPC4761ViaProtection as VariantArray>::VARIANTS.len()
+        );
+        assert!(descriptions
+            .iter()
+            .all(|description| !description.is_empty()));
+    }
+
+    #[test]
+    fn test_component_outline_display_and_from_str() {
+        let outline = ComponentOutline::Lead2Lead;
+        assert_eq!(outline.to_string(), "Lead2Lead");
+        assert_eq!(
+            ComponentOutline::from_str(&outline.to_string()).unwrap(),
+            ComponentOutline::Lead2Lead
+        );
+        assert!(matches!(
+            ComponentOutline::from_str("Outline"),
+            Err(GerberError::ConversionError(_))
+        ));
+    }
+
+    #[test]
+    fn test_file_function_other_reserved_names() {
+        assert!(matches!(
+            FileFunction::other("Copper"),
+            Err(GerberError::ValidationError(_))
+        ));
+        assert!(FileFunction::other("soldermask").is_err());
+        assert!(FileFunction::other_drawing("AssemblyDrawing").is_err());
+        assert!(FileFunction::other("Stiffener,Top").is_err());
+        assert_eq!(
+            FileFunction::other("Stiffener").unwrap(),
+            FileFunction::Other("Stiffener".to_string())
+        );
+
+        // Serialization stays permissive, only strict validation rejects reserved names.
+        let mut buf = Vec::new();
+        let attribute = FileAttribute::FileFunction(FileFunction::Other("Copper".to_string()));
+        attribute.serialize_partial(&mut buf).unwrap();
+        assert_eq!(buf, b".FileFunction,Other,Copper");
+        assert!(attribute.validate_strict().is_err());
+    }
+
+    #[test]
+    fn test_aperture_function_to_comment() {
+        let comment = ApertureFunction::ViaPad.to_comment();
+        assert_eq!(
+            comment,
+            Command::from(GCode::Comment(CommentContent::String(
+                "Aperture function: Via pad".to_string()
+            )))
+        );
+
+        let description = ApertureFunction::SmdPad(SmdPadType::SoldermaskDefined).description();
+        assert_eq!(description, "SMD pad, solder mask defined");
+    }
+
+    #[test]
+    fn test_file_attributes_with_fixed_uuid() {
+        let project_id = FileAttribute::project_id_with_uuid("Demo", Uuid::nil(), "1.0");
+        let mut buf = Vec::new();
+        project_id.serialize_partial(&mut buf).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            ".ProjectId,Demo,00000000-0000-0000-0000-000000000000,1.0"
+        );
+
+        let same_coordinates = FileAttribute::same_coordinates_with_uuid(Uuid::nil());
+        let mut buf = Vec::new();
+        same_coordinates.serialize_partial(&mut buf).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            ".SameCoordinates,00000000-0000-0000-0000-000000000000"
+        );
+    }
+
+    #[test]
+    fn test_creation_date_from() {
+        let dt = FixedOffset::east_opt(3600)
+            .unwrap()
+            .with_ymd_and_hms(2015, 2, 23, 15, 59, 51)
+            .unwrap();
+        let creation_date = FileAttribute::creation_date_from(dt);
+        let mut buf = Vec::new();
+        creation_date.serialize_partial(&mut buf).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            ".CreationDate,2015-02-23T15:59:51+01:00"
+        );
+    }
+
+    #[test]
+    fn test_component_mounting_ipc_terms() {
+        assert_eq!(
+            ComponentMounting::from_ipc("SMT").unwrap(),
+            ComponentMounting::SMD
+        );
+        assert_eq!(
+            ComponentMounting::from_ipc("tht").unwrap(),
+            ComponentMounting::ThroughHole
+        );
+        assert!(ComponentMounting::from_ipc("BGA").is_err());
+
+        for mounting in <ComponentMounting as VariantArray>::VARIANTS {
+            assert_eq!(
+                ComponentMounting::from_ipc(mounting.to_ipc()).unwrap(),
+                *mounting
+            );
+        }
+    }
+
+    #[test]
+    fn test_text_mode_from_str() {
+        assert_eq!(TextMode::from_str("B").unwrap(), TextMode::BarCode);
+        assert_eq!(TextMode::from_str("C").unwrap(), TextMode::Characters);
+        assert!(matches!(
+            TextMode::from_str("X"),
+            Err(GerberError::ConversionError(_))
+        ));
+    }
+
+    #[test]
+    fn test_text_mirroring_from_str() {
+        assert_eq!(
+            TextMirroring::from_str("R").unwrap(),
+            TextMirroring::Readable
+        );
+        assert_eq!(
+            TextMirroring::from_str("M").unwrap(),
+            TextMirroring::Mirrored
+        );
+        assert!(TextMirroring::from_str("Readable").is_err());
+    }
+}
@@ -1,12 +1,17 @@
 //! Generic code generation, e.g. implementations of `PartialGerberCode` for
-//! bool or Vec<G: GerberCode>.
+//! bool or slices of G: GerberCode.
 
-use std::io::Write;
+use alloc::borrow::Cow;
+use core::fmt;
 
 use crate::errors::GerberResult;
-use crate::traits::{GerberCode, PartialGerberCode};
+use crate::function_codes::{CommentContent, DCode, GCode, MCode};
+use crate::io::Write;
+use crate::prelude::*;
+use crate::traits::{
+    GerberCode, GerberCodeExt, PartialAttributeCode, PartialGerberCode, SerializationOptions,
+};
 use crate::types::*;
-use crate::{CoordinateMode, ZeroOmission};
 
 /// Implement `PartialGerberCode` for booleans
 impl<W: Write> PartialGerberCode<W> for bool {
@@ -20,14 +25,65 @@ impl<W: Write> PartialGerberCode<W> for bool {
     }
 }
 
-/// Implement `GerberCode` for Vectors of types that are `GerberCode`.
-impl<W: Write, G: GerberCode<W>> GerberCode<W> for Vec<G> {
+/// Implement `GerberCode` for slices of types that are `GerberCode`.
+impl<W: Write, G: GerberCode<W>> GerberCode<W> for [G] {
     fn serialize(&self, writer: &mut W) -> GerberResult<()> {
         for item in self.iter() {
             item.serialize(writer)?;
         }
         Ok(())
     }
+
+    fn serialize_with(&self, writer: &mut W, options: &SerializationOptions) -> GerberResult<()> {
+        for item in self.iter() {
+            item.serialize_with(writer, options)?;
+        }
+        Ok(())
+    }
+}
+
+/// Implement `GerberCode` for arrays of types that are `GerberCode`.
+impl<W: Write, G: GerberCode<W>, const N: usize> GerberCode<W> for [G; N] {
+    fn serialize(&self, writer: &mut W) -> GerberResult<()> {
+        self.as_slice().serialize(writer)
+    }
+
+    fn serialize_with(&self, writer: &mut W, options: &SerializationOptions) -> GerberResult<()> {
+        self.as_slice().serialize_with(writer, options)
+    }
+}
+
+/// Implement `GerberCode` for Vectors of types that are `GerberCode`.
+impl<W: Write, G: GerberCode<W>> GerberCode<W> for Vec<G> {
+    fn serialize(&self, writer: &mut W) -> GerberResult<()> {
+        self.as_slice().serialize(writer)
+    }
+
+    fn serialize_with(&self, writer: &mut W, options: &SerializationOptions) -> GerberResult<()> {
+        self.as_slice().serialize_with(writer, options)
+    }
+}
+
+/// Implement `GerberCode` for references to types that are `GerberCode`, e.g. `&[Command]`.
+impl<W: Write, G: GerberCode<W> + ?Sized> GerberCode<W> for &G {
+    fn serialize(&self, writer: &mut W) -> GerberResult<()> {
+        (**self).serialize(writer)
+    }
+
+    fn serialize_with(&self, writer: &mut W, options: &SerializationOptions) -> GerberResult<()> {
+        (**self).serialize_with(writer, options)
+    }
+}
+
+/// Implement `GerberCode` for borrowed or owned values of types that are `GerberCode`.
+impl<W: Write, G: GerberCode<W> + Clone> GerberCode<W> for Cow<'_, G> {
+    fn serialize(&self, writer: &mut W) -> GerberResult<()> {
+        self.as_ref().serialize(writer)
+    }
+
+    fn serialize_with(&self, writer: &mut W, options: &SerializationOptions) -> GerberResult<()> {
+        self.as_ref().serialize_with(writer, options)
+    }
 }
 
 /// Implement `PartialGerberCode` for `Option<T: PartialGerberCode>`
@@ -48,6 +104,14 @@ impl<W: Write> GerberCode<W> for Command {
         };
         Ok(())
     }
+
+    fn serialize_with(&self, writer: &mut W, options: &SerializationOptions) -> GerberResult<()> {
+        match *self {
+            Command::FunctionCode(ref code) => code.serialize_with(writer, options)?,
+            Command::ExtendedCode(ref code) => code.serialize_with(writer, options)?,
+        };
+        Ok(())
+    }
 }
 
 impl<W: Write> GerberCode<W> for FunctionCode {
@@ -59,25 +123,41 @@ impl<W: Write> GerberCode<W> for FunctionCode {
         };
         Ok(())
     }
+
+    fn serialize_with(&self, writer: &mut W, options: &SerializationOptions) -> GerberResult<()> {
+        match *self {
+            FunctionCode::DCode(ref code) => code.serialize_with(writer, options),
+            FunctionCode::GCode(GCode::Comment(CommentContent::Standard(ref standard)))
+                if options.raw_attribute_fields =>
+            {
+                write!(writer, "G04 ")?;
+                standard.serialize_fields(writer, true)?;
+                writeln!(writer, "*")?;
+                Ok(())
+            }
+            _ => self.serialize(writer),
+        }
+    }
+}
+
+/// Writes an attribute command, e.g. `%TF...*%`, the fields are escaped unless `raw`.
+fn serialize_attribute<W: Write, A: PartialAttributeCode<W>>(
+    writer: &mut W,
+    code: &str,
+    attribute: &A,
+    raw: bool,
+) -> GerberResult<()> {
+    write!(writer, "%{}", code)?;
+    attribute.serialize_fields(writer, raw)?;
+    writeln!(writer, "*%")?;
+    Ok(())
 }
 
 impl<W: Write> GerberCode<W> for ExtendedCode {
     fn serialize(&self, writer: &mut W) -> GerberResult<()> {
         match *self {
             ExtendedCode::CoordinateFormat(ref cf) => {
-                let zero_omission = match &cf.zero_omission {
-                    ZeroOmission::Leading => 'L',
-                    ZeroOmission::Trailing => 'T',
-                };
-                let mode = match &cf.coordinate_mode {
-                    CoordinateMode::Absolute => 'A',
-                    CoordinateMode::Incremental => 'I',
-                };
-                writeln!(
-                    writer,
-                    "%FS{2}{3}X{0}{1}Y{0}{1}*%",
-                    cf.integer, cf.decimal, zero_omission, mode
-                )?;
+                writeln!(writer, "%FS{}*%", cf)?;
             }
             ExtendedCode::Unit(ref unit) => {
                 write!(writer, "%MO")?;
@@ -120,9 +200,7 @@ impl<W: Write> GerberCode<W> for ExtendedCode {
                 writeln!(writer, "*%")?;
             }
             ExtendedCode::FileAttribute(ref attr) => {
-                write!(writer, "%TF")?;
-                attr.serialize_partial(writer)?;
-                writeln!(writer, "*%")?;
+                serialize_attribute(writer, "TF", attr, false)?;
             }
             ExtendedCode::DeleteAttribute(ref attr) => {
                 write!(writer, "%TD")?;
@@ -135,14 +213,10 @@ impl<W: Write> GerberCode<W> for ExtendedCode {
                 writeln!(writer, "*%")?;
             }
             ExtendedCode::ApertureAttribute(ref aa) => {
-                write!(writer, "%TA")?;
-                aa.serialize_partial(writer)?;
-                writeln!(writer, "*%")?;
+                serialize_attribute(writer, "TA", aa, false)?;
             }
             ExtendedCode::ObjectAttribute(ref oa) => {
-                write!(writer, "%TO")?;
-                oa.serialize_partial(writer)?;
-                writeln!(writer, "*%")?;
+                serialize_attribute(writer, "TO", oa, false)?;
             }
             ExtendedCode::MirrorImage(ref mi) => {
                 write!(writer, "%MI")?;
@@ -179,7 +253,61 @@ impl<W: Write> GerberCode<W> for ExtendedCode {
                 r#in.serialize_partial(writer)?;
                 writeln!(writer, "*%")?;
             }
+            ExtendedCode::ApertureBlockDefinition(ref block) => {
+                block.serialize(writer)?;
+            }
         };
         Ok(())
     }
+
+    fn serialize_with(&self, writer: &mut W, options: &SerializationOptions) -> GerberResult<()> {
+        if let ExtendedCode::ApertureBlockDefinition(block) = self {
+            return block.serialize_with(writer, options);
+        }
+        let raw = options.raw_attribute_fields;
+        let mut buf = Vec::new();
+        match *self {
+            ExtendedCode::FileAttribute(ref attr) if raw => {
+                serialize_attribute(&mut buf, "TF", attr, true)?
+            }
+            ExtendedCode::ApertureAttribute(ref aa) if raw => {
+                serialize_attribute(&mut buf, "TA", aa, true)?
+            }
+            ExtendedCode::ObjectAttribute(ref oa) if raw => {
+                serialize_attribute(&mut buf, "TO", oa, true)?
+            }
+            _ if !options.no_trailing_newline => return self.serialize(writer),
+            _ => self.serialize(&mut buf)?,
+        }
+        if options.no_trailing_newline && buf.last() == Some(&b'\n') {
+            buf.pop();
+        }
+        writer.write_all(&buf)?;
+        Ok(())
+    }
+}
+
+/// Writes the Gerber code of the value, without the newline at the end.
+///
+/// Errors during code generation are reported as `fmt::Error`.
+fn fmt_gerber_code<G: GerberCode<Vec<u8>>>(code: &G, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let code = code.to_gerber_string().map_err(|_| fmt::Error)?;
+    f.write_str(code.strip_suffix('\n').unwrap_or(&code))
+}
+
+macro_rules! impl_display_via_gerber_code {
+    ($name:ty) => {
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt_gerber_code(self, f)
+            }
+        }
+    };
 }
+
+impl_display_via_gerber_code!(Command);
+impl_display_via_gerber_code!(FunctionCode);
+impl_display_via_gerber_code!(ExtendedCode);
+impl_display_via_gerber_code!(DCode);
+impl_display_via_gerber_code!(GCode);
+impl_display_via_gerber_code!(MCode);
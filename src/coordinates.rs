@@ -1,14 +1,21 @@
 //! Types for Gerber code generation related to coordinates.
 
-use std::convert::TryFrom;
-use std::convert::{From, Into};
-use std::io::Write;
-use std::num::FpCategory;
+use core::convert::TryFrom;
+use core::convert::{From, Into};
+use core::fmt;
+use core::num::FpCategory;
+use core::ops::{Add, Neg, Sub};
+use core::str::FromStr;
 
 use num_rational::Ratio;
+// `FloatCore` provides the float methods that need `std` otherwise.
+use num_traits::float::FloatCore;
 
 use crate::errors::{GerberError, GerberResult};
-use crate::traits::PartialGerberCode;
+use crate::extended_codes::Unit;
+use crate::io::Write;
+use crate::prelude::*;
+use crate::traits::{PartialGerberCode, SerializationOptions, StrictValidation};
 
 // Helper macros
 
@@ -18,11 +25,21 @@ macro_rules! impl_xy_partial_gerbercode {
     ($class:ty, $x:expr, $y: expr) => {
         impl<W: Write> PartialGerberCode<W> for $class {
             fn serialize_partial(&self, writer: &mut W) -> GerberResult<()> {
+                self.serialize_partial_with(writer, &SerializationOptions::default())
+            }
+        }
+
+        impl $class {
+            pub(crate) fn serialize_partial_with<W: Write>(
+                &self,
+                writer: &mut W,
+                options: &SerializationOptions,
+            ) -> GerberResult<()> {
                 if let Some(x) = self.x {
-                    write!(writer, "{}{}", $x, x.gerber(&self.format)?)?;
+                    write!(writer, "{}{}", $x, x.gerber_with(&self.format, options)?)?;
                 }
                 if let Some(y) = self.y {
-                    write!(writer, "{}{}", $y, y.gerber(&self.format)?)?;
+                    write!(writer, "{}{}", $y, y.gerber_with(&self.format, options)?)?;
                 }
                 Ok(())
             }
@@ -39,6 +56,7 @@ macro_rules! impl_xy_partial_gerbercode {
 /// Note: This is deprecated since revision I1 from December 2012 of the Gerber
 /// format specification, but still used.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CoordinateMode {
     Absolute,
     Incremental,
@@ -51,6 +69,7 @@ pub enum CoordinateMode {
 /// Note: This is deprecated since revision 2015.06 of the Gerber format
 /// specification, but still used.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ZeroOmission {
     Leading,
     Trailing,
@@ -64,6 +83,7 @@ pub enum ZeroOmission {
 /// of integer places must be not more than 6. Thus the longest representable
 /// coordinate number is `nnnnnn.nnnnnn`.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CoordinateFormat {
     pub zero_omission: ZeroOmission,
     pub coordinate_mode: CoordinateMode,
@@ -85,6 +105,156 @@ impl CoordinateFormat {
             decimal,
         }
     }
+
+    /// The largest value that can be represented with this format, e.g. `99.9999` for `2,4`.
+    pub fn max_value(&self) -> f64 {
+        FloatCore::powi(10_f64, self.integer as i32)
+            - FloatCore::powi(10_f64, -(self.decimal as i32))
+    }
+
+    /// The smallest (most negative) value that can be represented with this format, e.g.
+    /// `-99.9999` for `2,4`.
+    pub fn min_value(&self) -> f64 {
+        -self.max_value()
+    }
+
+    /// The total number of digits (integer and decimal places), e.g. `8` for `2,6`.
+    ///
+    /// Use it to compare or sort formats by precision, e.g. with
+    /// `formats.sort_by_key(CoordinateFormat::precision_rank)`. `CoordinateFormat` does not
+    /// implement `PartialOrd` because different formats can have the same rank.
+    pub fn precision_rank(&self) -> u8 {
+        self.integer + self.decimal
+    }
+
+    /// A builder for coordinates and offsets in this format.
+    pub fn coords(self) -> CoordinateBuilder {
+        CoordinateBuilder { format: self }
+    }
+}
+
+/// Displays the format as the body of the FS command, e.g. `LAX26Y26`.
+impl fmt::Display for CoordinateFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let zero_omission = match self.zero_omission {
+            ZeroOmission::Leading => 'L',
+            ZeroOmission::Trailing => 'T',
+        };
+        let mode = match self.coordinate_mode {
+            CoordinateMode::Absolute => 'A',
+            CoordinateMode::Incremental => 'I',
+        };
+        write!(
+            f,
+            "{2}{3}X{0}{1}Y{0}{1}",
+            self.integer, self.decimal, zero_omission, mode
+        )
+    }
+}
+
+/// Parses the body of the FS command, e.g. `LAX26Y26`, or the short form without the axes, e.g.
+/// `LA26`.
+///
+/// Unlike [`parse_gerber`](crate::parse_gerber), the number of places is checked: there must be
+/// 1 to 6 integer and 4 to 6 decimal places.
+impl FromStr for CoordinateFormat {
+    type Err = GerberError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let chars: Vec<char> = s.chars().collect();
+        let format = match chars[..] {
+            [zero_omission, mode, integer, decimal] => parse_format(&format!(
+                "{0}{1}X{2}{3}Y{2}{3}",
+                zero_omission, mode, integer, decimal
+            )),
+            _ => parse_format(s),
+        }?;
+        format.validate_strict()?;
+        Ok(format)
+    }
+}
+
+/// Parses the body of the FS command without checking the number of places.
+pub(crate) fn parse_format(value: &str) -> GerberResult<CoordinateFormat> {
+    let invalid =
+        || GerberError::CoordinateFormatError(format!("Invalid coordinate format '{}'", value));
+    let chars: Vec<char> = value.chars().collect();
+    let (zero_omission, coordinate_mode, x, y) = match chars[..] {
+        [zero_omission, mode, 'X', xi, xd, 'Y', yi, yd] => {
+            (zero_omission, mode, (xi, xd), (yi, yd))
+        }
+        _ => return Err(invalid()),
+    };
+    if x != y {
+        return Err(GerberError::CoordinateFormatError(format!(
+            "Different formats for X and Y are not supported: '{}'",
+            value
+        )));
+    }
+    let zero_omission = match zero_omission {
+        'L' => ZeroOmission::Leading,
+        'T' => ZeroOmission::Trailing,
+        _ => return Err(invalid()),
+    };
+    let coordinate_mode = match coordinate_mode {
+        'A' => CoordinateMode::Absolute,
+        'I' => CoordinateMode::Incremental,
+        _ => return Err(invalid()),
+    };
+    let digit = |c: char| c.to_digit(10).map(|d| d as u8).ok_or_else(invalid);
+    Ok(CoordinateFormat::new(
+        zero_omission,
+        coordinate_mode,
+        digit(x.0)?,
+        digit(x.1)?,
+    ))
+}
+
+/// The smallest coordinate format that represents all `values` without loss, with leading zero
+/// omission and absolute coordinates.
+///
+/// At least one integer place and, as the specification requires, at least 4 decimal places are
+/// used. Values that need more than 6 integer or 6 decimal places, and values that are not
+/// finite, are an error.
+pub fn suggest_format(values: &[f64]) -> GerberResult<CoordinateFormat> {
+    let mut integer = 1;
+    let mut decimal = 4;
+    for &value in values {
+        if !value.is_finite() {
+            return Err(GerberError::ConversionError(format!(
+                "Value {} is not finite",
+                value
+            )));
+        }
+        let places = (0..=DECIMAL_PLACES_CHARS)
+            .find(|&places| {
+                let scaled = value * FloatCore::powi(10_f64, places as i32);
+                (scaled - FloatCore::round(scaled)).abs() <= scaled.abs().max(1.0) * 1e-12
+            })
+            .ok_or_else(|| {
+                GerberError::CoordinateFormatError(format!(
+                    "Value {} needs more than {} decimal places",
+                    value, DECIMAL_PLACES_CHARS
+                ))
+            })?;
+        decimal = decimal.max(places);
+        let factor = FloatCore::powi(10_f64, places as i32);
+        let rounded = FloatCore::round(value.abs() * factor) / factor;
+        let digits = (rounded.trunc() as u64).to_string().len() as u8;
+        if digits > 6 {
+            return Err(GerberError::CoordinateFormatError(format!(
+                "Value {} needs more than 6 integer places",
+                value
+            )));
+        }
+        integer = integer.max(digits);
+    }
+    Ok(CoordinateFormat::new(
+        ZeroOmission::Leading,
+        CoordinateMode::Absolute,
+        integer,
+        decimal,
+    ))
 }
 
 /// Coordinate numbers are integers conforming to the rules set by the FS
@@ -95,8 +265,11 @@ impl CoordinateFormat {
 /// A coordinate number must have at least one character. Zero therefore must
 /// be encoded as `0`.
 ///
-/// The value is stored as a 64 bit integer with 6 decimal places.
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// The value is stored as a 64 bit integer with 6 decimal places. Coordinate numbers can be
+/// added, subtracted and compared without losing precision.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct CoordinateNumber {
     nano: i64,
 }
@@ -105,6 +278,35 @@ impl CoordinateNumber {
     pub fn new(nano: i64) -> Self {
         CoordinateNumber { nano }
     }
+
+    /// The value in millionths of a unit.
+    pub(crate) fn nano(&self) -> i64 {
+        self.nano
+    }
+
+    /// Converts the value from the unit `from` to the unit `to`, e.g. from inches to
+    /// millimeters.
+    ///
+    /// The conversion is done on the internal fixed-point value (millionths of a unit), not with
+    /// floating point math. One inch is exactly 25.4 mm, but the converted value is not always a
+    /// whole number of millionths, e.g. one millionth of a millimeter is 0.0393... millionths
+    /// of an inch. The result is rounded to the nearest millionth, halfway values away from zero,
+    /// like [`CoordinateNumber::gerber`].
+    pub fn convert(self, from: Unit, to: Unit) -> CoordinateNumber {
+        let nano = i128::from(self.nano);
+        let converted = match (from, to) {
+            (Unit::Inches, Unit::Millimeters) => Ratio::new(nano * 254, 10),
+            (Unit::Millimeters, Unit::Inches) => Ratio::new(nano * 10, 254),
+            _ => return self,
+        };
+        CoordinateNumber::new(converted.round().to_integer() as i64)
+    }
+
+    /// The value converted from the unit `from` to the unit `to`, as a float. See
+    /// [`CoordinateNumber::convert`] for the rounding.
+    pub fn to_f64_in_unit(self, from: Unit, to: Unit) -> f64 {
+        f64::from(self.convert(from, to))
+    }
 }
 
 const DECIMAL_PLACES_CHARS: u8 = 6;
@@ -139,6 +341,30 @@ impl From<CoordinateNumber> for f64 {
     }
 }
 
+impl Add for CoordinateNumber {
+    type Output = CoordinateNumber;
+
+    fn add(self, rhs: CoordinateNumber) -> Self::Output {
+        CoordinateNumber::new(self.nano + rhs.nano)
+    }
+}
+
+impl Sub for CoordinateNumber {
+    type Output = CoordinateNumber;
+
+    fn sub(self, rhs: CoordinateNumber) -> Self::Output {
+        CoordinateNumber::new(self.nano - rhs.nano)
+    }
+}
+
+impl Neg for CoordinateNumber {
+    type Output = CoordinateNumber;
+
+    fn neg(self) -> Self::Output {
+        CoordinateNumber::new(-self.nano)
+    }
+}
+
 macro_rules! impl_from_integer {
     ($class:ty) => {
         impl From<$class> for CoordinateNumber {
@@ -212,6 +438,12 @@ impl_from_option_integer!(u8);
 impl_from_option_integer!(u16);
 
 impl CoordinateNumber {
+    /// The coordinate number as it is written in a file with the given format.
+    ///
+    /// With leading zero omission (`%FSL...`) the leading zeros are omitted, e.g. `1.5` is
+    /// written as `15000` in a `24` format. With trailing zero omission (`%FST...`) the integer
+    /// part is padded with zeros to the declared number of integer places and the trailing
+    /// zeros are omitted, e.g. `1.5` is written as `015`.
     pub fn gerber(&self, format: &CoordinateFormat) -> Result<String, GerberError> {
         self.validate(format)?;
 
@@ -238,6 +470,30 @@ impl CoordinateNumber {
         }
     }
 
+    /// Like [`gerber`](Self::gerber), but also returns whether rounding to the decimal places
+    /// of the format discarded nonzero digits, i.e. whether the written value differs from the
+    /// stored one.
+    pub fn gerber_checked(&self, format: &CoordinateFormat) -> Result<(String, bool), GerberError> {
+        let number = self.gerber(format)?;
+        let divisor: i64 = 10_i64.pow((DECIMAL_PLACES_CHARS - format.decimal) as u32);
+        Ok((number, self.nano % divisor != 0))
+    }
+
+    /// Like [`gerber`](Self::gerber), but positive numbers get an explicit `+` if
+    /// [`SerializationOptions::explicit_positive_sign`] is set.
+    pub(crate) fn gerber_with(
+        &self,
+        format: &CoordinateFormat,
+        options: &SerializationOptions,
+    ) -> Result<String, GerberError> {
+        let number = self.gerber(format)?;
+        if options.explicit_positive_sign && self.nano > 0 {
+            Ok(format!("+{}", number))
+        } else {
+            Ok(number)
+        }
+    }
+
     pub fn validate(self, format: &CoordinateFormat) -> Result<Self, GerberError> {
         if format.decimal > DECIMAL_PLACES_CHARS {
             return Err(GerberError::CoordinateFormatError(
@@ -251,6 +507,45 @@ impl CoordinateNumber {
         }
         Ok(self)
     }
+
+    /// Parses a coordinate number as it is written in a file with the given format, the
+    /// reverse of [`CoordinateNumber::gerber`].
+    pub fn parse(value: &str, format: &CoordinateFormat) -> Result<Self, GerberError> {
+        if format.decimal > DECIMAL_PLACES_CHARS {
+            return Err(GerberError::CoordinateFormatError(
+                "Invalid precision: Too high!".into(),
+            ));
+        }
+        let (negative, digits) = match value.as_bytes().first() {
+            Some(b'-') => (true, &value[1..]),
+            Some(b'+') => (false, &value[1..]),
+            _ => (false, value),
+        };
+        let len = (format.integer + format.decimal) as usize;
+        if digits.is_empty() || !digits.bytes().all(|c| c.is_ascii_digit()) {
+            return Err(GerberError::ConversionError(format!(
+                "Invalid coordinate number: '{}'",
+                value
+            )));
+        }
+        if digits.len() > len {
+            return Err(GerberError::CoordinateFormatError(format!(
+                "Coordinate number '{}' has more than {} digits",
+                value, len
+            )));
+        }
+        let padded = match format.zero_omission {
+            ZeroOmission::Leading => digits.to_string(),
+            ZeroOmission::Trailing => format!("{:0<width$}", digits, width = len),
+        };
+        let number: i64 = padded.parse().map_err(|_| {
+            GerberError::ConversionError(format!("Invalid coordinate number: '{}'", value))
+        })?;
+        let nano = number * 10_i64.pow((DECIMAL_PLACES_CHARS - format.decimal) as u32);
+        Ok(CoordinateNumber {
+            nano: if negative { -nano } else { nano },
+        })
+    }
 }
 
 /// Coordinates are part of an operation.
@@ -260,6 +555,7 @@ impl CoordinateNumber {
 ///
 /// It is NOT valid for both X and Y to be omitted, instead the Coordinates itself should be contained in an `Option`
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Coordinates {
     pub x: Option<CoordinateNumber>,
     pub y: Option<CoordinateNumber>,
@@ -321,6 +617,7 @@ impl_xy_partial_gerbercode!(Coordinates, "X", "Y");
 ///
 /// It is NOT valid for both X and Y to be omitted, instead the CoordinateOffset itself should be contained in an `Option`
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CoordinateOffset {
     pub x: Option<CoordinateNumber>,
     pub y: Option<CoordinateNumber>,
@@ -377,7 +674,73 @@ impl CoordinateOffset {
 
 impl_xy_partial_gerbercode!(CoordinateOffset, "I", "J");
 
-#[cfg(test)]
+/// Builds [`Coordinates`] and [`CoordinateOffset`]s in a single format, so the format does not
+/// have to be passed to every constructor. Created with [`CoordinateFormat::coords`].
+///
+/// ```
+/// # use gerber_types::{CoordinateFormat, CoordinateMode, Coordinates, ZeroOmission};
+/// let format = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 4);
+/// let c = format.coords();
+/// assert_eq!(c.xy(5, 0), Coordinates::new(5, 0, format));
+/// assert_eq!(c.at_x(6), Coordinates::at_x(6, format));
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CoordinateBuilder {
+    format: CoordinateFormat,
+}
+
+impl CoordinateBuilder {
+    /// The format of the built coordinates and offsets.
+    pub fn format(&self) -> CoordinateFormat {
+        self.format
+    }
+
+    pub fn xy<T, U>(&self, x: T, y: U) -> Coordinates
+    where
+        T: IntoOptionalCoordinate,
+        U: IntoOptionalCoordinate,
+    {
+        Coordinates::new(x, y, self.format)
+    }
+
+    pub fn at_x<T>(&self, x: T) -> Coordinates
+    where
+        T: IntoOptionalCoordinate,
+    {
+        Coordinates::at_x(x, self.format)
+    }
+
+    pub fn at_y<T>(&self, y: T) -> Coordinates
+    where
+        T: IntoOptionalCoordinate,
+    {
+        Coordinates::at_y(y, self.format)
+    }
+
+    pub fn offset<T, U>(&self, x: T, y: U) -> CoordinateOffset
+    where
+        T: IntoOptionalCoordinate,
+        U: IntoOptionalCoordinate,
+    {
+        CoordinateOffset::new(x, y, self.format)
+    }
+
+    pub fn offset_x<T>(&self, x: T) -> CoordinateOffset
+    where
+        T: IntoOptionalCoordinate,
+    {
+        CoordinateOffset::at_x(x, self.format)
+    }
+
+    pub fn offset_y<T>(&self, y: T) -> CoordinateOffset
+    where
+        T: IntoOptionalCoordinate,
+    {
+        CoordinateOffset::at_y(y, self.format)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
 mod test {
     use super::*;
 
@@ -386,7 +749,105 @@ mod test {
 
     use std::convert::TryFrom;
 
-    use crate::traits::PartialGerberCode;
+    use crate::traits::{PartialGerberCode, StrictValidation};
+
+    #[test]
+    fn test_coordinate_format_display_from_str() {
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 6);
+        assert_eq!(cf.to_string(), "LAX26Y26");
+        assert_eq!("LAX26Y26".parse::<CoordinateFormat>().unwrap(), cf);
+        assert_eq!("LA26".parse::<CoordinateFormat>().unwrap(), cf);
+        let cf = CoordinateFormat::new(ZeroOmission::Trailing, CoordinateMode::Incremental, 6, 4);
+        assert_eq!(cf.to_string().parse::<CoordinateFormat>().unwrap(), cf);
+        assert_eq!("TI64".parse::<CoordinateFormat>().unwrap(), cf);
+
+        for invalid in &[
+            "LA23", "LA76", "LA06", "LAX26Y25", "XA26", "LA2", "", "LAX2aY2a",
+        ] {
+            assert!(
+                matches!(
+                    invalid.parse::<CoordinateFormat>(),
+                    Err(GerberError::CoordinateFormatError(_))
+                ),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_precision_rank() {
+        let cf26 = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 6);
+        let cf24 = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 4);
+        let cf35 = CoordinateFormat::new(ZeroOmission::Trailing, CoordinateMode::Absolute, 3, 5);
+        assert_eq!(cf26.precision_rank(), 8);
+        assert!(cf26.precision_rank() > cf24.precision_rank());
+
+        let mut formats = vec![cf26, cf24, cf35];
+        formats.sort_by_key(CoordinateFormat::precision_rank);
+        assert_eq!(formats, vec![cf24, cf26, cf35]);
+    }
+
+    #[test]
+    fn test_coordinate_builder() {
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 4);
+        let c = cf.coords();
+        assert_eq!(c.format(), cf);
+        assert_eq!(c.xy(5, 0), Coordinates::new(5, 0, cf));
+        assert_eq!(c.at_x(6), Coordinates::at_x(6, cf));
+        assert_eq!(c.at_y(3), Coordinates::at_y(3, cf));
+        assert_eq!(c.offset(1, 2), CoordinateOffset::new(1, 2, cf));
+        assert_eq!(c.offset_x(1), CoordinateOffset::at_x(1, cf));
+        assert_eq!(c.offset_y(2), CoordinateOffset::at_y(2, cf));
+    }
+
+    #[test]
+    fn test_suggest_format() {
+        let format = suggest_format(&[12.345, -1.5, 0.0]).unwrap();
+        assert_eq!((format.integer, format.decimal), (2, 4));
+        assert_eq!(format.zero_omission, ZeroOmission::Leading);
+        assert_eq!(format.coordinate_mode, CoordinateMode::Absolute);
+
+        let format = suggest_format(&[123456.000001, 0.1]).unwrap();
+        assert_eq!((format.integer, format.decimal), (6, 6));
+        let format = suggest_format(&[]).unwrap();
+        assert_eq!((format.integer, format.decimal), (1, 4));
+
+        // Fewer decimal places are not allowed by the specification
+        let format = suggest_format(&[1.5, 2.25]).unwrap();
+        assert_eq!((format.integer, format.decimal), (1, 4));
+        assert!(format.validate_strict().is_ok());
+        let format = suggest_format(&[0.12345]).unwrap();
+        assert_eq!((format.integer, format.decimal), (1, 5));
+
+        assert!(matches!(
+            suggest_format(&[1.2345678]),
+            Err(GerberError::CoordinateFormatError(_))
+        ));
+        assert!(suggest_format(&[1234567.0]).is_err());
+        assert!(suggest_format(&[f64::NAN]).is_err());
+    }
+
+    #[test]
+    fn test_parse_coordinate_number() {
+        let leading = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 4);
+        let trailing =
+            CoordinateFormat::new(ZeroOmission::Trailing, CoordinateMode::Absolute, 2, 4);
+        for value in [0.0, 1.5, -1.5, 12.3456, 0.0001].iter() {
+            let number = CoordinateNumber::try_from(*value).unwrap();
+            for format in [leading, trailing].iter() {
+                let gerber = number.gerber(format).unwrap();
+                assert_eq!(CoordinateNumber::parse(&gerber, format).unwrap(), number);
+            }
+        }
+        assert_eq!(
+            CoordinateNumber::parse("015", &trailing).unwrap(),
+            CoordinateNumber::try_from(1.5).unwrap()
+        );
+        assert!(CoordinateNumber::parse("1234567", &leading).is_err());
+        assert!(CoordinateNumber::parse("1.5", &leading).is_err());
+        assert!(CoordinateNumber::parse("", &leading).is_err());
+    }
 
     #[test]
     /// Test integer to coordinate number conversion
@@ -478,6 +939,68 @@ mod test {
         assert_eq!(cn1.nano, nano);
     }
 
+    #[test]
+    fn test_coordinate_number_arithmetic() {
+        let a = CoordinateNumber::from(5);
+        let b = CoordinateNumber::new(1_500_000);
+        assert_eq!(a + b, CoordinateNumber::new(6_500_000));
+        assert_eq!(a - b, CoordinateNumber::new(3_500_000));
+        assert_eq!(b - a, CoordinateNumber::new(-3_500_000));
+        assert_eq!(-a, CoordinateNumber::from(-5));
+        assert!(b < a);
+        assert!(-a < b);
+        assert_eq!(a.max(b), a);
+
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 6);
+        let start = Coordinates::new(1, 2, cf);
+        let center = Coordinates::new(3, 1, cf);
+        let offset = CoordinateOffset::new(
+            center.x.unwrap() - start.x.unwrap(),
+            center.y.unwrap() - start.y.unwrap(),
+            cf,
+        );
+        assert_eq!(offset, CoordinateOffset::new(2, -1, cf));
+    }
+
+    #[test]
+    fn test_coordinate_number_convert() {
+        let inch = CoordinateNumber::from(1);
+        assert_eq!(
+            inch.convert(Unit::Inches, Unit::Millimeters),
+            CoordinateNumber::new(25_400_000)
+        );
+        assert_eq!(inch.to_f64_in_unit(Unit::Inches, Unit::Millimeters), 25.4);
+        assert_eq!(
+            CoordinateNumber::new(25_400_000).convert(Unit::Millimeters, Unit::Inches),
+            inch
+        );
+        assert_eq!(inch.convert(Unit::Inches, Unit::Inches), inch);
+
+        // 1 mm = 0.039370078... in, rounded to the nearest millionth
+        assert_eq!(
+            CoordinateNumber::from(1).convert(Unit::Millimeters, Unit::Inches),
+            CoordinateNumber::new(39_370)
+        );
+        assert_eq!(
+            CoordinateNumber::from(-1).convert(Unit::Millimeters, Unit::Inches),
+            CoordinateNumber::new(-39_370)
+        );
+        // 0.000005 in = 0.000127 mm, 0.000001 mm = 0.0000000394 in rounds to 0
+        assert_eq!(
+            CoordinateNumber::new(5).convert(Unit::Inches, Unit::Millimeters),
+            CoordinateNumber::new(127)
+        );
+        assert_eq!(
+            CoordinateNumber::new(1).convert(Unit::Millimeters, Unit::Inches),
+            CoordinateNumber::new(0)
+        );
+        // 0.000127 mm is exactly 0.000005 in
+        assert_eq!(
+            CoordinateNumber::new(127).convert(Unit::Millimeters, Unit::Inches),
+            CoordinateNumber::new(5)
+        );
+    }
+
     #[test]
     /// Test coordinate number to string conversion when it's 0
     fn test_formatted_zero() {
@@ -522,6 +1045,24 @@ mod test {
         assert_eq!(d, "-2".to_string());
     }
 
+    #[test]
+    /// Test that the integer part is padded when trailing zeros are omitted
+    fn test_formatted_omit_trailing_padding() {
+        let cf24 = CoordinateFormat::new(ZeroOmission::Trailing, CoordinateMode::Absolute, 2, 4);
+        let cf36 = CoordinateFormat::new(ZeroOmission::Trailing, CoordinateMode::Absolute, 3, 6);
+
+        let a = CoordinateNumber { nano: 1500000 }.gerber(&cf24).unwrap();
+        let b = CoordinateNumber { nano: 50000 }.gerber(&cf24).unwrap();
+        let c = CoordinateNumber { nano: -12345600 }.gerber(&cf24).unwrap();
+        let d = CoordinateNumber { nano: 1500000 }.gerber(&cf36).unwrap();
+        let e = CoordinateNumber { nano: 123000001 }.gerber(&cf36).unwrap();
+        assert_eq!(a, "015".to_string());
+        assert_eq!(b, "0005".to_string());
+        assert_eq!(c, "-123456".to_string());
+        assert_eq!(d, "0015".to_string());
+        assert_eq!(e, "123000001".to_string());
+    }
+
     #[test]
     /// Test coordinate number to string conversion
     fn test_formatted_65() {
@@ -574,6 +1115,37 @@ mod test {
         assert_eq!(d, "-1234567891".to_string());
     }
 
+    #[test]
+    fn test_formatted_checked() {
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 4, 4);
+        let exact = CoordinateNumber { nano: 1234432100 }.gerber_checked(&cf);
+        assert_eq!(exact.unwrap(), ("12344321".to_string(), false));
+        let rounded = CoordinateNumber { nano: 1234432199 }.gerber_checked(&cf);
+        assert_eq!(rounded.unwrap(), ("12344322".to_string(), true));
+        let negative = CoordinateNumber { nano: -1 }.gerber_checked(&cf);
+        assert_eq!(negative.unwrap(), ("0".to_string(), true));
+
+        let cf26 = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 6);
+        let full = CoordinateNumber { nano: 1234567 }.gerber_checked(&cf26);
+        assert_eq!(full.unwrap(), ("1234567".to_string(), false));
+    }
+
+    #[test]
+    fn test_format_max_min_value() {
+        let cf24 = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 4);
+        assert!(cf24.max_value() < 100.0);
+        assert!((cf24.max_value() - 99.9999).abs() < 1e-9);
+        assert!((cf24.min_value() + 99.9999).abs() < 1e-9);
+
+        let max = CoordinateNumber::try_from(cf24.max_value()).unwrap();
+        assert_eq!(max.gerber(&cf24).unwrap(), "999999");
+        let min = CoordinateNumber::try_from(cf24.min_value()).unwrap();
+        assert_eq!(min.gerber(&cf24).unwrap(), "-999999");
+
+        let cf66 = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 6, 6);
+        assert!((cf66.max_value() - 999999.999999).abs() < 1e-6);
+    }
+
     #[test]
     fn test_coordinates_into() {
         let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 4);
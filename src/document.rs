@@ -0,0 +1,273 @@
+//! A complete Gerber file as a list of commands.
+
+use crate::errors::{GerberError, GerberResult, LocatedError};
+use crate::extended_codes::Aperture;
+use crate::function_codes::DCode;
+use crate::io::Write;
+use crate::prelude::*;
+use crate::traits::{GerberCode, SerializationOptions};
+use crate::types::{Command, ExtendedCode, FunctionCode};
+use crate::validation::{
+    validate_closed_at_eof, validate_defined_apertures, validate_eof, validate_quadrant_mode,
+    validate_single_file_function, validate_single_header, validate_single_image_name,
+};
+
+/// The commands of a complete Gerber file, from the header to the end of file (M02).
+///
+/// Unlike a plain `Vec<Command>`, a document can check the ordering that the specification
+/// requires for a whole file, see [`GerberDocument::validate`].
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GerberDocument {
+    pub commands: Vec<Command>,
+}
+
+impl GerberDocument {
+    pub fn new(commands: Vec<Command>) -> Self {
+        GerberDocument { commands }
+    }
+
+    /// Checks the problems that make the file invalid:
+    ///
+    /// - The coordinate format (FS) and unit (MO) must be set exactly once, before the first
+    ///   operation (D01, D02 or D03).
+    /// - There must be exactly one end of file command (M02), and it must be the last command.
+    /// - Every selected aperture must be defined before it is selected.
+    /// - Polygon, rectangle and obround apertures must be valid, see
+    ///   [`Polygon::validate`](crate::Polygon::validate) and
+    ///   [`Rectangular::validate`](crate::Rectangular::validate).
+    ///
+    /// The first problem found is returned.
+    pub fn validate(&self) -> GerberResult<()> {
+        self.validate_header()?;
+        validate_single_header(&self.commands)?;
+        validate_eof(&self.commands)?;
+        if let Some(error) = validate_defined_apertures(&self.commands)
+            .into_iter()
+            .next()
+        {
+            return Err(error.into());
+        }
+        self.validate_apertures()
+    }
+
+    fn validate_apertures(&self) -> GerberResult<()> {
+        for (index, command) in self.commands.iter().enumerate() {
+            if let Command::ExtendedCode(ExtendedCode::ApertureDefinition(definition)) = command {
+                match &definition.aperture {
+                    Aperture::Polygon(polygon) => polygon.validate(),
+                    Aperture::Rectangle(rectangular) | Aperture::Obround(rectangular) => {
+                        rectangular.validate()
+                    }
+                    _ => Ok(()),
+                }
+                .map_err(|error| LocatedError::new(index, error))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the problems that readers usually tolerate, e.g. a missing quadrant mode, an
+    /// unclosed region or a second file function.
+    pub fn warnings(&self) -> Vec<LocatedError> {
+        let mut warnings = validate_quadrant_mode(&self.commands);
+        warnings.extend(validate_closed_at_eof(&self.commands));
+        warnings.extend(validate_single_image_name(&self.commands));
+        warnings.extend(validate_single_file_function(&self.commands));
+        warnings
+    }
+
+    /// Checks that FS and MO are present before the first operation, `validate_single_header`
+    /// does not report them missing.
+    fn validate_header(&self) -> GerberResult<()> {
+        let mut format_seen = false;
+        let mut unit_seen = false;
+        for (index, command) in self.commands.iter().enumerate() {
+            match command {
+                Command::ExtendedCode(ExtendedCode::CoordinateFormat(_)) => format_seen = true,
+                Command::ExtendedCode(ExtendedCode::Unit(_)) => unit_seen = true,
+                Command::FunctionCode(FunctionCode::DCode(DCode::Operation(_))) => {
+                    let missing = match (format_seen, unit_seen) {
+                        (false, _) => "coordinate format (FS)",
+                        (true, false) => "unit (MO)",
+                        (true, true) => return Ok(()),
+                    };
+                    return Err(LocatedError::new(
+                        index,
+                        GerberError::MissingDataError(format!(
+                            "the {} must be set before the first operation",
+                            missing
+                        )),
+                    )
+                    .into());
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+impl From<Vec<Command>> for GerberDocument {
+    fn from(commands: Vec<Command>) -> Self {
+        GerberDocument::new(commands)
+    }
+}
+
+impl<W: Write> GerberCode<W> for GerberDocument {
+    fn serialize(&self, writer: &mut W) -> GerberResult<()> {
+        self.commands.serialize(writer)
+    }
+
+    fn serialize_with(&self, writer: &mut W, options: &SerializationOptions) -> GerberResult<()> {
+        self.commands.serialize_with(writer, options)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+
+    use std::io::BufWriter;
+
+    use crate::coordinates::{CoordinateFormat, Coordinates};
+    use crate::extended_codes::{ApertureDefinition, Circle, Polygon};
+    use crate::function_codes::{MCode, Operation};
+    use crate::{CoordinateMode, Unit, ZeroOmission};
+
+    fn document() -> GerberDocument {
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 6);
+        GerberDocument::new(vec![
+            ExtendedCode::CoordinateFormat(cf).into(),
+            ExtendedCode::Unit(Unit::Millimeters).into(),
+            ExtendedCode::ApertureDefinition(ApertureDefinition::new(
+                10,
+                Aperture::Circle(Circle::new(0.5)),
+            ))
+            .into(),
+            DCode::SelectAperture(10).into(),
+            DCode::Operation(Operation::Flash(Some(Coordinates::new(1, 1, cf)))).into(),
+            MCode::EndOfFile.into(),
+        ])
+    }
+
+    #[test]
+    fn test_validate() {
+        let valid = document();
+        assert!(valid.validate().is_ok());
+        assert!(valid.warnings().is_empty());
+
+        let mut missing_unit = document();
+        missing_unit.commands.remove(1);
+        let error = missing_unit.validate().unwrap_err();
+        assert!(matches!(
+            &error,
+            GerberError::Located(located)
+                if located.index == 3 && matches!(located.error, GerberError::MissingDataError(_))
+        ));
+        assert!(error
+            .to_string()
+            .contains("Command 3: Required data is missing: the unit (MO) must be set before the first operation"));
+
+        let mut late_format = document();
+        let format = late_format.commands.remove(0);
+        late_format.commands.insert(4, format);
+        assert!(late_format.validate().is_err());
+
+        let mut missing_eof = document();
+        missing_eof.commands.pop();
+        assert!(missing_eof.validate().is_err());
+
+        let mut two_eofs = document();
+        two_eofs.commands.push(MCode::EndOfFile.into());
+        assert!(two_eofs.validate().is_err());
+
+        let mut undefined = document();
+        undefined.commands.remove(2);
+        let error = undefined.validate().unwrap_err();
+        assert!(error.to_string().contains("aperture D10"));
+
+        let mut polygon = document();
+        polygon.commands[2] = ExtendedCode::ApertureDefinition(ApertureDefinition::new(
+            10,
+            Aperture::Polygon(Polygon::new(0.5, 2)),
+        ))
+        .into();
+        let error = polygon.validate().unwrap_err();
+        assert!(matches!(
+            &error,
+            GerberError::Located(located)
+                if located.index == 2 && matches!(located.error, GerberError::RangeError(_))
+        ));
+        assert!(error
+            .to_string()
+            .contains("Command 2: A value is out of range: The number of vertices"));
+
+        let mut obround = document();
+        obround.commands[2] = ExtendedCode::ApertureDefinition(ApertureDefinition::new(
+            10,
+            Aperture::obround(1.0, 0.0),
+        ))
+        .into();
+        let error = obround.validate().unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("Command 2: A value is out of range: The size of a rectangle"));
+    }
+
+    #[test]
+    fn test_serialize() {
+        assert_code!(
+            document(),
+            "%FSLAX26Y26*%\n%MOMM*%\n%ADD10C,0.5*%\nD10*\nX1000000Y1000000D03*\nM02*\n"
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        use crate::attributes::{FileAttribute, ObjectAttribute};
+        use crate::coordinates::{CoordinateNumber, CoordinateOffset};
+        use crate::function_codes::{GCode, InterpolationMode};
+        use crate::macros::{ApertureMacro, CirclePrimitive, MacroDecimal};
+        use crate::{GerberDate, Uuid};
+
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 6);
+        let date = GerberDate::parse_from_rfc3339("2015-02-23T15:59:51+01:00").unwrap();
+        let mut document = document();
+        document.commands.splice(
+            2..2,
+            vec![
+                ExtendedCode::FileAttribute(FileAttribute::creation_date_from(date)).into(),
+                ExtendedCode::FileAttribute(FileAttribute::project_id_with_uuid(
+                    "Demo",
+                    Uuid::nil(),
+                    "1.0",
+                ))
+                .into(),
+                ExtendedCode::ApertureMacro(
+                    ApertureMacro::new("DOT")
+                        .add_content(CirclePrimitive::new(MacroDecimal::Variable(1))),
+                )
+                .into(),
+                ExtendedCode::ObjectAttribute(ObjectAttribute::Component("R1".to_string())).into(),
+                GCode::InterpolationMode(InterpolationMode::ClockwiseCircular).into(),
+                DCode::Operation(Operation::Interpolate(
+                    Some(Coordinates::new(2, 0, cf)),
+                    Some(CoordinateOffset::new(1, 0, cf)),
+                ))
+                .into(),
+            ],
+        );
+
+        let json = serde_json::to_string(&document).unwrap();
+        assert!(json.contains("\"2015-02-23T15:59:51+01:00\""));
+        let deserialized: GerberDocument = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, document);
+
+        assert_eq!(
+            serde_json::to_string(&CoordinateNumber::new(1_500_000)).unwrap(),
+            "1500000"
+        );
+    }
+}
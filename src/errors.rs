@@ -1,6 +1,7 @@
 //! Error types used in the gerber-types library.
 
-use std::io::Error as IoError;
+use crate::io::Error as IoError;
+use crate::prelude::*;
 
 use thiserror::Error;
 
@@ -18,11 +19,38 @@ pub enum GerberError {
     #[error("Required data is missing: {0}")]
     MissingDataError(String),
 
+    #[error("Validation failed: {0}")]
+    ValidationError(String),
+
     #[error("I/O error during code generation")]
     IoError(#[from] IoError),
 
     #[error("Empty coordinates")]
     EmptyCoordinates,
+
+    #[error(transparent)]
+    Located(Box<LocatedError>),
+}
+
+/// An error caused by the command at `index` of a command stream, e.g. reported by the
+/// `validate_*` functions.
+#[derive(Error, Debug)]
+#[error("Command {index}: {error}")]
+pub struct LocatedError {
+    pub index: usize,
+    pub error: GerberError,
+}
+
+impl LocatedError {
+    pub fn new(index: usize, error: GerberError) -> Self {
+        LocatedError { index, error }
+    }
+}
+
+impl From<LocatedError> for GerberError {
+    fn from(error: LocatedError) -> Self {
+        GerberError::Located(Box::new(error))
+    }
 }
 
 pub type GerberResult<T> = Result<T, GerberError>;
@@ -39,4 +67,13 @@ mod tests {
             "Bad coordinate format: Something went wrong"
         );
     }
+
+    #[test]
+    fn test_located_error_msg() {
+        let err = LocatedError::new(3, GerberError::ValidationError("Something".into()));
+        assert_eq!(err.to_string(), "Command 3: Validation failed: Something");
+        let err = GerberError::from(err);
+        assert_eq!(err.to_string(), "Command 3: Validation failed: Something");
+        assert!(matches!(err, GerberError::Located(located) if located.index == 3));
+    }
 }
@@ -0,0 +1,237 @@
+//! Export of drill hits to Excellon drill files.
+//!
+//! Only available with the `excellon` feature.
+
+use std::collections::HashMap;
+use std::io::Write;
+
+use crate::coordinates::{CoordinateFormat, CoordinateMode, CoordinateNumber, ZeroOmission};
+use crate::errors::{GerberError, GerberResult, LocatedError};
+use crate::extended_codes::{Aperture, Unit};
+use crate::function_codes::{DCode, Operation};
+use crate::types::{Command, ExtendedCode, FunctionCode};
+
+/// Writes the flashes of the stream as an Excellon 2 drill program.
+///
+/// Every flash (D03) is a drill hit with the selected aperture, which must be a circle. The
+/// distinct diameters become the tools, numbered from `T01` in order of first use. The unit and
+/// the coordinate format are taken from the `%MO` and `%FS` commands, which are required. Only
+/// absolute coordinates are supported.
+///
+/// Moves are followed to know the current point, interpolations (routed slots) are an error.
+/// All other commands are ignored.
+pub fn write_excellon<W: Write>(commands: &[Command], writer: &mut W) -> GerberResult<()> {
+    let mut format: Option<CoordinateFormat> = None;
+    let mut unit: Option<Unit> = None;
+    let mut apertures: HashMap<i32, &Aperture> = HashMap::new();
+    let mut selected: Option<i32> = None;
+    let mut current: (Option<CoordinateNumber>, Option<CoordinateNumber>) = (None, None);
+    let mut tools: Vec<f64> = Vec::new();
+    let mut hits: Vec<(usize, CoordinateNumber, CoordinateNumber)> = Vec::new();
+    for (index, command) in commands.iter().enumerate() {
+        match command {
+            Command::ExtendedCode(ExtendedCode::CoordinateFormat(cf)) => {
+                if cf.coordinate_mode == CoordinateMode::Incremental {
+                    return Err(LocatedError::new(
+                        index,
+                        GerberError::CoordinateFormatError(
+                            "incremental coordinates are not supported".into(),
+                        ),
+                    )
+                    .into());
+                }
+                format = Some(*cf);
+            }
+            Command::ExtendedCode(ExtendedCode::Unit(mo)) => unit = Some(*mo),
+            Command::ExtendedCode(ExtendedCode::ApertureDefinition(def)) => {
+                apertures.insert(def.code, &def.aperture);
+            }
+            Command::FunctionCode(FunctionCode::DCode(DCode::SelectAperture(code))) => {
+                selected = Some(*code);
+            }
+            Command::FunctionCode(FunctionCode::DCode(DCode::Operation(operation))) => {
+                let coordinates = match operation {
+                    Operation::Interpolate(..) => {
+                        return Err(LocatedError::new(
+                            index,
+                            GerberError::ConversionError(
+                                "interpolations (routed slots) are not supported".into(),
+                            ),
+                        )
+                        .into())
+                    }
+                    Operation::Move(coordinates) | Operation::Flash(coordinates) => coordinates,
+                };
+                if let Some(coordinates) = coordinates {
+                    current = (coordinates.x.or(current.0), coordinates.y.or(current.1));
+                }
+                if let Operation::Flash(_) = operation {
+                    let diameter = drill_diameter(index, selected, &apertures)?;
+                    let tool = match tools.iter().position(|tool| *tool == diameter) {
+                        Some(tool) => tool,
+                        None => {
+                            tools.push(diameter);
+                            tools.len() - 1
+                        }
+                    };
+                    match current {
+                        (Some(x), Some(y)) => hits.push((tool, x, y)),
+                        _ => {
+                            return Err(LocatedError::new(
+                                index,
+                                GerberError::MissingDataError(
+                                    "the position of the flash is not known".into(),
+                                ),
+                            )
+                            .into())
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    let format = format.ok_or_else(|| {
+        GerberError::MissingDataError("The coordinate format (FS) is missing".into())
+    })?;
+    let unit =
+        unit.ok_or_else(|| GerberError::MissingDataError("The unit (MO) is missing".into()))?;
+
+    writeln!(writer, "M48")?;
+    writeln!(writer, ";FILE_FORMAT={}:{}", format.integer, format.decimal)?;
+    let unit = match unit {
+        Unit::Millimeters => "METRIC",
+        Unit::Inches => "INCH",
+    };
+    // Excellon names the zeros that are kept, Gerber the zeros that are omitted.
+    let zeros = match format.zero_omission {
+        ZeroOmission::Leading => "TZ",
+        ZeroOmission::Trailing => "LZ",
+    };
+    writeln!(writer, "{},{}", unit, zeros)?;
+    for (tool, diameter) in tools.iter().enumerate() {
+        writeln!(writer, "T{:02}C{}", tool + 1, diameter)?;
+    }
+    writeln!(writer, "%")?;
+    writeln!(writer, "G90")?;
+    writeln!(writer, "G05")?;
+    let mut selected_tool = None;
+    for (tool, x, y) in hits {
+        if selected_tool != Some(tool) {
+            writeln!(writer, "T{:02}", tool + 1)?;
+            selected_tool = Some(tool);
+        }
+        writeln!(writer, "X{}Y{}", x.gerber(&format)?, y.gerber(&format)?)?;
+    }
+    writeln!(writer, "M30")?;
+    Ok(())
+}
+
+fn drill_diameter(
+    index: usize,
+    selected: Option<i32>,
+    apertures: &HashMap<i32, &Aperture>,
+) -> GerberResult<f64> {
+    let code = selected.ok_or_else(|| {
+        LocatedError::new(
+            index,
+            GerberError::MissingDataError("flash without a selected aperture".into()),
+        )
+    })?;
+    match apertures.get(&code) {
+        Some(Aperture::Circle(circle)) => Ok(circle.diameter),
+        Some(_) => Err(LocatedError::new(
+            index,
+            GerberError::ConversionError(format!(
+                "aperture D{} is not a circle, it cannot be drilled",
+                code
+            )),
+        )
+        .into()),
+        None => Err(LocatedError::new(
+            index,
+            GerberError::MissingDataError(format!("aperture D{} is not defined", code)),
+        )
+        .into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::coordinates::Coordinates;
+    use crate::extended_codes::{ApertureDefinition, Circle, Rectangular};
+    use crate::function_codes::MCode;
+
+    fn drill_file() -> Vec<Command> {
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 4);
+        let flash = |x, y| DCode::Operation(Operation::Flash(Some(Coordinates::new(x, y, cf))));
+        vec![
+            ExtendedCode::CoordinateFormat(cf).into(),
+            ExtendedCode::Unit(Unit::Millimeters).into(),
+            ApertureDefinition::new(10, Aperture::Circle(Circle::new(0.8))).into(),
+            ApertureDefinition::new(11, Aperture::Circle(Circle::new(1.2))).into(),
+            ApertureDefinition::new(12, Aperture::Circle(Circle::new(0.8))).into(),
+            DCode::SelectAperture(10).into(),
+            flash(1, 2).into(),
+            DCode::Operation(Operation::Flash(Some(Coordinates::at_x(3, cf)))).into(),
+            DCode::SelectAperture(11).into(),
+            flash(0, 0).into(),
+            DCode::SelectAperture(12).into(),
+            flash(5, 5).into(),
+            MCode::EndOfFile.into(),
+        ]
+    }
+
+    #[test]
+    fn test_write_excellon() {
+        let mut buf = Vec::new();
+        write_excellon(&drill_file(), &mut buf).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "M48\n\
+             ;FILE_FORMAT=2:4\n\
+             METRIC,TZ\n\
+             T01C0.8\n\
+             T02C1.2\n\
+             %\n\
+             G90\n\
+             G05\n\
+             T01\n\
+             X10000Y20000\n\
+             X30000Y20000\n\
+             T02\n\
+             X0Y0\n\
+             T01\n\
+             X50000Y50000\n\
+             M30\n"
+        );
+    }
+
+    #[test]
+    fn test_write_excellon_errors() {
+        let mut commands = drill_file();
+        commands[3] =
+            ApertureDefinition::new(11, Aperture::Rectangle(Rectangular::new(1.0, 1.0))).into();
+        let error = write_excellon(&commands, &mut Vec::new()).unwrap_err();
+        assert!(matches!(
+            &error,
+            GerberError::Located(located)
+                if located.index == 9 && matches!(located.error, GerberError::ConversionError(_))
+        ));
+        assert!(error.to_string().contains("Command 9: "));
+        assert!(error.to_string().contains("aperture D11"));
+
+        let mut commands = drill_file();
+        commands.remove(1);
+        assert!(matches!(
+            write_excellon(&commands, &mut Vec::new()),
+            Err(GerberError::MissingDataError(_))
+        ));
+
+        let mut commands = drill_file();
+        commands.remove(5);
+        assert!(write_excellon(&commands, &mut Vec::new()).is_err());
+    }
+}
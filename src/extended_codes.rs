@@ -1,16 +1,18 @@
 //! Extended code types.
 
-use std::io::Write;
-
-use crate::errors::GerberResult;
-use crate::traits::PartialGerberCode;
+use crate::errors::{GerberError, GerberResult};
+use crate::io::Write;
+use crate::prelude::*;
+use crate::traits::{GerberCode, PartialGerberCode, SerializationOptions};
+use crate::types::{Command, ExtendedCode};
+use crate::validation::check_aperture_code;
 use crate::MacroDecimal;
-use strum_macros;
 use strum_macros::{IntoStaticStr, VariantArray, VariantNames};
 
 // Unit
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, IntoStaticStr, VariantNames, VariantArray)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[strum(serialize_all = "UPPERCASE")]
 pub enum Unit {
     #[strum(serialize = "IN")]
@@ -20,10 +22,52 @@ pub enum Unit {
 }
 
 impl_partial_gerber_code_via_strum!(Unit);
+impl_from_str_via_strum!(Unit);
+
+/// A length together with its unit.
+///
+/// Used to define apertures from sizes in a different unit than the file, e.g.
+/// [`Circle::from_length`], so that a size in mils is not written as millimeters by mistake.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Length {
+    pub value: f64,
+    pub unit: Unit,
+}
+
+impl Length {
+    pub fn new(value: f64, unit: Unit) -> Self {
+        Length { value, unit }
+    }
+
+    pub fn millimeters(value: f64) -> Self {
+        Length::new(value, Unit::Millimeters)
+    }
+
+    pub fn inches(value: f64) -> Self {
+        Length::new(value, Unit::Inches)
+    }
+
+    /// Thousandths of an inch.
+    pub fn mils(value: f64) -> Self {
+        Length::inches(value / 1000.0)
+    }
+
+    /// The same length in `unit`.
+    pub fn to(self, unit: Unit) -> Length {
+        let value = match (self.unit, unit) {
+            (Unit::Inches, Unit::Millimeters) => self.value * 25.4,
+            (Unit::Millimeters, Unit::Inches) => self.value / 25.4,
+            _ => self.value,
+        };
+        Length::new(value, unit)
+    }
+}
 
 // ApertureDefinition
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ApertureDefinition {
     pub code: i32,
     pub aperture: Aperture,
@@ -33,6 +77,13 @@ impl ApertureDefinition {
     pub fn new(code: i32, aperture: Aperture) -> Self {
         ApertureDefinition { code, aperture }
     }
+
+    /// Like `new`, but returns an error if the code is reserved, i.e. below
+    /// [`MIN_APERTURE_CODE`](crate::MIN_APERTURE_CODE).
+    pub fn try_new(code: i32, aperture: Aperture) -> GerberResult<Self> {
+        check_aperture_code(code)?;
+        Ok(Self::new(code, aperture))
+    }
 }
 
 impl<W: Write> PartialGerberCode<W> for ApertureDefinition {
@@ -46,6 +97,7 @@ impl<W: Write> PartialGerberCode<W> for ApertureDefinition {
 // Aperture
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Aperture {
     Circle(Circle),
     Rectangle(Rectangular),
@@ -98,9 +150,103 @@ impl<W: Write> PartialGerberCode<W> for Aperture {
     }
 }
 
+impl Aperture {
+    /// An obround (`O`), a rectangle of `x` by `y` with semicircular ends on its shorter sides,
+    /// i.e. a stadium shape whose end diameter is the smaller of `x` and `y`.
+    ///
+    /// With `x` equal to `y` it is a circle, use [`Aperture::Circle`] instead since some
+    /// fabricators flag such obrounds.
+    pub fn obround(x: f64, y: f64) -> Aperture {
+        Aperture::Obround(Rectangular::new(x, y))
+    }
+
+    /// A copy of the aperture with all dimensions (diameters, sizes and hole diameters)
+    /// multiplied by `factor`.
+    ///
+    /// The rotation and number of vertices of a polygon are not changed. Macro apertures can not
+    /// be scaled, the meaning of their arguments is defined by the macro.
+    pub fn scaled(&self, factor: f64) -> GerberResult<Aperture> {
+        let hole = |hole_diameter: Option<f64>| hole_diameter.map(|hole| hole * factor);
+        Ok(match self {
+            Aperture::Circle(circle) => Aperture::Circle(Circle {
+                diameter: circle.diameter * factor,
+                hole_diameter: hole(circle.hole_diameter),
+            }),
+            Aperture::Rectangle(rectangular) => Aperture::Rectangle(Rectangular {
+                x: rectangular.x * factor,
+                y: rectangular.y * factor,
+                hole_diameter: hole(rectangular.hole_diameter),
+            }),
+            Aperture::Obround(rectangular) => Aperture::Obround(Rectangular {
+                x: rectangular.x * factor,
+                y: rectangular.y * factor,
+                hole_diameter: hole(rectangular.hole_diameter),
+            }),
+            Aperture::Polygon(polygon) => Aperture::Polygon(Polygon {
+                diameter: polygon.diameter * factor,
+                hole_diameter: hole(polygon.hole_diameter),
+                ..polygon.clone()
+            }),
+            Aperture::Macro(name, _) => {
+                return Err(GerberError::ValidationError(format!(
+                    "Macro aperture {} can not be scaled",
+                    name
+                )))
+            }
+        })
+    }
+
+    /// A copy of the aperture grown by `delta` on every side, i.e. `2 * delta` is added to the
+    /// diameter or to the width and height. Hole diameters are not changed.
+    ///
+    /// A negative `delta` shrinks the aperture, it is an error if that makes a dimension
+    /// negative. For a polygon the circumscribed diameter is grown, which is an approximation of
+    /// the offset shape. Macro apertures can not be grown.
+    pub fn grown(&self, delta: f64) -> GerberResult<Aperture> {
+        let grow = |dimension: f64| {
+            let grown = dimension + 2.0 * delta;
+            if grown < 0.0 {
+                Err(GerberError::RangeError(format!(
+                    "Growing {} by {} results in a negative dimension",
+                    dimension, delta
+                )))
+            } else {
+                Ok(grown)
+            }
+        };
+        Ok(match self {
+            Aperture::Circle(circle) => Aperture::Circle(Circle {
+                diameter: grow(circle.diameter)?,
+                ..circle.clone()
+            }),
+            Aperture::Rectangle(rectangular) => Aperture::Rectangle(Rectangular {
+                x: grow(rectangular.x)?,
+                y: grow(rectangular.y)?,
+                ..rectangular.clone()
+            }),
+            Aperture::Obround(rectangular) => Aperture::Obround(Rectangular {
+                x: grow(rectangular.x)?,
+                y: grow(rectangular.y)?,
+                ..rectangular.clone()
+            }),
+            Aperture::Polygon(polygon) => Aperture::Polygon(Polygon {
+                diameter: grow(polygon.diameter)?,
+                ..polygon.clone()
+            }),
+            Aperture::Macro(name, _) => {
+                return Err(GerberError::ValidationError(format!(
+                    "Macro aperture {} can not be grown",
+                    name
+                )))
+            }
+        })
+    }
+}
+
 // Circle
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Circle {
     pub diameter: f64,
     pub hole_diameter: Option<f64>,
@@ -120,6 +266,19 @@ impl Circle {
             hole_diameter: Some(hole_diameter),
         }
     }
+
+    /// Useful when the hole is shared between apertures and may or may not be present.
+    pub fn with_optional_hole(diameter: f64, hole_diameter: Option<f64>) -> Self {
+        Circle {
+            diameter,
+            hole_diameter,
+        }
+    }
+
+    /// A circle with the `diameter` converted to the `unit` of the file.
+    pub fn from_length(diameter: Length, unit: Unit) -> Self {
+        Circle::new(diameter.to(unit).value)
+    }
 }
 
 impl<W: Write> PartialGerberCode<W> for Circle {
@@ -137,6 +296,7 @@ impl<W: Write> PartialGerberCode<W> for Circle {
 // Rectangular
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Rectangular {
     pub x: f64,
     pub y: f64,
@@ -159,6 +319,46 @@ impl Rectangular {
             hole_diameter: Some(hole_diameter),
         }
     }
+
+    /// Useful when the hole is shared between apertures and may or may not be present.
+    pub fn with_optional_hole(x: f64, y: f64, hole_diameter: Option<f64>) -> Self {
+        Rectangular {
+            x,
+            y,
+            hole_diameter,
+        }
+    }
+
+    /// A rectangle with the sizes converted to the `unit` of the file.
+    pub fn from_lengths(x: Length, y: Length, unit: Unit) -> Self {
+        Rectangular::new(x.to(unit).value, y.to(unit).value)
+    }
+
+    /// Checks that the sizes are positive and that a hole is smaller than both of them.
+    pub fn validate(&self) -> GerberResult<()> {
+        if !self.x.is_finite() || !self.y.is_finite() || self.x <= 0.0 || self.y <= 0.0 {
+            return Err(GerberError::RangeError(format!(
+                "The size of a rectangle or obround must be positive, got {}X{}",
+                self.x, self.y
+            )));
+        }
+        if let Some(hole_diameter) = self.hole_diameter {
+            if !hole_diameter.is_finite()
+                || hole_diameter < 0.0
+                || hole_diameter >= self.x.min(self.y)
+            {
+                return Err(GerberError::RangeError(format!(
+                    "The hole diameter of a {}X{} rectangle or obround must be at least 0 and less \
+                     than {}, got {}",
+                    self.x,
+                    self.y,
+                    self.x.min(self.y),
+                    hole_diameter
+                )));
+            }
+        }
+        Ok(())
+    }
 }
 
 impl<W: Write> PartialGerberCode<W> for Rectangular {
@@ -174,6 +374,7 @@ impl<W: Write> PartialGerberCode<W> for Rectangular {
 // Polygon
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Polygon {
     pub diameter: f64,
     pub vertices: u8, // 3--12
@@ -191,6 +392,35 @@ impl Polygon {
         }
     }
 
+    /// A polygon with the `diameter` converted to the `unit` of the file.
+    pub fn from_length(diameter: Length, vertices: u8, unit: Unit) -> Self {
+        Polygon::new(diameter.to(unit).value, vertices)
+    }
+
+    /// Like `new`, but returns an error if the polygon is invalid, see [`Polygon::validate`].
+    pub fn try_new(diameter: f64, vertices: u8) -> GerberResult<Self> {
+        let polygon = Self::new(diameter, vertices);
+        polygon.validate()?;
+        Ok(polygon)
+    }
+
+    /// Checks that the polygon has 3 to 12 vertices and a positive diameter.
+    pub fn validate(&self) -> GerberResult<()> {
+        if !(3..=12).contains(&self.vertices) {
+            return Err(GerberError::RangeError(format!(
+                "The number of vertices of a polygon must be 3 to 12, got {}",
+                self.vertices
+            )));
+        }
+        if !self.diameter.is_finite() || self.diameter <= 0.0 {
+            return Err(GerberError::RangeError(format!(
+                "The diameter of a polygon must be positive, got {}",
+                self.diameter
+            )));
+        }
+        Ok(())
+    }
+
     pub fn with_rotation(mut self, angle: f64) -> Self {
         self.rotation = Some(angle);
         self
@@ -200,6 +430,12 @@ impl Polygon {
         self.diameter = diameter;
         self
     }
+
+    /// Useful when the hole is shared between apertures and may or may not be present.
+    pub fn with_optional_hole(mut self, hole_diameter: Option<f64>) -> Self {
+        self.hole_diameter = hole_diameter;
+        self
+    }
 }
 
 impl<W: Write> PartialGerberCode<W> for Polygon {
@@ -219,6 +455,7 @@ impl<W: Write> PartialGerberCode<W> for Polygon {
 // Polarity
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, IntoStaticStr, VariantNames, VariantArray)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[strum(serialize_all = "UPPERCASE")]
 pub enum Polarity {
     #[strum(serialize = "C")]
@@ -228,10 +465,12 @@ pub enum Polarity {
 }
 
 impl_partial_gerber_code_via_strum!(Polarity);
+impl_from_str_via_strum!(Polarity);
 
 // Mirroring
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, IntoStaticStr, VariantNames, VariantArray)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[strum(serialize_all = "UPPERCASE")]
 pub enum Mirroring {
     #[strum(serialize = "N")]
@@ -242,10 +481,12 @@ pub enum Mirroring {
 }
 
 impl_partial_gerber_code_via_strum!(Mirroring);
+impl_from_str_via_strum!(Mirroring);
 
 // Scaling
 
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Scaling {
     pub scale: f64,
 }
@@ -260,6 +501,7 @@ impl<W: Write> PartialGerberCode<W> for Scaling {
 // Rotation
 
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Rotation {
     /// in degrees, counter-clockwise
     pub rotation: f64,
@@ -272,9 +514,59 @@ impl<W: Write> PartialGerberCode<W> for Rotation {
     }
 }
 
+// GraphicsState
+
+/// The object transformation parameters, set with `%LP`, `%LM`, `%LR` and `%LS`.
+///
+/// The default is the state at the start of a file: dark polarity, no mirroring, no rotation
+/// and a scale of 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GraphicsState {
+    pub polarity: Polarity,
+    pub mirroring: Mirroring,
+    pub rotation: Rotation,
+    pub scaling: Scaling,
+}
+
+impl Default for GraphicsState {
+    fn default() -> Self {
+        GraphicsState {
+            polarity: Polarity::Dark,
+            mirroring: Mirroring::None,
+            rotation: Rotation { rotation: 0.0 },
+            scaling: Scaling { scale: 1.0 },
+        }
+    }
+}
+
+impl GraphicsState {
+    /// The commands that change the state from `self` to `target`, in the order LP, LM, LR, LS.
+    ///
+    /// Parameters that are the same in both states are skipped, so the result is empty if the
+    /// states are equal.
+    pub fn transition_to(&self, target: &GraphicsState) -> Vec<ExtendedCode> {
+        let mut codes = Vec::new();
+        if self.polarity != target.polarity {
+            codes.push(ExtendedCode::LoadPolarity(target.polarity));
+        }
+        if self.mirroring != target.mirroring {
+            codes.push(ExtendedCode::LoadMirroring(target.mirroring));
+        }
+        if self.rotation != target.rotation {
+            codes.push(ExtendedCode::LoadRotation(target.rotation));
+        }
+        if self.scaling != target.scaling {
+            codes.push(ExtendedCode::LoadScaling(target.scaling));
+        }
+        codes
+    }
+}
+
 // StepAndRepeat
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum StepAndRepeat {
     Open {
         repeat_x: u32,
@@ -303,6 +595,7 @@ impl<W: Write> PartialGerberCode<W> for StepAndRepeat {
 // ApertureBlock
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ApertureBlock {
     Open { code: i32 },
     Close,
@@ -318,10 +611,111 @@ impl<W: Write> PartialGerberCode<W> for ApertureBlock {
     }
 }
 
-#[cfg(test)]
+/// A block aperture with its content, serialized as `%AB<code>*%`, the commands and `%AB*%`.
+///
+/// See [`make_aperture_block`](crate::make_aperture_block) for a constructor that checks the
+/// content. Blocks can be nested by adding the inner block to the commands of the outer one,
+/// either as a [`Command`] or as its [`into_commands`](Self::into_commands).
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ApertureBlockDefinition {
+    pub code: i32,
+    pub commands: Vec<Command>,
+}
+
+impl ApertureBlockDefinition {
+    /// The block as a flat list of commands, starting with `%AB<code>*%` and ending with
+    /// `%AB*%`. Nested blocks are flattened as well.
+    pub fn into_commands(self) -> Vec<Command> {
+        let mut commands = Vec::with_capacity(self.commands.len() + 2);
+        commands.push(ExtendedCode::ApertureBlock(ApertureBlock::Open { code: self.code }).into());
+        for command in self.commands {
+            match command {
+                Command::ExtendedCode(ExtendedCode::ApertureBlockDefinition(block)) => {
+                    commands.extend(block.into_commands())
+                }
+                command => commands.push(command),
+            }
+        }
+        commands.push(ExtendedCode::ApertureBlock(ApertureBlock::Close).into());
+        commands
+    }
+}
+
+impl<W: Write> GerberCode<W> for ApertureBlockDefinition {
+    fn serialize(&self, writer: &mut W) -> GerberResult<()> {
+        ExtendedCode::ApertureBlock(ApertureBlock::Open { code: self.code }).serialize(writer)?;
+        self.commands.serialize(writer)?;
+        ExtendedCode::ApertureBlock(ApertureBlock::Close).serialize(writer)?;
+        Ok(())
+    }
+
+    fn serialize_with(&self, writer: &mut W, options: &SerializationOptions) -> GerberResult<()> {
+        ExtendedCode::ApertureBlock(ApertureBlock::Open { code: self.code })
+            .serialize_with(writer, options)?;
+        self.commands.serialize_with(writer, options)?;
+        ExtendedCode::ApertureBlock(ApertureBlock::Close).serialize_with(writer, options)?;
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
 mod test {
     use super::*;
 
+    use std::convert::TryFrom;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!(Unit::from_str("MM").unwrap(), Unit::Millimeters);
+        assert_eq!("IN".parse::<Unit>().unwrap(), Unit::Inches);
+        assert_eq!("D".parse::<Polarity>().unwrap(), Polarity::Dark);
+        assert_eq!("N".parse::<Mirroring>().unwrap(), Mirroring::None);
+        assert_eq!("XY".parse::<Mirroring>().unwrap(), Mirroring::XY);
+        assert_eq!(
+            "270".parse::<ImageRotation>().unwrap(),
+            ImageRotation::CCW_270
+        );
+
+        let error = "mm".parse::<Unit>().unwrap_err();
+        assert!(matches!(error, GerberError::ConversionError(_)));
+        assert_eq!(
+            error.to_string(),
+            "Conversion between two types failed: Unknown Unit 'mm', expected one of: IN, MM"
+        );
+        assert!("Dark".parse::<Polarity>().is_err());
+    }
+
+    #[test]
+    fn test_try_from_str() {
+        assert_eq!(Polarity::try_from("D").unwrap(), Polarity::Dark);
+        assert_eq!(Polarity::try_from("C").unwrap(), Polarity::Clear);
+        assert_eq!(Mirroring::try_from("XY").unwrap(), Mirroring::XY);
+        assert_eq!(Mirroring::try_from("Y").unwrap(), Mirroring::Y);
+        assert!(matches!(
+            Polarity::try_from("X"),
+            Err(GerberError::ConversionError(_))
+        ));
+        assert!(Mirroring::try_from("xy").is_err());
+        assert!(Mirroring::try_from("").is_err());
+    }
+
+    #[test]
+    fn test_aperture_definition_try_new() {
+        let circle = Aperture::Circle(Circle::new(0.5));
+        assert_eq!(
+            ApertureDefinition::try_new(10, circle.clone()).unwrap(),
+            ApertureDefinition::new(10, circle.clone())
+        );
+        let error = ApertureDefinition::try_new(9, circle.clone()).unwrap_err();
+        assert!(matches!(error, GerberError::RangeError(_)));
+        assert!(error
+            .to_string()
+            .contains("Aperture codes below 10 are reserved, got 9"));
+        assert!(ApertureDefinition::try_new(-1, circle).is_err());
+    }
+
     #[test]
     fn test_aperture_definition_new() {
         let ad1 = ApertureDefinition::new(10, Aperture::Circle(Circle::new(3.0)));
@@ -374,6 +768,105 @@ mod test {
         assert_eq!(c1, c2);
     }
 
+    #[test]
+    fn test_circle_with_optional_hole() {
+        assert_eq!(Circle::with_optional_hole(3.0, None), Circle::new(3.0));
+        assert_eq!(
+            Circle::with_optional_hole(3.0, Some(1.0)),
+            Circle::with_hole(3.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn test_rectangular_with_optional_hole() {
+        assert_eq!(
+            Rectangular::with_optional_hole(3.0, 2.0, None),
+            Rectangular::new(3.0, 2.0)
+        );
+        assert_eq!(
+            Rectangular::with_optional_hole(3.0, 2.0, Some(1.0)),
+            Rectangular::with_hole(3.0, 2.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn test_polygon_with_optional_hole() {
+        let p1 = Polygon::new(3.0, 4).with_optional_hole(Some(1.0));
+        let p2 = Polygon {
+            diameter: 3.0,
+            vertices: 4,
+            rotation: None,
+            hole_diameter: Some(1.0),
+        };
+        assert_eq!(p1, p2);
+        assert_eq!(
+            Polygon::new(3.0, 4).with_optional_hole(None),
+            Polygon::new(3.0, 4)
+        );
+    }
+
+    #[test]
+    fn test_polygon_validate() {
+        assert!(Polygon::new(1.0, 3).validate().is_ok());
+        assert!(Polygon::new(1.0, 12).validate().is_ok());
+        assert!(matches!(
+            Polygon::new(1.0, 2).validate(),
+            Err(GerberError::RangeError(_))
+        ));
+        assert!(Polygon::new(1.0, 13).validate().is_err());
+        assert!(Polygon::new(0.0, 5).validate().is_err());
+        assert!(Polygon::new(f64::NAN, 5).validate().is_err());
+
+        assert_eq!(Polygon::try_new(5.0, 6).unwrap(), Polygon::new(5.0, 6));
+        assert!(Polygon::try_new(5.0, 2).is_err());
+    }
+
+    #[test]
+    fn test_rectangular_validate() {
+        assert!(Rectangular::new(1.0, 0.5).validate().is_ok());
+        assert!(Rectangular::with_hole(1.0, 0.5, 0.4).validate().is_ok());
+        assert!(matches!(
+            Rectangular::new(0.0, 0.5).validate(),
+            Err(GerberError::RangeError(_))
+        ));
+        assert!(Rectangular::new(1.0, -0.5).validate().is_err());
+        assert!(Rectangular::new(f64::INFINITY, 0.5).validate().is_err());
+        assert!(Rectangular::with_hole(1.0, 0.5, 0.5).validate().is_err());
+        assert!(Rectangular::with_hole(1.0, 0.5, -0.1).validate().is_err());
+
+        assert_eq!(
+            Aperture::obround(2.0, 1.0),
+            Aperture::Obround(Rectangular::new(2.0, 1.0))
+        );
+    }
+
+    #[test]
+    fn test_length() {
+        let mils = Length::mils(10.0);
+        assert_eq!(mils, Length::inches(0.01));
+        assert!((mils.to(Unit::Millimeters).value - 0.254).abs() < 1e-12);
+        assert_eq!(mils.to(Unit::Millimeters).unit, Unit::Millimeters);
+        assert_eq!(mils.to(Unit::Inches), mils);
+        assert_eq!(Length::millimeters(25.4).to(Unit::Inches).value, 1.0);
+
+        assert_eq!(
+            Circle::from_length(Length::inches(1.0), Unit::Millimeters),
+            Circle::new(25.4)
+        );
+        assert_eq!(
+            Rectangular::from_lengths(
+                Length::millimeters(1.0),
+                Length::inches(1.0),
+                Unit::Millimeters
+            ),
+            Rectangular::new(1.0, 25.4)
+        );
+        assert_eq!(
+            Polygon::from_length(Length::millimeters(2.0), 6, Unit::Millimeters),
+            Polygon::new(2.0, 6)
+        );
+    }
+
     #[test]
     fn test_polygon_new() {
         let p1 = Polygon::new(3.0, 4).with_rotation(45.0);
@@ -386,6 +879,58 @@ mod test {
         assert_eq!(p1, p2);
     }
 
+    #[test]
+    fn test_aperture_scaled() {
+        let circle = Aperture::Circle(Circle::new(2.0));
+        assert_eq!(
+            circle.scaled(1.5).unwrap(),
+            Aperture::Circle(Circle::new(3.0))
+        );
+
+        let rectangle = Aperture::Rectangle(Rectangular::with_hole(1.0, 2.0, 0.5));
+        assert_eq!(
+            rectangle.scaled(2.0).unwrap(),
+            Aperture::Rectangle(Rectangular::with_hole(2.0, 4.0, 1.0))
+        );
+
+        let polygon = Aperture::Polygon(Polygon::new(2.0, 6).with_rotation(30.0));
+        assert_eq!(
+            polygon.scaled(0.5).unwrap(),
+            Aperture::Polygon(Polygon::new(1.0, 6).with_rotation(30.0))
+        );
+
+        let with_macro = Aperture::Macro("M".to_string(), Some(vec![MacroDecimal::Value(1.0)]));
+        assert!(matches!(
+            with_macro.scaled(2.0),
+            Err(GerberError::ValidationError(_))
+        ));
+    }
+
+    #[test]
+    fn test_aperture_grown() {
+        let rectangle = Aperture::Rectangle(Rectangular::new(1.0, 1.0));
+        assert_eq!(
+            rectangle.grown(0.5).unwrap(),
+            Aperture::Rectangle(Rectangular::new(2.0, 2.0))
+        );
+
+        let circle = Aperture::Circle(Circle::with_hole(2.0, 0.5));
+        assert_eq!(
+            circle.grown(-0.25).unwrap(),
+            Aperture::Circle(Circle::with_hole(1.5, 0.5))
+        );
+        assert!(matches!(
+            circle.grown(-1.5),
+            Err(GerberError::RangeError(_))
+        ));
+
+        let with_macro = Aperture::Macro("M".to_string(), None);
+        assert!(matches!(
+            with_macro.grown(0.1),
+            Err(GerberError::ValidationError(_))
+        ));
+    }
+
     /// This test is to ensure that the `Unit` enum is hashable.
     #[test]
     fn unit_in_hashmap() {
@@ -395,13 +940,54 @@ mod test {
 
         assert_eq!(map.len(), 2);
     }
+
+    #[test]
+    fn test_graphics_state_transition() {
+        let initial = GraphicsState::default();
+        assert!(initial.transition_to(&initial).is_empty());
+
+        let rotated = GraphicsState {
+            polarity: Polarity::Clear,
+            rotation: Rotation { rotation: 90.0 },
+            ..initial
+        };
+        assert_eq!(
+            initial.transition_to(&rotated),
+            vec![
+                ExtendedCode::LoadPolarity(Polarity::Clear),
+                ExtendedCode::LoadRotation(Rotation { rotation: 90.0 }),
+            ]
+        );
+        assert_eq!(
+            rotated.transition_to(&initial),
+            vec![
+                ExtendedCode::LoadPolarity(Polarity::Dark),
+                ExtendedCode::LoadRotation(Rotation { rotation: 0.0 }),
+            ]
+        );
+
+        let mirrored = GraphicsState {
+            mirroring: Mirroring::XY,
+            scaling: Scaling { scale: 0.5 },
+            ..rotated
+        };
+        assert_eq!(
+            rotated.transition_to(&mirrored),
+            vec![
+                ExtendedCode::LoadMirroring(Mirroring::XY),
+                ExtendedCode::LoadScaling(Scaling { scale: 0.5 }),
+            ]
+        );
+    }
 }
 
 // Image Mirroring
 
 /// Gerber spec 2024.05 8.1.7 "Mirror Image (MI)"
-#[derive(Debug, Clone, Copy, PartialEq, Eq, IntoStaticStr, VariantNames, VariantArray)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, IntoStaticStr, VariantNames, VariantArray)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ImageMirroring {
+    #[default]
     #[strum(serialize = "")]
     None,
     #[strum(serialize = "A1")]
@@ -413,19 +999,16 @@ pub enum ImageMirroring {
 }
 
 impl_partial_gerber_code_via_strum!(ImageMirroring);
-
-impl Default for ImageMirroring {
-    fn default() -> Self {
-        ImageMirroring::None
-    }
-}
+impl_from_str_via_strum!(ImageMirroring);
 
 // Image Rotation
 
 /// Gerber spec 2024.05 8.1.5 "Image Rotation (IR)"
-#[derive(Debug, Clone, Copy, PartialEq, Eq, IntoStaticStr, VariantNames, VariantArray)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, IntoStaticStr, VariantNames, VariantArray)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(non_camel_case_types)]
 pub enum ImageRotation {
+    #[default]
     #[strum(serialize = "0")]
     None,
     #[strum(serialize = "90")]
@@ -437,17 +1020,14 @@ pub enum ImageRotation {
 }
 
 impl_partial_gerber_code_via_strum!(ImageRotation);
+impl_from_str_via_strum!(ImageRotation);
 
-impl Default for ImageRotation {
-    fn default() -> Self {
-        ImageRotation::None
-    }
-}
 // Image Scaling
 
 /// Gerber spec 2024.05 8.1.9 "Scale Factor (SF)"
 /// By default, A=X, B=Y, but this changes depending on the axis select command (AS)
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ImageScaling {
     /// scale factor for A axis
     pub a: f64,
@@ -478,6 +1058,7 @@ impl Default for ImageScaling {
 /// Gerber spec 2024.05 8.1.8 "Offset (OF)"
 /// By default, A=X, B=Y, but this changes depending on the axis select command (AS)
 #[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ImageOffset {
     /// offset for A axis
     pub a: f64,
@@ -499,25 +1080,24 @@ impl<W: Write> PartialGerberCode<W> for ImageOffset {
 
 // Axis Select
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, IntoStaticStr, VariantNames, VariantArray)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, IntoStaticStr, VariantNames, VariantArray)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[strum(serialize_all = "UPPERCASE")]
 pub enum AxisSelect {
+    #[default]
     AXBY,
     AYBX,
 }
 
 impl_partial_gerber_code_via_strum!(AxisSelect);
-
-impl Default for AxisSelect {
-    fn default() -> Self {
-        AxisSelect::AXBY
-    }
-}
+impl_from_str_via_strum!(AxisSelect);
 
 // Image Polarity
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, IntoStaticStr, VariantNames, VariantArray)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, IntoStaticStr, VariantNames, VariantArray)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ImagePolarity {
+    #[default]
     #[strum(serialize = "POS")]
     Positive,
     #[strum(serialize = "NEG")]
@@ -525,16 +1105,12 @@ pub enum ImagePolarity {
 }
 
 impl_partial_gerber_code_via_strum!(ImagePolarity);
-
-impl Default for ImagePolarity {
-    fn default() -> Self {
-        ImagePolarity::Positive
-    }
-}
+impl_from_str_via_strum!(ImagePolarity);
 
 /// Gerber spec 2024.05 8.1.9 "Scale Factor (SF)"
 /// By default, A=X, B=Y, but this changes depending on the axis select command (AS)
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ImageName {
     pub name: String,
 }
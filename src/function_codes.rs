@@ -1,19 +1,31 @@
 //! Function code types.
 
-use crate::coordinates::{CoordinateOffset, Coordinates};
-use crate::errors::GerberResult;
-use crate::traits::{GerberCode, PartialGerberCode};
+use crate::coordinates::{CoordinateFormat, CoordinateNumber, CoordinateOffset, Coordinates};
+use crate::errors::{GerberError, GerberResult};
+use crate::io::Write;
+use crate::prelude::*;
+use crate::traits::{GerberCode, PartialAttributeCode, PartialGerberCode, SerializationOptions};
+use crate::validation::check_aperture_code;
 use crate::{attributes, CoordinateMode, Unit};
-use std::io::Write;
 
 // DCode
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DCode {
     Operation(Operation),
     SelectAperture(i32),
 }
 
+impl DCode {
+    /// Selects the aperture `code`, or returns an error if the code is reserved, i.e. below
+    /// [`MIN_APERTURE_CODE`](crate::MIN_APERTURE_CODE).
+    pub fn try_select_aperture(code: i32) -> GerberResult<Self> {
+        check_aperture_code(code)?;
+        Ok(DCode::SelectAperture(code))
+    }
+}
+
 impl<W: Write> GerberCode<W> for DCode {
     fn serialize(&self, writer: &mut W) -> GerberResult<()> {
         match *self {
@@ -22,14 +34,22 @@ impl<W: Write> GerberCode<W> for DCode {
         };
         Ok(())
     }
+
+    fn serialize_with(&self, writer: &mut W, options: &SerializationOptions) -> GerberResult<()> {
+        match *self {
+            DCode::Operation(ref operation) => operation.serialize_with(writer, options),
+            _ => self.serialize(writer),
+        }
+    }
 }
 
 // GCode
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum GCode {
     InterpolationMode(InterpolationMode),
-    RegionMode(bool),
+    RegionMode(RegionMode),
     QuadrantMode(QuadrantMode),
     Comment(CommentContent),
     /// Deprecated since December 2012, but still in use
@@ -44,13 +64,7 @@ impl<W: Write> GerberCode<W> for GCode {
     fn serialize(&self, writer: &mut W) -> GerberResult<()> {
         match *self {
             GCode::InterpolationMode(ref mode) => mode.serialize(writer)?,
-            GCode::RegionMode(enabled) => {
-                if enabled {
-                    writeln!(writer, "G36*")?;
-                } else {
-                    writeln!(writer, "G37*")?;
-                }
-            }
+            GCode::RegionMode(ref mode) => mode.serialize(writer)?,
             GCode::QuadrantMode(ref mode) => mode.serialize(writer)?,
             GCode::Comment(ref content) => {
                 write!(writer, "G04 ")?;
@@ -75,6 +89,7 @@ impl<W: Write> GerberCode<W> for GCode {
 /// 1) 4.1 - Comment (G04)
 /// 2) 5.1.1 - Comment attributes
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CommentContent {
     String(String),
     /// "Content starting with ”#@!“ is reserved for standard comments. The purpose of standard
@@ -101,6 +116,7 @@ impl<W: Write> PartialGerberCode<W> for CommentContent {
 /// 1) 4.1 - Comment (G04)
 /// 2) 5.1.1 - Comment attributes
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum StandardComment {
     /// TF
     FileAttribute(attributes::FileAttribute),
@@ -112,21 +128,21 @@ pub enum StandardComment {
     DeleteAttribute(attributes::AttributeDeletionCriterion),
 }
 
-impl<W: Write> PartialGerberCode<W> for StandardComment {
-    fn serialize_partial(&self, writer: &mut W) -> GerberResult<()> {
+impl<W: Write> PartialAttributeCode<W> for StandardComment {
+    fn serialize_fields(&self, writer: &mut W, raw: bool) -> GerberResult<()> {
         write!(writer, "#@! ")?;
         match *self {
             StandardComment::FileAttribute(ref fa) => {
                 write!(writer, "TF")?;
-                fa.serialize_partial(writer)?;
+                fa.serialize_fields(writer, raw)?;
             }
             StandardComment::ObjectAttribute(ref oa) => {
                 write!(writer, "TO")?;
-                oa.serialize_partial(writer)?;
+                oa.serialize_fields(writer, raw)?;
             }
             StandardComment::ApertureAttribute(ref aa) => {
                 write!(writer, "TA")?;
-                aa.serialize_partial(writer)?;
+                aa.serialize_fields(writer, raw)?;
             }
             StandardComment::DeleteAttribute(ref adc) => {
                 write!(writer, "TD")?;
@@ -137,9 +153,12 @@ impl<W: Write> PartialGerberCode<W> for StandardComment {
     }
 }
 
+impl_partial_gerber_code_via_fields!(StandardComment);
+
 // MCode
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum MCode {
     EndOfFile,
 }
@@ -156,6 +175,7 @@ impl<W: Write> GerberCode<W> for MCode {
 // Operation
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Operation {
     /// D01 Command
     /// `D01 = ['X' integer] ['Y' integer] ['I' integer 'J' integer] 'D01*';`
@@ -170,18 +190,30 @@ pub enum Operation {
 
 impl<W: Write> GerberCode<W> for Operation {
     fn serialize(&self, writer: &mut W) -> GerberResult<()> {
+        self.serialize_with(writer, &SerializationOptions::default())
+    }
+
+    fn serialize_with(&self, writer: &mut W, options: &SerializationOptions) -> GerberResult<()> {
         match *self {
             Operation::Interpolate(ref coords, ref offset) => {
-                coords.serialize_partial(writer)?;
-                offset.serialize_partial(writer)?;
+                if let Some(coords) = coords {
+                    coords.serialize_partial_with(writer, options)?;
+                }
+                if let Some(offset) = offset {
+                    offset.serialize_partial_with(writer, options)?;
+                }
                 writeln!(writer, "D01*")?;
             }
             Operation::Move(ref coords) => {
-                coords.serialize_partial(writer)?;
+                if let Some(coords) = coords {
+                    coords.serialize_partial_with(writer, options)?;
+                }
                 writeln!(writer, "D02*")?;
             }
             Operation::Flash(ref coords) => {
-                coords.serialize_partial(writer)?;
+                if let Some(coords) = coords {
+                    coords.serialize_partial_with(writer, options)?;
+                }
                 writeln!(writer, "D03*")?;
             }
         };
@@ -189,9 +221,78 @@ impl<W: Write> GerberCode<W> for Operation {
     }
 }
 
+impl Operation {
+    /// Parses an operation (D01, D02 or D03) with its coordinates, e.g. `X100Y200D01*`.
+    ///
+    /// The coordinate numbers are decoded with the given format. The `*` at the end is optional.
+    pub fn parse(line: &str, format: &CoordinateFormat) -> GerberResult<Operation> {
+        let line = line.trim();
+        let line = line.strip_suffix('*').unwrap_or(line);
+        let invalid = || GerberError::ConversionError(format!("Invalid operation: '{}'", line));
+
+        let mut x = None;
+        let mut y = None;
+        let mut i = None;
+        let mut j = None;
+        let mut d = None;
+        let mut rest = line;
+        while let Some(letter) = rest.chars().next() {
+            if d.is_some() || !letter.is_ascii() {
+                return Err(invalid());
+            }
+            let value_len = rest[1..]
+                .find(|c: char| c.is_ascii_alphabetic())
+                .unwrap_or(rest.len() - 1);
+            let value = &rest[1..1 + value_len];
+            rest = &rest[1 + value_len..];
+            let target = match letter {
+                'X' => &mut x,
+                'Y' => &mut y,
+                'I' => &mut i,
+                'J' => &mut j,
+                'D' => {
+                    d = Some(value.parse::<u8>().map_err(|_| invalid())?);
+                    continue;
+                }
+                _ => return Err(invalid()),
+            };
+            if target.is_some() {
+                return Err(invalid());
+            }
+            *target = Some(CoordinateNumber::parse(value, format)?);
+        }
+
+        let coordinates = if x.is_some() || y.is_some() {
+            Some(Coordinates {
+                x,
+                y,
+                format: *format,
+            })
+        } else {
+            None
+        };
+        let offset = if i.is_some() || j.is_some() {
+            Some(CoordinateOffset {
+                x: i,
+                y: j,
+                format: *format,
+            })
+        } else {
+            None
+        };
+        match d {
+            Some(1) => Ok(Operation::Interpolate(coordinates, offset)),
+            Some(2) if offset.is_none() => Ok(Operation::Move(coordinates)),
+            Some(3) if offset.is_none() => Ok(Operation::Flash(coordinates)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
 // InterpolationMode
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum InterpolationMode {
     Linear,
     ClockwiseCircular,
@@ -209,9 +310,47 @@ impl<W: Write> GerberCode<W> for InterpolationMode {
     }
 }
 
+// RegionMode
+
+/// Region mode, turned on with G36 and off with G37.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RegionMode {
+    On,
+    Off,
+}
+
+impl RegionMode {
+    pub fn is_on(&self) -> bool {
+        *self == RegionMode::On
+    }
+}
+
+/// `true` is `On`, for code that used the former `bool` of `GCode::RegionMode`.
+impl From<bool> for RegionMode {
+    fn from(enabled: bool) -> Self {
+        if enabled {
+            RegionMode::On
+        } else {
+            RegionMode::Off
+        }
+    }
+}
+
+impl<W: Write> GerberCode<W> for RegionMode {
+    fn serialize(&self, writer: &mut W) -> GerberResult<()> {
+        match *self {
+            RegionMode::On => writeln!(writer, "G36*")?,
+            RegionMode::Off => writeln!(writer, "G37*")?,
+        };
+        Ok(())
+    }
+}
+
 // QuadrantMode
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum QuadrantMode {
     Single,
     Multi,
@@ -227,5 +366,66 @@ impl<W: Write> GerberCode<W> for QuadrantMode {
     }
 }
 
-#[cfg(test)]
-mod test {}
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+
+    use std::convert::TryFrom;
+
+    use crate::ZeroOmission;
+
+    #[test]
+    fn test_region_mode_from_bool() {
+        assert_eq!(RegionMode::from(true), RegionMode::On);
+        assert_eq!(RegionMode::from(false), RegionMode::Off);
+        assert_eq!(
+            GCode::RegionMode(true.into()),
+            GCode::RegionMode(RegionMode::On)
+        );
+        assert!(RegionMode::On.is_on());
+        assert!(!RegionMode::Off.is_on());
+    }
+
+    #[test]
+    fn test_try_select_aperture() {
+        assert_eq!(
+            DCode::try_select_aperture(10).unwrap(),
+            DCode::SelectAperture(10)
+        );
+        assert!(matches!(
+            DCode::try_select_aperture(3),
+            Err(GerberError::RangeError(_))
+        ));
+        assert!(DCode::try_select_aperture(0).is_err());
+    }
+
+    #[test]
+    fn test_parse_operation() {
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 5);
+        assert_eq!(
+            Operation::parse("X100000Y200000I500000J1000000D01*", &cf).unwrap(),
+            Operation::Interpolate(
+                Some(Coordinates::new(1, 2, cf)),
+                Some(CoordinateOffset::new(5, 10, cf))
+            )
+        );
+        assert_eq!(
+            Operation::parse("Y-50000D02*", &cf).unwrap(),
+            Operation::Move(Some(Coordinates::at_y(
+                CoordinateNumber::try_from(-0.5).unwrap(),
+                cf
+            )))
+        );
+        assert_eq!(
+            Operation::parse("D03*", &cf).unwrap(),
+            Operation::Flash(None)
+        );
+
+        assert!(Operation::parse("X100I100D02*", &cf).is_err());
+        assert!(Operation::parse("X100D04*", &cf).is_err());
+        assert!(Operation::parse("X100", &cf).is_err());
+        assert!(Operation::parse("D01X100*", &cf).is_err());
+        assert!(Operation::parse("X100X200D01*", &cf).is_err());
+        assert!(Operation::parse("ÄX100D01*", &cf).is_err());
+    }
+}
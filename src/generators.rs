@@ -0,0 +1,1872 @@
+//! Helpers that generate command sequences for common constructs.
+//!
+//! The generated commands are plain `Command` values, they can be inspected,
+//! modified and combined with other commands before serialization.
+
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+
+use crate::attributes::{
+    ApertureAttribute, ApertureFunction, AttributeDeletionCriterion, FileAttribute, FileFunction,
+    Net, ObjectAttribute, Part, Pin, Position, Profile, TextMirroring, TextMode,
+};
+use crate::coordinates::{CoordinateFormat, CoordinateNumber, CoordinateOffset, Coordinates};
+use crate::errors::{GerberError, GerberResult, LocatedError};
+use crate::extended_codes::{
+    Aperture, ApertureBlock, ApertureBlockDefinition, ApertureDefinition, Circle, Polygon,
+    Rectangular, StepAndRepeat, Unit,
+};
+use crate::function_codes::{
+    CommentContent, DCode, GCode, InterpolationMode, MCode, Operation, QuadrantMode, RegionMode,
+};
+use crate::macros::ApertureMacro;
+use crate::types::{Command, ExtendedCode, FunctionCode};
+use crate::validation::check_balanced_blocks;
+
+pub(crate) fn coordinates(x: f64, y: f64, format: CoordinateFormat) -> GerberResult<Coordinates> {
+    Ok(Coordinates::new(
+        CoordinateNumber::try_from(x)?,
+        CoordinateNumber::try_from(y)?,
+        format,
+    ))
+}
+
+fn offset(x: f64, y: f64, format: CoordinateFormat) -> GerberResult<CoordinateOffset> {
+    Ok(CoordinateOffset::new(
+        CoordinateNumber::try_from(x)?,
+        CoordinateNumber::try_from(y)?,
+        format,
+    ))
+}
+
+/// Draws a full circle, centered at `cx`,`cy`, with the currently selected aperture.
+///
+/// A single 360° arc is ambiguous, so the circle is drawn as two counter-clockwise semicircles
+/// in multi-quadrant mode, starting and ending at the right-most point of the circle.
+///
+/// Note: the interpolation mode is left as counter-clockwise circular and the quadrant mode is
+/// left as multi-quadrant.
+pub fn draw_circle(
+    cx: f64,
+    cy: f64,
+    radius: f64,
+    format: CoordinateFormat,
+) -> GerberResult<Vec<Command>> {
+    if radius.is_nan() || radius <= 0.0 {
+        return Err(GerberError::RangeError(
+            "The radius of a circle must be positive".into(),
+        ));
+    }
+
+    Ok(vec![
+        GCode::QuadrantMode(QuadrantMode::Multi).into(),
+        DCode::Operation(Operation::Move(Some(coordinates(cx + radius, cy, format)?))).into(),
+        GCode::InterpolationMode(InterpolationMode::CounterclockwiseCircular).into(),
+        DCode::Operation(Operation::Interpolate(
+            Some(coordinates(cx - radius, cy, format)?),
+            Some(offset(-radius, 0.0, format)?),
+        ))
+        .into(),
+        DCode::Operation(Operation::Interpolate(
+            Some(coordinates(cx + radius, cy, format)?),
+            Some(offset(radius, 0.0, format)?),
+        ))
+        .into(),
+    ])
+}
+
+/// The arc parameters of a [`Segment`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SegmentArc {
+    pub center: (f64, f64),
+    pub clockwise: bool,
+}
+
+/// A line or arc, drawn with an aperture.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Segment {
+    pub aperture: i32,
+    pub start: (f64, f64),
+    pub end: (f64, f64),
+    /// `None` for a straight line.
+    pub arc: Option<SegmentArc>,
+}
+
+impl Segment {
+    pub fn line(aperture: i32, start: (f64, f64), end: (f64, f64)) -> Self {
+        Segment {
+            aperture,
+            start,
+            end,
+            arc: None,
+        }
+    }
+
+    pub fn arc(
+        aperture: i32,
+        start: (f64, f64),
+        end: (f64, f64),
+        center: (f64, f64),
+        clockwise: bool,
+    ) -> Self {
+        Segment {
+            aperture,
+            start,
+            end,
+            arc: Some(SegmentArc { center, clockwise }),
+        }
+    }
+}
+
+/// Generates the commands to draw the segments.
+///
+/// The segments are grouped by aperture, in order of first use, so each aperture is selected
+/// once. Within a group the order of the segments is kept, a move is only emitted when a
+/// segment does not start where the previous one ended and the interpolation mode is only
+/// emitted when it changes. Arcs are drawn in multi-quadrant mode.
+///
+/// The apertures must be defined separately.
+pub fn from_segments(segments: &[Segment], format: CoordinateFormat) -> GerberResult<Vec<Command>> {
+    let mut apertures: Vec<i32> = Vec::new();
+    for segment in segments {
+        if !apertures.contains(&segment.aperture) {
+            apertures.push(segment.aperture);
+        }
+    }
+
+    let mut commands: Vec<Command> = Vec::new();
+    if segments.iter().any(|segment| segment.arc.is_some()) {
+        commands.push(GCode::QuadrantMode(QuadrantMode::Multi).into());
+    }
+
+    let mut mode: Option<InterpolationMode> = None;
+    let mut position: Option<(f64, f64)> = None;
+    for aperture in apertures {
+        commands.push(DCode::SelectAperture(aperture).into());
+        for segment in segments
+            .iter()
+            .filter(|segment| segment.aperture == aperture)
+        {
+            if position != Some(segment.start) {
+                commands.push(
+                    DCode::Operation(Operation::Move(Some(coordinates(
+                        segment.start.0,
+                        segment.start.1,
+                        format,
+                    )?)))
+                    .into(),
+                );
+            }
+
+            let segment_mode = match segment.arc {
+                None => InterpolationMode::Linear,
+                Some(SegmentArc {
+                    clockwise: true, ..
+                }) => InterpolationMode::ClockwiseCircular,
+                Some(SegmentArc {
+                    clockwise: false, ..
+                }) => InterpolationMode::CounterclockwiseCircular,
+            };
+            if mode != Some(segment_mode) {
+                commands.push(GCode::InterpolationMode(segment_mode).into());
+                mode = Some(segment_mode);
+            }
+
+            let center_offset = match segment.arc {
+                Some(arc) => Some(offset(
+                    arc.center.0 - segment.start.0,
+                    arc.center.1 - segment.start.1,
+                    format,
+                )?),
+                None => None,
+            };
+            commands.push(
+                DCode::Operation(Operation::Interpolate(
+                    Some(coordinates(segment.end.0, segment.end.1, format)?),
+                    center_offset,
+                ))
+                .into(),
+            );
+            position = Some(segment.end);
+        }
+    }
+    Ok(commands)
+}
+
+/// The commands to draw a region (G36/G37) with the given contour.
+///
+/// The contour is closed if the last point differs from the first.
+fn region(points: &[(f64, f64)], format: CoordinateFormat) -> GerberResult<Vec<Command>> {
+    if points.len() < 3 {
+        return Err(GerberError::MissingDataError(
+            "A region contour needs at least 3 points".into(),
+        ));
+    }
+    let mut commands = vec![
+        GCode::RegionMode(RegionMode::On).into(),
+        DCode::Operation(Operation::Move(Some(coordinates(
+            points[0].0,
+            points[0].1,
+            format,
+        )?)))
+        .into(),
+        GCode::InterpolationMode(InterpolationMode::Linear).into(),
+    ];
+    let closing = if points[0] != points[points.len() - 1] {
+        Some(points[0])
+    } else {
+        None
+    };
+    for (x, y) in points.iter().skip(1).copied().chain(closing) {
+        commands.push(
+            DCode::Operation(Operation::Interpolate(
+                Some(coordinates(x, y, format)?),
+                None,
+            ))
+            .into(),
+        );
+    }
+    commands.push(GCode::RegionMode(RegionMode::Off).into());
+    Ok(commands)
+}
+
+/// The file function attribute of a keep-out layer and the keep-out regions.
+///
+/// Each region is given by its contour, which is closed if the last point differs from the
+/// first.
+pub fn keepout_layer(
+    pos: Position,
+    regions: Vec<Vec<(f64, f64)>>,
+    format: CoordinateFormat,
+) -> GerberResult<Vec<Command>> {
+    let mut commands: Vec<Command> =
+        vec![
+            ExtendedCode::FileAttribute(FileAttribute::FileFunction(FileFunction::KeepOut(pos)))
+                .into(),
+        ];
+    for points in regions {
+        commands.extend(region(&points, format)?);
+    }
+    Ok(commands)
+}
+
+/// The aperture code used by [`profile_layer`].
+pub const PROFILE_APERTURE_CODE: i32 = 10;
+
+/// The file function attribute of a profile (board outline) layer and the outline.
+///
+/// The outline is drawn with a zero-size aperture (D10) that has the `.AperFunction,Profile`
+/// attribute, since the profile is a line without width. The outline is closed if the last point
+/// differs from the first.
+pub fn profile_layer(
+    plating: Option<Profile>,
+    outline: Vec<(f64, f64)>,
+    format: CoordinateFormat,
+) -> GerberResult<Vec<Command>> {
+    if outline.len() < 3 {
+        return Err(GerberError::MissingDataError(
+            "A profile needs at least 3 points".into(),
+        ));
+    }
+    let mut commands: Vec<Command> = vec![
+        ExtendedCode::FileAttribute(FileAttribute::FileFunction(FileFunction::Profile(plating)))
+            .into(),
+        ExtendedCode::ApertureAttribute(ApertureAttribute::ApertureFunction(
+            ApertureFunction::Profile,
+        ))
+        .into(),
+        ApertureDefinition::new(PROFILE_APERTURE_CODE, Aperture::Circle(Circle::new(0.0))).into(),
+        ExtendedCode::DeleteAttribute(AttributeDeletionCriterion::SingleApertureAttribute(
+            ".AperFunction".to_string(),
+        ))
+        .into(),
+        DCode::SelectAperture(PROFILE_APERTURE_CODE).into(),
+        DCode::Operation(Operation::Move(Some(coordinates(
+            outline[0].0,
+            outline[0].1,
+            format,
+        )?)))
+        .into(),
+        GCode::InterpolationMode(InterpolationMode::Linear).into(),
+    ];
+    let closing = if outline[0] != outline[outline.len() - 1] {
+        Some(outline[0])
+    } else {
+        None
+    };
+    for (x, y) in outline.iter().skip(1).copied().chain(closing) {
+        commands.push(
+            DCode::Operation(Operation::Interpolate(
+                Some(coordinates(x, y, format)?),
+                None,
+            ))
+            .into(),
+        );
+    }
+    Ok(commands)
+}
+
+/// Selects the aperture `code` and flashes it at every point of a grid.
+///
+/// The grid has `count.0` columns and `count.1` rows, the first flash is at `origin` and the
+/// distance between flashes is given by `pitch`. The flashes are emitted row by row.
+pub fn flash_grid(
+    code: i32,
+    origin: (f64, f64),
+    pitch: (f64, f64),
+    count: (u32, u32),
+    format: CoordinateFormat,
+) -> GerberResult<Vec<Command>> {
+    let mut commands = Vec::with_capacity(1 + count.0 as usize * count.1 as usize);
+    commands.push(DCode::SelectAperture(code).into());
+    for row in 0..count.1 {
+        let y = origin.1 + f64::from(row) * pitch.1;
+        for column in 0..count.0 {
+            let x = origin.0 + f64::from(column) * pitch.0;
+            commands
+                .push(DCode::Operation(Operation::Flash(Some(coordinates(x, y, format)?))).into());
+        }
+    }
+    Ok(commands)
+}
+
+/// The file function attribute of a via fill layer and the vias to fill.
+///
+/// Each via is given as `(x, y, diameter)`. A via drill aperture is defined for each distinct
+/// diameter, in order of first use, starting at D10. The vias are flashed grouped by aperture.
+pub fn via_fill_layer(
+    vias: Vec<(f64, f64, f64)>,
+    format: CoordinateFormat,
+) -> GerberResult<Vec<Command>> {
+    let mut diameters: Vec<f64> = Vec::new();
+    for &(_, _, diameter) in &vias {
+        if !diameter.is_finite() || diameter <= 0.0 {
+            return Err(GerberError::RangeError(format!(
+                "The diameter of a via must be positive, got {}",
+                diameter
+            )));
+        }
+        if !diameters.contains(&diameter) {
+            diameters.push(diameter);
+        }
+    }
+
+    let mut commands: Vec<Command> = vec![
+        ExtendedCode::FileAttribute(FileAttribute::FileFunction(FileFunction::ViaFill)).into(),
+        ExtendedCode::ApertureAttribute(ApertureAttribute::ApertureFunction(
+            ApertureFunction::ViaDrill(None),
+        ))
+        .into(),
+    ];
+    for (code, diameter) in (10..).zip(&diameters) {
+        commands
+            .push(ApertureDefinition::new(code, Aperture::Circle(Circle::new(*diameter))).into());
+    }
+    commands.push(
+        ExtendedCode::DeleteAttribute(AttributeDeletionCriterion::SingleApertureAttribute(
+            ".AperFunction".to_string(),
+        ))
+        .into(),
+    );
+    for (code, diameter) in (10..).zip(&diameters) {
+        commands.push(DCode::SelectAperture(code).into());
+        for &(x, y, _) in vias.iter().filter(|via| via.2 == *diameter) {
+            commands
+                .push(DCode::Operation(Operation::Flash(Some(coordinates(x, y, format)?))).into());
+        }
+    }
+    Ok(commands)
+}
+
+/// The file function attribute of a solder paste layer and the pads, shrunk for the stencil.
+///
+/// Each pad is given as its (copper) aperture and position. The apertures are scaled by
+/// `1 - reduction`, e.g. a reduction of `0.1` makes the stencil openings 10% smaller. An
+/// aperture is defined for each distinct pad aperture, in order of first use, starting at D10.
+/// The pads are flashed grouped by aperture. Macro apertures can not be scaled, it is an error
+/// if a pad uses one.
+pub fn paste_layer(
+    pads: Vec<(Aperture, (f64, f64))>,
+    reduction: f64,
+    pos: Position,
+    format: CoordinateFormat,
+) -> GerberResult<Vec<Command>> {
+    if !(0.0..1.0).contains(&reduction) {
+        return Err(GerberError::RangeError(format!(
+            "The paste reduction must be at least 0 and less than 1, got {}",
+            reduction
+        )));
+    }
+    let mut apertures: Vec<&Aperture> = Vec::new();
+    for (aperture, _) in &pads {
+        if let Aperture::Macro(name, _) = aperture {
+            return Err(GerberError::ValidationError(format!(
+                "Macro aperture {} can not be scaled for the paste layer",
+                name
+            )));
+        }
+        if !apertures.contains(&aperture) {
+            apertures.push(aperture);
+        }
+    }
+
+    let mut commands: Vec<Command> =
+        vec![
+            ExtendedCode::FileAttribute(FileAttribute::FileFunction(FileFunction::Paste(pos)))
+                .into(),
+        ];
+    for (code, aperture) in (10..).zip(&apertures) {
+        commands.push(ApertureDefinition::new(code, aperture.scaled(1.0 - reduction)?).into());
+    }
+    for (code, aperture) in (10..).zip(&apertures) {
+        commands.push(DCode::SelectAperture(code).into());
+        for (_, (x, y)) in pads.iter().filter(|pad| pad.0 == **aperture) {
+            commands.push(
+                DCode::Operation(Operation::Flash(Some(coordinates(*x, *y, format)?))).into(),
+            );
+        }
+    }
+    Ok(commands)
+}
+
+/// The symbol of the drill map for the `index`th drill diameter, as large as the drill.
+///
+/// The shapes repeat after six diameters, the apertures are still distinct because their sizes
+/// differ.
+fn drill_map_symbol(index: usize, diameter: f64) -> Aperture {
+    match index % 6 {
+        0 => Aperture::Circle(Circle::new(diameter)),
+        1 => Aperture::Rectangle(Rectangular::new(diameter, diameter)),
+        2 => Aperture::Polygon(Polygon::new(diameter, 3).with_rotation(90.0)),
+        3 => Aperture::Polygon(Polygon::new(diameter, 4)),
+        4 => Aperture::Polygon(Polygon::new(diameter, 6)),
+        _ => Aperture::Obround(Rectangular::new(diameter, diameter / 2.0)),
+    }
+}
+
+/// The file function attribute of a drill map and a symbol at every drill.
+///
+/// Each drill is given as `(x, y, diameter)`. A symbol aperture with a different shape is defined
+/// for each distinct diameter, in order of first use, starting at D10. The symbols are flashed
+/// grouped by aperture.
+pub fn drill_map(
+    drills: Vec<(f64, f64, f64)>,
+    format: CoordinateFormat,
+) -> GerberResult<Vec<Command>> {
+    let mut diameters: Vec<f64> = Vec::new();
+    for &(_, _, diameter) in &drills {
+        if !diameter.is_finite() || diameter <= 0.0 {
+            return Err(GerberError::RangeError(format!(
+                "The diameter of a drill must be positive, got {}",
+                diameter
+            )));
+        }
+        if !diameters.contains(&diameter) {
+            diameters.push(diameter);
+        }
+    }
+
+    let mut commands: Vec<Command> =
+        vec![
+            ExtendedCode::FileAttribute(FileAttribute::FileFunction(FileFunction::DrillMap)).into(),
+        ];
+    for (index, (code, diameter)) in (10..).zip(&diameters).enumerate() {
+        commands.push(ApertureDefinition::new(code, drill_map_symbol(index, *diameter)).into());
+    }
+    for (code, diameter) in (10..).zip(&diameters) {
+        commands.push(DCode::SelectAperture(code).into());
+        for &(x, y, _) in drills.iter().filter(|drill| drill.2 == *diameter) {
+            commands
+                .push(DCode::Operation(Operation::Flash(Some(coordinates(x, y, format)?))).into());
+        }
+    }
+    Ok(commands)
+}
+
+/// Flashes a component, tagged with its reference designator (`.C` object attribute).
+///
+/// If `aperture` is given it is selected first, otherwise the currently selected aperture is
+/// flashed. The `.C` attribute is deleted after the flash, so it is only attached to this flash.
+pub fn flash_component(aperture: Option<i32>, refdes: &str, coords: Coordinates) -> Vec<Command> {
+    let mut commands: Vec<Command> = Vec::with_capacity(4);
+    if let Some(code) = aperture {
+        commands.push(DCode::SelectAperture(code).into());
+    }
+    commands
+        .push(ExtendedCode::ObjectAttribute(ObjectAttribute::Component(refdes.to_string())).into());
+    commands.push(DCode::Operation(Operation::Flash(Some(coords))).into());
+    commands.push(
+        ExtendedCode::DeleteAttribute(AttributeDeletionCriterion::SingleObjectAttribute(
+            ".C".to_string(),
+        ))
+        .into(),
+    );
+    commands
+}
+
+/// A reference designator label for an assembly drawing
+/// (`FileFunction::AssemblyDrawing(pos)`).
+///
+/// Defines the aperture `code` with a `.FlashText` attribute holding the reference designator and
+/// flashes it at `coords`. The text is mirrored on the bottom side. The aperture itself is a
+/// circle with a diameter of `size`, which is what readers without `.FlashText` support show.
+pub fn assembly_label(
+    code: i32,
+    refdes: &str,
+    pos: Position,
+    size: f64,
+    coords: (f64, f64),
+    format: CoordinateFormat,
+) -> GerberResult<Vec<Command>> {
+    ObjectAttribute::check_refdes(refdes)?;
+    let mirroring = match pos {
+        Position::Top => TextMirroring::Readable,
+        Position::Bottom => TextMirroring::Mirrored,
+    };
+    Ok(vec![
+        ExtendedCode::ApertureAttribute(ApertureAttribute::FlashText {
+            text: refdes.to_string(),
+            mode: TextMode::Characters,
+            mirroring: Some(mirroring),
+            font: None,
+            size: None,
+            comment: None,
+        })
+        .into(),
+        ApertureDefinition::new(code, Aperture::Circle(Circle::new(size))).into(),
+        ExtendedCode::DeleteAttribute(AttributeDeletionCriterion::SingleApertureAttribute(
+            ".FlashText".to_string(),
+        ))
+        .into(),
+        DCode::SelectAperture(code).into(),
+        DCode::Operation(Operation::Flash(Some(coordinates(
+            coords.0, coords.1, format,
+        )?)))
+        .into(),
+    ])
+}
+
+/// A single flashed pad with its X2 attributes, e.g. an SMD pad of a component.
+///
+/// The aperture is defined with its `.AperFunction` attribute and flashed with the `.N`, `.P`
+/// and `.C` object attributes that are set. All attributes are deleted again, so none of them
+/// leak to the following commands.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pad {
+    code: i32,
+    aperture: Aperture,
+    function: Option<ApertureFunction>,
+    net: Option<Net>,
+    pin: Option<Pin>,
+    component: Option<String>,
+    x: f64,
+    y: f64,
+}
+
+impl Pad {
+    /// A pad that flashes `aperture`, defined as aperture `code`, at (`x`, `y`).
+    pub fn new(code: i32, aperture: Aperture, x: f64, y: f64) -> Self {
+        Pad {
+            code,
+            aperture,
+            function: None,
+            net: None,
+            pin: None,
+            component: None,
+            x,
+            y,
+        }
+    }
+
+    /// Sets the `.AperFunction` aperture attribute.
+    pub fn function(mut self, function: ApertureFunction) -> Self {
+        self.function = Some(function);
+        self
+    }
+
+    /// Sets the `.N` object attribute.
+    pub fn net(mut self, net: Net) -> Self {
+        self.net = Some(net);
+        self
+    }
+
+    /// Sets the `.P` object attribute.
+    pub fn pin(mut self, pin: Pin) -> Self {
+        self.pin = Some(pin);
+        self
+    }
+
+    /// Sets the `.C` object attribute.
+    pub fn component(mut self, refdes: impl Into<String>) -> Self {
+        self.component = Some(refdes.into());
+        self
+    }
+
+    /// Generates the commands: the [`definition`](Self::definition) of the aperture, followed by
+    /// the [`flash`](Self::flash).
+    pub fn build(&self, format: CoordinateFormat) -> GerberResult<Vec<Command>> {
+        let mut commands = self.definition();
+        commands.append(&mut self.flash(format)?);
+        Ok(commands)
+    }
+
+    /// Generates the aperture attribute, the aperture definition and the deletion of the
+    /// attribute.
+    ///
+    /// Pads that share an aperture only need one definition, use [`flash`](Self::flash) for the
+    /// others.
+    pub fn definition(&self) -> Vec<Command> {
+        let mut commands: Vec<Command> = Vec::new();
+        if let Some(function) = &self.function {
+            commands.push(
+                ExtendedCode::ApertureAttribute(ApertureAttribute::ApertureFunction(
+                    function.clone(),
+                ))
+                .into(),
+            );
+        }
+        commands.push(ApertureDefinition::new(self.code, self.aperture.clone()).into());
+        if self.function.is_some() {
+            commands.push(
+                ExtendedCode::DeleteAttribute(AttributeDeletionCriterion::SingleApertureAttribute(
+                    ".AperFunction".to_string(),
+                ))
+                .into(),
+            );
+        }
+        commands
+    }
+
+    /// Generates the object attributes, the aperture selection and the flash, followed by the
+    /// deletion of each attribute. The aperture must already be defined, e.g. with
+    /// [`definition`](Self::definition).
+    ///
+    /// It is an error if the reference designator of the component or the pin is empty.
+    pub fn flash(&self, format: CoordinateFormat) -> GerberResult<Vec<Command>> {
+        if let Some(refdes) = &self.component {
+            ObjectAttribute::check_refdes(refdes)?;
+        }
+        if let Some(pin) = &self.pin {
+            ObjectAttribute::check_refdes(&pin.refdes)?;
+        }
+        let mut object_attributes: Vec<(ObjectAttribute, &str)> = Vec::new();
+        if let Some(net) = &self.net {
+            object_attributes.push((ObjectAttribute::Net(net.clone()), ".N"));
+        }
+        if let Some(pin) = &self.pin {
+            object_attributes.push((ObjectAttribute::Pin(pin.clone()), ".P"));
+        }
+        if let Some(refdes) = &self.component {
+            object_attributes.push((ObjectAttribute::Component(refdes.clone()), ".C"));
+        }
+        let mut commands: Vec<Command> = Vec::new();
+        for (attribute, _) in &object_attributes {
+            commands.push(ExtendedCode::ObjectAttribute(attribute.clone()).into());
+        }
+        commands.push(DCode::SelectAperture(self.code).into());
+        commands.push(
+            DCode::Operation(Operation::Flash(Some(coordinates(self.x, self.y, format)?))).into(),
+        );
+        for (_, name) in object_attributes {
+            commands.push(
+                ExtendedCode::DeleteAttribute(AttributeDeletionCriterion::SingleObjectAttribute(
+                    name.to_string(),
+                ))
+                .into(),
+            );
+        }
+        Ok(commands)
+    }
+}
+
+/// The strokes of a character of the [`stroke_text`] font, as polylines on a grid that is 4 units
+/// wide and 6 units high. A polyline with a single point is a dot.
+type Glyph = &'static [&'static [(u8, u8)]];
+
+const GLYPH_WIDTH: u8 = 4;
+const GLYPH_HEIGHT: u8 = 6;
+/// The distance between the left edges of two characters, in grid units.
+const GLYPH_ADVANCE: u8 = 6;
+
+const GLYPH_O: &[(u8, u8)] = &[
+    (1, 0),
+    (0, 1),
+    (0, 5),
+    (1, 6),
+    (3, 6),
+    (4, 5),
+    (4, 1),
+    (3, 0),
+    (1, 0),
+];
+const GLYPH_P: &[(u8, u8)] = &[(0, 0), (0, 6), (3, 6), (4, 5), (4, 4), (3, 3), (0, 3)];
+
+fn glyph(c: char) -> Option<Glyph> {
+    let glyph: Glyph = match c.to_ascii_uppercase() {
+        ' ' => &[],
+        'A' => &[&[(0, 0), (0, 4), (2, 6), (4, 4), (4, 0)], &[(0, 3), (4, 3)]],
+        'B' => &[
+            &[(0, 0), (0, 6), (3, 6), (4, 5), (4, 4), (3, 3), (0, 3)],
+            &[(3, 3), (4, 2), (4, 1), (3, 0), (0, 0)],
+        ],
+        'C' => &[&[
+            (4, 5),
+            (3, 6),
+            (1, 6),
+            (0, 5),
+            (0, 1),
+            (1, 0),
+            (3, 0),
+            (4, 1),
+        ]],
+        'D' => &[&[(0, 0), (0, 6), (2, 6), (4, 4), (4, 2), (2, 0), (0, 0)]],
+        'E' => &[&[(4, 6), (0, 6), (0, 0), (4, 0)], &[(0, 3), (3, 3)]],
+        'F' => &[&[(4, 6), (0, 6), (0, 0)], &[(0, 3), (3, 3)]],
+        'G' => &[&[
+            (4, 5),
+            (3, 6),
+            (1, 6),
+            (0, 5),
+            (0, 1),
+            (1, 0),
+            (3, 0),
+            (4, 1),
+            (4, 3),
+            (2, 3),
+        ]],
+        'H' => &[&[(0, 0), (0, 6)], &[(4, 0), (4, 6)], &[(0, 3), (4, 3)]],
+        'I' => &[&[(1, 6), (3, 6)], &[(2, 6), (2, 0)], &[(1, 0), (3, 0)]],
+        'J' => &[&[(4, 6), (4, 1), (3, 0), (1, 0), (0, 1)]],
+        'K' => &[&[(0, 0), (0, 6)], &[(4, 6), (0, 2)], &[(1, 3), (4, 0)]],
+        'L' => &[&[(0, 6), (0, 0), (4, 0)]],
+        'M' => &[&[(0, 0), (0, 6), (2, 3), (4, 6), (4, 0)]],
+        'N' => &[&[(0, 0), (0, 6), (4, 0), (4, 6)]],
+        'O' => &[GLYPH_O],
+        'P' => &[GLYPH_P],
+        'Q' => &[GLYPH_O, &[(2, 2), (4, 0)]],
+        'R' => &[GLYPH_P, &[(2, 3), (4, 0)]],
+        'S' => &[&[
+            (4, 5),
+            (3, 6),
+            (1, 6),
+            (0, 5),
+            (0, 4),
+            (1, 3),
+            (3, 3),
+            (4, 2),
+            (4, 1),
+            (3, 0),
+            (1, 0),
+            (0, 1),
+        ]],
+        'T' => &[&[(0, 6), (4, 6)], &[(2, 6), (2, 0)]],
+        'U' => &[&[(0, 6), (0, 1), (1, 0), (3, 0), (4, 1), (4, 6)]],
+        'V' => &[&[(0, 6), (2, 0), (4, 6)]],
+        'W' => &[&[(0, 6), (1, 0), (2, 3), (3, 0), (4, 6)]],
+        'X' => &[&[(0, 0), (4, 6)], &[(0, 6), (4, 0)]],
+        'Y' => &[&[(0, 6), (2, 3), (4, 6)], &[(2, 3), (2, 0)]],
+        'Z' => &[&[(0, 6), (4, 6), (0, 0), (4, 0)]],
+        '0' => &[GLYPH_O, &[(0, 1), (4, 5)]],
+        '1' => &[&[(1, 5), (2, 6), (2, 0)], &[(1, 0), (3, 0)]],
+        '2' => &[&[(0, 5), (1, 6), (3, 6), (4, 5), (4, 4), (0, 0), (4, 0)]],
+        '3' => &[
+            &[
+                (0, 5),
+                (1, 6),
+                (3, 6),
+                (4, 5),
+                (4, 4),
+                (3, 3),
+                (4, 2),
+                (4, 1),
+                (3, 0),
+                (1, 0),
+                (0, 1),
+            ],
+            &[(1, 3), (3, 3)],
+        ],
+        '4' => &[&[(3, 0), (3, 6), (0, 2), (4, 2)]],
+        '5' => &[&[
+            (4, 6),
+            (0, 6),
+            (0, 3),
+            (3, 3),
+            (4, 2),
+            (4, 1),
+            (3, 0),
+            (0, 0),
+        ]],
+        '6' => &[&[
+            (4, 5),
+            (3, 6),
+            (1, 6),
+            (0, 5),
+            (0, 1),
+            (1, 0),
+            (3, 0),
+            (4, 1),
+            (4, 2),
+            (3, 3),
+            (0, 3),
+        ]],
+        '7' => &[&[(0, 6), (4, 6), (1, 0)]],
+        '8' => &[&[
+            (1, 3),
+            (0, 4),
+            (0, 5),
+            (1, 6),
+            (3, 6),
+            (4, 5),
+            (4, 4),
+            (3, 3),
+            (1, 3),
+            (0, 2),
+            (0, 1),
+            (1, 0),
+            (3, 0),
+            (4, 1),
+            (4, 2),
+            (3, 3),
+        ]],
+        '9' => &[&[
+            (0, 1),
+            (1, 0),
+            (3, 0),
+            (4, 1),
+            (4, 5),
+            (3, 6),
+            (1, 6),
+            (0, 5),
+            (0, 4),
+            (1, 3),
+            (4, 3),
+        ]],
+        '-' => &[&[(1, 3), (3, 3)]],
+        '+' => &[&[(0, 3), (4, 3)], &[(2, 1), (2, 5)]],
+        '/' => &[&[(0, 0), (4, 6)]],
+        '.' => &[&[(2, 0)]],
+        _ => return None,
+    };
+    Some(glyph)
+}
+
+/// A thin circle aperture `code` for [`stroke_text`] of the given height, with a line width of a
+/// tenth of the height.
+pub fn stroke_text_aperture(code: i32, height: f64) -> ApertureDefinition {
+    ApertureDefinition::new(code, Aperture::Circle(Circle::new(height / 10.0)))
+}
+
+/// Strokes the text with lines, e.g. for a legend (silkscreen) layer.
+///
+/// The text is drawn with a minimal vector font that supports the letters `A` to `Z` (lower case
+/// letters are drawn as upper case), the digits and ` `, `-`, `+`, `/` and `.`. Any other
+/// character is an error. `origin` is the bottom left corner of the first character and `height`
+/// the height of the characters. Characters are `0.67 * height` wide with a pitch of `height`.
+///
+/// The strokes are drawn with the aperture `code`, which must be defined by the caller, e.g. with
+/// [`stroke_text_aperture`]. The interpolation mode is left as linear.
+pub fn stroke_text(
+    code: i32,
+    text: &str,
+    origin: (f64, f64),
+    height: f64,
+    format: CoordinateFormat,
+) -> GerberResult<Vec<Command>> {
+    if !height.is_finite() || height <= 0.0 {
+        return Err(GerberError::RangeError(format!(
+            "The height of text must be positive, got {}",
+            height
+        )));
+    }
+    let scale = height / f64::from(GLYPH_HEIGHT);
+    let mut commands: Vec<Command> = vec![DCode::SelectAperture(code).into()];
+    let mut linear = false;
+    for (index, c) in text.chars().enumerate() {
+        let glyph = glyph(c).ok_or_else(|| {
+            GerberError::ConversionError(format!(
+                "Character '{}' is not supported by the stroke font",
+                c
+            ))
+        })?;
+        let left = origin.0 + (index * GLYPH_ADVANCE as usize) as f64 * scale;
+        let point = |&(x, y): &(u8, u8)| {
+            debug_assert!(x <= GLYPH_WIDTH && y <= GLYPH_HEIGHT);
+            coordinates(
+                left + f64::from(x) * scale,
+                origin.1 + f64::from(y) * scale,
+                format,
+            )
+        };
+        for stroke in glyph {
+            commands.push(DCode::Operation(Operation::Move(Some(point(&stroke[0])?))).into());
+            if stroke.len() == 1 {
+                commands.push(DCode::Operation(Operation::Flash(None)).into());
+                continue;
+            }
+            if !linear {
+                commands.push(GCode::InterpolationMode(InterpolationMode::Linear).into());
+                linear = true;
+            }
+            for vertex in &stroke[1..] {
+                commands.push(
+                    DCode::Operation(Operation::Interpolate(Some(point(vertex)?), None)).into(),
+                );
+            }
+        }
+    }
+    Ok(commands)
+}
+
+/// Wraps the commands in a block aperture with the given code.
+///
+/// The content of a block is part of the file it is used in, so it must not contain a
+/// coordinate format (FS), unit (MO) or end of file (M02) command. Nested blocks in the content
+/// must be closed.
+pub fn make_aperture_block(
+    code: i32,
+    content: Vec<Command>,
+) -> GerberResult<ApertureBlockDefinition> {
+    for (index, command) in content.iter().enumerate() {
+        let name = match command {
+            Command::ExtendedCode(ExtendedCode::CoordinateFormat(_)) => "coordinate format (FS)",
+            Command::ExtendedCode(ExtendedCode::Unit(_)) => "unit (MO)",
+            Command::FunctionCode(FunctionCode::MCode(MCode::EndOfFile)) => "end of file (M02)",
+            _ => continue,
+        };
+        return Err(LocatedError::new(
+            index,
+            GerberError::ValidationError(format!(
+                "an aperture block cannot contain a {} command",
+                name
+            )),
+        )
+        .into());
+    }
+    check_balanced_blocks(&content)?;
+    Ok(ApertureBlockDefinition {
+        code,
+        commands: content,
+    })
+}
+
+/// Builds a fabrication panel from the commands of a single board.
+///
+/// The board is repeated with a step-and-repeat (SR) block and the file is marked as a
+/// fabrication panel with the `.Part` attribute.
+///
+/// The `.FileFunction` of the board is kept: a panel of a copper layer is still a copper layer.
+/// `FileFunction::ArrayDrawing` is meant for drawings that document the array, so it is not
+/// added here.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PanelBuilder {
+    columns: u32,
+    rows: u32,
+    pitch_x: f64,
+    pitch_y: f64,
+}
+
+impl PanelBuilder {
+    /// A panel of `columns` x `rows` boards, the pitch is the distance between the origins of
+    /// neighbouring boards.
+    pub fn new(columns: u32, rows: u32, pitch_x: f64, pitch_y: f64) -> Self {
+        PanelBuilder {
+            columns,
+            rows,
+            pitch_x,
+            pitch_y,
+        }
+    }
+
+    /// Generates the panel.
+    ///
+    /// The header of the board (coordinate format, unit, file attributes, comments, aperture
+    /// macros, definitions and blocks) is emitted once, before the step-and-repeat block, with
+    /// any `.Part` attribute replaced. Aperture attributes and attribute deletions directly before
+    /// a definition or block move to the header with it. All other commands, except the end of
+    /// file, are repeated.
+    pub fn build(&self, board: &[Command]) -> GerberResult<Vec<Command>> {
+        if self.columns == 0 || self.rows == 0 {
+            return Err(GerberError::RangeError(
+                "A panel must have at least one column and one row".into(),
+            ));
+        }
+        if !self.pitch_x.is_finite() || !self.pitch_y.is_finite() {
+            return Err(GerberError::RangeError(
+                "The pitch of a panel must be finite".into(),
+            ));
+        }
+
+        let part: Command =
+            ExtendedCode::FileAttribute(FileAttribute::Part(Part::FabricationPanel)).into();
+        let mut header = vec![part];
+        let mut body = Vec::new();
+        // Aperture attributes apply to the definitions that follow them, so they are kept
+        // until it is known whether the next command is a definition.
+        let mut attributes = Vec::new();
+        // Aperture blocks are definitions, their content goes to the header as well.
+        let mut block_depth = 0_usize;
+        for command in board {
+            match command {
+                Command::ExtendedCode(ExtendedCode::ApertureAttribute(_))
+                | Command::ExtendedCode(ExtendedCode::DeleteAttribute(_))
+                    if block_depth == 0 =>
+                {
+                    attributes.push(command.clone());
+                    continue;
+                }
+                Command::ExtendedCode(ExtendedCode::ApertureDefinition(_))
+                | Command::ExtendedCode(ExtendedCode::ApertureBlock(ApertureBlock::Open {
+                    ..
+                })) => header.append(&mut attributes),
+                _ => body.append(&mut attributes),
+            }
+            match command {
+                Command::ExtendedCode(ExtendedCode::ApertureBlock(block)) => {
+                    block_depth = match block {
+                        ApertureBlock::Open { .. } => block_depth + 1,
+                        ApertureBlock::Close => block_depth.saturating_sub(1),
+                    };
+                    header.push(command.clone())
+                }
+                _ if block_depth > 0 => header.push(command.clone()),
+                Command::ExtendedCode(ExtendedCode::FileAttribute(FileAttribute::Part(_))) => {}
+                Command::ExtendedCode(ExtendedCode::CoordinateFormat(_))
+                | Command::ExtendedCode(ExtendedCode::Unit(_))
+                | Command::ExtendedCode(ExtendedCode::FileAttribute(_))
+                | Command::ExtendedCode(ExtendedCode::ApertureMacro(_))
+                | Command::ExtendedCode(ExtendedCode::ApertureDefinition(_))
+                | Command::FunctionCode(FunctionCode::GCode(GCode::Comment(_))) => {
+                    header.push(command.clone())
+                }
+                Command::FunctionCode(FunctionCode::MCode(MCode::EndOfFile)) => {}
+                _ => body.push(command.clone()),
+            }
+        }
+        body.append(&mut attributes);
+
+        let mut commands = header;
+        commands.push(
+            ExtendedCode::StepAndRepeat(StepAndRepeat::Open {
+                repeat_x: self.columns,
+                repeat_y: self.rows,
+                distance_x: self.pitch_x,
+                distance_y: self.pitch_y,
+            })
+            .into(),
+        );
+        commands.append(&mut body);
+        commands.push(ExtendedCode::StepAndRepeat(StepAndRepeat::Close).into());
+        commands.push(MCode::EndOfFile.into());
+        Ok(commands)
+    }
+}
+
+fn aperture_code_mut(command: &mut Command) -> Option<&mut i32> {
+    match command {
+        Command::ExtendedCode(ExtendedCode::ApertureDefinition(definition)) => {
+            Some(&mut definition.code)
+        }
+        Command::ExtendedCode(ExtendedCode::ApertureBlock(ApertureBlock::Open { code }))
+        | Command::FunctionCode(FunctionCode::DCode(DCode::SelectAperture(code))) => Some(code),
+        _ => None,
+    }
+}
+
+/// The code of an aperture definition, aperture block or aperture selection.
+fn aperture_code(command: &Command) -> Option<i32> {
+    match command {
+        Command::ExtendedCode(ExtendedCode::ApertureDefinition(definition)) => {
+            Some(definition.code)
+        }
+        Command::ExtendedCode(ExtendedCode::ApertureBlock(ApertureBlock::Open { code }))
+        | Command::FunctionCode(FunctionCode::DCode(DCode::SelectAperture(code))) => Some(*code),
+        _ => None,
+    }
+}
+
+/// Merges two files into one.
+///
+/// The coordinate format (FS) and unit (MO) of both files must be the same, if declared. The
+/// merged file has a single FS and MO, followed by the commands of `a` and then the commands of
+/// `b`, and a single end of file. File attributes and aperture macros of `b` that are already
+/// in `a` are dropped, a macro with the same name as a different macro in `a` is an error.
+/// Apertures of `b` with a code that is used in `a` are renumbered.
+///
+/// The graphics state (polarity, interpolation mode, etc.) at the end of `a` is not reset, so
+/// `b` should set the state it needs.
+pub fn merge_streams(a: Vec<Command>, b: Vec<Command>) -> GerberResult<Vec<Command>> {
+    let format_of = |commands: &[Command]| {
+        commands.iter().find_map(|command| match command {
+            Command::ExtendedCode(ExtendedCode::CoordinateFormat(format)) => Some(*format),
+            _ => None,
+        })
+    };
+    let unit_of = |commands: &[Command]| {
+        commands.iter().find_map(|command| match command {
+            Command::ExtendedCode(ExtendedCode::Unit(unit)) => Some(*unit),
+            _ => None,
+        })
+    };
+    let format = match (format_of(&a), format_of(&b)) {
+        (Some(format_a), Some(format_b)) if format_a != format_b => {
+            return Err(GerberError::CoordinateFormatError(
+                "The files have different coordinate formats".into(),
+            ));
+        }
+        (format_a, format_b) => format_a.or(format_b),
+    };
+    let unit = match (unit_of(&a), unit_of(&b)) {
+        (Some(unit_a), Some(unit_b)) if unit_a != unit_b => {
+            return Err(GerberError::ValidationError(
+                "The files have different units".into(),
+            ));
+        }
+        (unit_a, unit_b) => unit_a.or(unit_b),
+    };
+
+    let is_header_or_eof = |command: &Command| {
+        matches!(
+            command,
+            Command::ExtendedCode(ExtendedCode::CoordinateFormat(_))
+                | Command::ExtendedCode(ExtendedCode::Unit(_))
+                | Command::FunctionCode(FunctionCode::MCode(MCode::EndOfFile))
+        )
+    };
+    let mut a: Vec<Command> = a.into_iter().filter(|c| !is_header_or_eof(c)).collect();
+    let mut b: Vec<Command> = b.into_iter().filter(|c| !is_header_or_eof(c)).collect();
+
+    let used_codes: HashSet<i32> = a.iter().filter_map(aperture_code).collect();
+    let mut next_code = a
+        .iter()
+        .chain(b.iter())
+        .filter_map(aperture_code)
+        .max()
+        .map_or(10, |max| max.max(9) + 1);
+    let mut renumbered: HashMap<i32, i32> = HashMap::new();
+    for code in b.iter().filter_map(aperture_code) {
+        if used_codes.contains(&code) && !renumbered.contains_key(&code) {
+            renumbered.insert(code, next_code);
+            next_code += 1;
+        }
+    }
+
+    let mut b_filtered = Vec::with_capacity(b.len());
+    for mut command in b.drain(..) {
+        match &command {
+            Command::ExtendedCode(ExtendedCode::FileAttribute(_)) if a.contains(&command) => {
+                continue
+            }
+            Command::ExtendedCode(ExtendedCode::ApertureMacro(am)) => {
+                let existing = a.iter().find_map(|command| match command {
+                    Command::ExtendedCode(ExtendedCode::ApertureMacro(existing))
+                        if existing.name == am.name =>
+                    {
+                        Some(existing)
+                    }
+                    _ => None,
+                });
+                match existing {
+                    Some(existing) if existing == am => continue,
+                    Some(_) => {
+                        return Err(GerberError::ValidationError(format!(
+                            "Aperture macro {} is defined differently in the files",
+                            am.name
+                        )));
+                    }
+                    None => {}
+                }
+            }
+            _ => {}
+        }
+        if let Some(code) = aperture_code_mut(&mut command) {
+            if let Some(new_code) = renumbered.get(code) {
+                *code = *new_code;
+            }
+        }
+        b_filtered.push(command);
+    }
+
+    let mut commands: Vec<Command> = Vec::with_capacity(a.len() + b_filtered.len() + 3);
+    commands.extend(format.map(Command::from));
+    commands.extend(unit.map(Command::from));
+    commands.append(&mut a);
+    commands.append(&mut b_filtered);
+    commands.push(MCode::EndOfFile.into());
+    Ok(commands)
+}
+
+/// The smallest valid Gerber file: the coordinate format, the unit and the end of file.
+pub fn empty_gerber(format: CoordinateFormat, unit: Unit) -> Vec<Command> {
+    vec![format.into(), unit.into(), MCode::EndOfFile.into()]
+}
+
+/// A library of aperture macros, to share the macros between files.
+///
+/// Contains a comment followed by the macro definitions (`%AM`), there is no coordinate format,
+/// unit or end of file, so the commands can be inserted into the header of another file.
+pub fn macro_library(macros: &[ApertureMacro]) -> Vec<Command> {
+    let mut commands: Vec<Command> = Vec::with_capacity(macros.len() + 1);
+    commands
+        .push(GCode::Comment(CommentContent::String("Aperture macro library".to_string())).into());
+    commands.extend(macros.iter().cloned().map(Command::from));
+    commands
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::io::BufWriter;
+
+    use crate::attributes::SmdPadType;
+    use crate::traits::GerberCode;
+    use crate::{CenterLinePrimitive, CirclePrimitive, CoordinateMode, MacroDecimal, ZeroOmission};
+
+    #[test]
+    fn test_draw_unit_circle() {
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 4);
+        let commands = draw_circle(0.0, 0.0, 1.0, cf).unwrap();
+
+        let expected: Vec<Command> = vec![
+            GCode::QuadrantMode(QuadrantMode::Multi).into(),
+            DCode::Operation(Operation::Move(Some(Coordinates::new(1, 0, cf)))).into(),
+            GCode::InterpolationMode(InterpolationMode::CounterclockwiseCircular).into(),
+            DCode::Operation(Operation::Interpolate(
+                Some(Coordinates::new(-1, 0, cf)),
+                Some(CoordinateOffset::new(-1, 0, cf)),
+            ))
+            .into(),
+            DCode::Operation(Operation::Interpolate(
+                Some(Coordinates::new(1, 0, cf)),
+                Some(CoordinateOffset::new(1, 0, cf)),
+            ))
+            .into(),
+        ];
+        assert_eq!(commands, expected);
+
+        assert_code!(
+            commands,
+            "G75*\nX10000Y0D02*\nG03*\nX-10000Y0I-10000J0D01*\nX10000Y0I10000J0D01*\n"
+        );
+    }
+
+    #[test]
+    fn test_from_segments() {
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 4);
+        let segments = vec![
+            Segment::line(10, (0.0, 0.0), (1.0, 0.0)),
+            Segment::line(11, (5.0, 5.0), (6.0, 5.0)),
+            Segment::line(10, (1.0, 0.0), (1.0, 1.0)),
+            Segment::arc(10, (1.0, 1.0), (0.0, 2.0), (0.0, 1.0), false),
+        ];
+        let commands = from_segments(&segments, cf).unwrap();
+
+        let selects = commands
+            .iter()
+            .filter(|command| {
+                matches!(
+                    command,
+                    Command::FunctionCode(FunctionCode::DCode(DCode::SelectAperture(_)))
+                )
+            })
+            .count();
+        assert_eq!(selects, 2);
+
+        assert_code!(
+            commands,
+            "G75*\n\
+             D10*\n\
+             X0Y0D02*\n\
+             G01*\n\
+             X10000Y0D01*\n\
+             X10000Y10000D01*\n\
+             G03*\n\
+             X0Y20000I-10000J0D01*\n\
+             D11*\n\
+             X50000Y50000D02*\n\
+             G01*\n\
+             X60000Y50000D01*\n"
+        );
+    }
+
+    #[test]
+    fn test_flash_grid() {
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 4);
+        let commands = flash_grid(10, (1.0, 2.0), (2.5, 5.0), (2, 2), cf).unwrap();
+        assert_eq!(commands.len(), 5);
+        assert_code!(
+            commands,
+            "D10*\nX10000Y20000D03*\nX35000Y20000D03*\nX10000Y70000D03*\nX35000Y70000D03*\n"
+        );
+    }
+
+    #[test]
+    fn test_via_fill_layer() {
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 4);
+        let commands =
+            via_fill_layer(vec![(0.0, 0.0, 0.3), (1.0, 0.0, 0.5), (2.0, 0.0, 0.3)], cf).unwrap();
+        let definitions = commands
+            .iter()
+            .filter(|command| {
+                matches!(
+                    command,
+                    Command::ExtendedCode(ExtendedCode::ApertureDefinition(_))
+                )
+            })
+            .count();
+        assert_eq!(definitions, 2);
+        assert_code!(
+            commands,
+            "%TF.FileFunction,Viafill*%\n\
+             %TA.AperFunction,ViaDrill*%\n\
+             %ADD10C,0.3*%\n\
+             %ADD11C,0.5*%\n\
+             %TD.AperFunction*%\n\
+             D10*\n\
+             X0Y0D03*\n\
+             X20000Y0D03*\n\
+             D11*\n\
+             X10000Y0D03*\n"
+        );
+
+        assert!(via_fill_layer(vec![(0.0, 0.0, 0.0)], cf).is_err());
+    }
+
+    #[test]
+    fn test_flash_component() {
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 4);
+        let commands = flash_component(None, "R1", Coordinates::new(1, 2, cf));
+        assert_eq!(commands.len(), 3);
+        assert_code!(commands, "%TO.C,R1*%\nX10000Y20000D03*\n%TD.C*%\n");
+
+        let commands = flash_component(Some(10), "R2", Coordinates::new(3, 2, cf));
+        assert_code!(commands, "D10*\n%TO.C,R2*%\nX30000Y20000D03*\n%TD.C*%\n");
+    }
+
+    #[test]
+    fn test_pad() {
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 4);
+        let commands = Pad::new(
+            11,
+            Aperture::Rectangle(Rectangular::new(0.6, 0.9)),
+            2.5,
+            1.0,
+        )
+        .function(ApertureFunction::SmdPad(SmdPadType::CopperDefined))
+        .net(Net::single("GND"))
+        .pin(Pin {
+            refdes: "C1".to_string(),
+            name: "2".to_string(),
+            function: None,
+        })
+        .component("C1")
+        .build(cf)
+        .unwrap();
+        assert_eq!(commands.len(), 11);
+        assert_code!(
+            commands,
+            "%TA.AperFunction,SMDPad,CuDef*%\n\
+             %ADD11R,0.6X0.9*%\n\
+             %TD.AperFunction*%\n\
+             %TO.N,GND*%\n\
+             %TO.P,C1,2*%\n\
+             %TO.C,C1*%\n\
+             D11*\n\
+             X25000Y10000D03*\n\
+             %TD.N*%\n\
+             %TD.P*%\n\
+             %TD.C*%\n"
+        );
+
+        let commands = Pad::new(10, Aperture::Circle(Circle::new(1.0)), 0.0, 0.0)
+            .build(cf)
+            .unwrap();
+        assert_code!(commands, "%ADD10C,1*%\nD10*\nX0Y0D03*\n");
+
+        assert!(Pad::new(10, Aperture::Circle(Circle::new(1.0)), 0.0, 0.0)
+            .component("")
+            .build(cf)
+            .is_err());
+        assert!(matches!(
+            Pad::new(10, Aperture::Circle(Circle::new(1.0)), 0.0, 0.0)
+                .pin(Pin {
+                    refdes: String::new(),
+                    name: "1".to_string(),
+                    function: None,
+                })
+                .build(cf),
+            Err(GerberError::ValidationError(_))
+        ));
+    }
+
+    #[test]
+    fn test_pad_shared_aperture() {
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 4);
+        let pad = |x: f64| {
+            Pad::new(10, Aperture::Circle(Circle::new(1.0)), x, 0.0)
+                .function(ApertureFunction::ComponentPad)
+        };
+        let mut commands = pad(0.0).definition();
+        for x in [0.0, 2.0] {
+            commands.append(&mut pad(x).flash(cf).unwrap());
+        }
+        assert_code!(
+            commands,
+            "%TA.AperFunction,ComponentPad*%\n\
+             %ADD10C,1*%\n\
+             %TD.AperFunction*%\n\
+             D10*\n\
+             X0Y0D03*\n\
+             D10*\n\
+             X20000Y0D03*\n"
+        );
+    }
+
+    #[test]
+    fn test_assembly_label() {
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 4);
+        let commands = assembly_label(10, "U1", Position::Top, 1.5, (2.0, 3.5), cf).unwrap();
+        assert_eq!(
+            commands[0],
+            ExtendedCode::ApertureAttribute(ApertureAttribute::FlashText {
+                text: "U1".to_string(),
+                mode: TextMode::Characters,
+                mirroring: Some(TextMirroring::Readable),
+                font: None,
+                size: None,
+                comment: None,
+            })
+            .into()
+        );
+        assert_eq!(
+            commands[4],
+            DCode::Operation(Operation::Flash(Some(Coordinates::new(
+                2,
+                CoordinateNumber::try_from(3.5).unwrap(),
+                cf
+            ))))
+            .into()
+        );
+        assert_code!(
+            commands,
+            "%TA.FlashText,U1,C,R,,,*%\n%ADD10C,1.5*%\n%TD.FlashText*%\nD10*\nX20000Y35000D03*\n"
+        );
+
+        let commands = assembly_label(11, "C2", Position::Bottom, 1.0, (0.0, 0.0), cf).unwrap();
+        assert_code!(
+            commands,
+            "%TA.FlashText,C2,C,M,,,*%\n%ADD11C,1*%\n%TD.FlashText*%\nD11*\nX0Y0D03*\n"
+        );
+
+        let commands = assembly_label(12, "R,1", Position::Top, 1.0, (0.0, 0.0), cf).unwrap();
+        assert_code!(
+            commands,
+            "%TA.FlashText,R\\u002C1,C,R,,,*%\n%ADD12C,1*%\n%TD.FlashText*%\nD12*\nX0Y0D03*\n"
+        );
+        assert!(matches!(
+            assembly_label(12, "", Position::Top, 1.0, (0.0, 0.0), cf),
+            Err(GerberError::ValidationError(_))
+        ));
+    }
+
+    #[test]
+    fn test_stroke_text() {
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 4);
+        let commands = stroke_text(10, "A", (0.0, 0.0), 1.2, cf).unwrap();
+        assert_eq!(commands[0], DCode::SelectAperture(10).into());
+        assert!(matches!(
+            commands[1],
+            Command::FunctionCode(FunctionCode::DCode(DCode::Operation(Operation::Move(_))))
+        ));
+        assert_code!(
+            commands,
+            "D10*\n\
+             X0Y0D02*\n\
+             G01*\n\
+             X0Y8000D01*\n\
+             X4000Y12000D01*\n\
+             X8000Y8000D01*\n\
+             X8000Y0D01*\n\
+             X0Y6000D02*\n\
+             X8000Y6000D01*\n"
+        );
+        assert_code!(
+            ExtendedCode::ApertureDefinition(stroke_text_aperture(15, 1.2)),
+            "%ADD15C,0.12*%\n"
+        );
+
+        // the second character starts one pitch (the height) to the right, a dot is a flash
+        let commands = stroke_text(10, "a.", (1.0, 0.0), 1.2, cf).unwrap();
+        assert_eq!(
+            commands[commands.len() - 2..],
+            [
+                DCode::Operation(Operation::Move(Some(coordinates(2.6, 0.0, cf).unwrap()))).into(),
+                DCode::Operation(Operation::Flash(None)).into(),
+            ]
+        );
+
+        assert!(stroke_text(10, " ", (0.0, 0.0), 1.0, cf).unwrap().len() == 1);
+        assert_eq!(
+            stroke_text(12, "-", (0.0, 0.0), 1.0, cf).unwrap()[0],
+            DCode::SelectAperture(12).into()
+        );
+        assert!(matches!(
+            stroke_text(10, "A!", (0.0, 0.0), 1.0, cf),
+            Err(GerberError::ConversionError(_))
+        ));
+        assert!(stroke_text(10, "A", (0.0, 0.0), 0.0, cf).is_err());
+    }
+
+    #[test]
+    fn test_make_aperture_block() {
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 4);
+        let content = flash_grid(10, (0.0, 0.0), (1.0, 1.0), (2, 1), cf).unwrap();
+        let block = make_aperture_block(100, content).unwrap();
+        assert_code!(block, "%AB100*%\nD10*\nX0Y0D03*\nX10000Y0D03*\n%AB*%\n");
+
+        let content = vec![DCode::SelectAperture(10).into(), MCode::EndOfFile.into()];
+        assert!(matches!(
+            make_aperture_block(100, content),
+            Err(GerberError::Located(error))
+                if error.index == 1 && matches!(error.error, GerberError::ValidationError(_))
+        ));
+        let content = vec![Unit::Millimeters.into()];
+        assert!(make_aperture_block(100, content).is_err());
+
+        let inner = make_aperture_block(11, vec![DCode::SelectAperture(10).into()]).unwrap();
+        let block = make_aperture_block(100, inner.clone().into_commands()).unwrap();
+        assert_code!(block, "%AB100*%\n%AB11*%\nD10*\n%AB*%\n%AB*%\n");
+        let mut content = inner.into_commands();
+        content.pop();
+        assert!(make_aperture_block(100, content).is_err());
+    }
+
+    #[test]
+    fn test_panel_builder() {
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 4);
+        let board: Vec<Command> = vec![
+            ExtendedCode::FileAttribute(FileAttribute::Part(Part::Single)).into(),
+            cf.into(),
+            Unit::Millimeters.into(),
+            ExtendedCode::ApertureDefinition(ApertureDefinition::new(
+                10,
+                Aperture::Circle(Circle::new(0.5)),
+            ))
+            .into(),
+            GCode::Comment(CommentContent::String("Pads".to_string())).into(),
+            ExtendedCode::ApertureBlock(ApertureBlock::Open { code: 20 }).into(),
+            DCode::SelectAperture(10).into(),
+            DCode::Operation(Operation::Flash(Some(Coordinates::new(0, 0, cf)))).into(),
+            DCode::Operation(Operation::Flash(Some(Coordinates::new(1, 0, cf)))).into(),
+            ExtendedCode::ApertureBlock(ApertureBlock::Close).into(),
+            DCode::SelectAperture(10).into(),
+            DCode::Operation(Operation::Move(Some(Coordinates::new(0, 0, cf)))).into(),
+            DCode::Operation(Operation::Interpolate(
+                Some(Coordinates::new(5, 0, cf)),
+                None,
+            ))
+            .into(),
+            DCode::SelectAperture(20).into(),
+            DCode::Operation(Operation::Flash(Some(Coordinates::new(1, 1, cf)))).into(),
+            MCode::EndOfFile.into(),
+        ];
+
+        let panel = PanelBuilder::new(2, 2, 20.0, 15.0).build(&board).unwrap();
+        assert_code!(
+            panel,
+            "%TF.Part,FabricationPanel*%\n\
+             %FSLAX24Y24*%\n\
+             %MOMM*%\n\
+             %ADD10C,0.5*%\n\
+             G04 Pads*\n\
+             %AB20*%\n\
+             D10*\n\
+             X0Y0D03*\n\
+             X10000Y0D03*\n\
+             %AB*%\n\
+             %SRX2Y2I20J15*%\n\
+             D10*\n\
+             X0Y0D02*\n\
+             X50000Y0D01*\n\
+             D20*\n\
+             X10000Y10000D03*\n\
+             %SR*%\n\
+             M02*\n"
+        );
+
+        assert!(PanelBuilder::new(0, 2, 20.0, 15.0).build(&board).is_err());
+    }
+
+    #[test]
+    fn test_panel_builder_aperture_attributes() {
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 4);
+        let board: Vec<Command> = vec![
+            cf.into(),
+            Unit::Millimeters.into(),
+            ExtendedCode::ApertureAttribute(ApertureAttribute::ApertureFunction(
+                ApertureFunction::ViaPad,
+            ))
+            .into(),
+            ApertureDefinition::new(10, Aperture::Circle(Circle::new(0.5))).into(),
+            ExtendedCode::DeleteAttribute(
+                AttributeDeletionCriterion::AllApertureAndObjectAttributes,
+            )
+            .into(),
+            ApertureDefinition::new(11, Aperture::Circle(Circle::new(0.8))).into(),
+            DCode::SelectAperture(10).into(),
+            DCode::Operation(Operation::Flash(Some(Coordinates::new(0, 0, cf)))).into(),
+            ExtendedCode::DeleteAttribute(
+                AttributeDeletionCriterion::AllApertureAndObjectAttributes,
+            )
+            .into(),
+            MCode::EndOfFile.into(),
+        ];
+
+        let panel = PanelBuilder::new(2, 1, 20.0, 0.0).build(&board).unwrap();
+        assert_code!(
+            panel,
+            "%TF.Part,FabricationPanel*%\n\
+             %FSLAX24Y24*%\n\
+             %MOMM*%\n\
+             %TA.AperFunction,ViaPad*%\n\
+             %ADD10C,0.5*%\n\
+             %TD*%\n\
+             %ADD11C,0.8*%\n\
+             %SRX2Y1I20J0*%\n\
+             D10*\n\
+             X0Y0D03*\n\
+             %TD*%\n\
+             %SR*%\n\
+             M02*\n"
+        );
+    }
+
+    #[test]
+    fn test_merge_streams() {
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 4);
+        let single_box = |origin: f64| -> Vec<Command> {
+            let mut commands = vec![
+                cf.into(),
+                Unit::Millimeters.into(),
+                ApertureDefinition::new(10, Aperture::Circle(Circle::new(0.1))).into(),
+                DCode::SelectAperture(10).into(),
+            ];
+            commands.append(&mut box_outline(origin, cf));
+            commands.push(MCode::EndOfFile.into());
+            commands
+        };
+
+        let merged = merge_streams(single_box(0.0), single_box(2.0)).unwrap();
+        assert_code!(
+            merged,
+            "%FSLAX24Y24*%\n\
+             %MOMM*%\n\
+             %ADD10C,0.1*%\n\
+             D10*\n\
+             X0Y0D02*\n\
+             G01*\n\
+             X10000Y0D01*\n\
+             X10000Y10000D01*\n\
+             X0Y10000D01*\n\
+             X0Y0D01*\n\
+             %ADD11C,0.1*%\n\
+             D11*\n\
+             X20000Y0D02*\n\
+             G01*\n\
+             X30000Y0D01*\n\
+             X30000Y10000D01*\n\
+             X20000Y10000D01*\n\
+             X20000Y0D01*\n\
+             M02*\n"
+        );
+
+        let mut inches = single_box(0.0);
+        inches[1] = Unit::Inches.into();
+        assert!(merge_streams(single_box(0.0), inches).is_err());
+    }
+
+    fn box_outline(origin: f64, cf: CoordinateFormat) -> Vec<Command> {
+        let mut commands = vec![
+            DCode::Operation(Operation::Move(Some(coordinates(origin, 0.0, cf).unwrap()))).into(),
+            GCode::InterpolationMode(InterpolationMode::Linear).into(),
+        ];
+        for (x, y) in [(1.0, 0.0), (1.0, 1.0), (0.0, 1.0), (0.0, 0.0)].iter() {
+            commands.push(
+                DCode::Operation(Operation::Interpolate(
+                    Some(coordinates(origin + x, *y, cf).unwrap()),
+                    None,
+                ))
+                .into(),
+            );
+        }
+        commands
+    }
+
+    #[test]
+    fn test_drill_map() {
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 4);
+        let commands = drill_map(
+            vec![
+                (0.0, 0.0, 0.3),
+                (1.0, 0.0, 0.8),
+                (2.0, 0.0, 0.3),
+                (3.0, 0.0, 1.0),
+            ],
+            cf,
+        )
+        .unwrap();
+        assert_code!(
+            commands,
+            "%TF.FileFunction,Drillmap*%\n\
+             %ADD10C,0.3*%\n\
+             %ADD11R,0.8X0.8*%\n\
+             %ADD12P,1X3X90*%\n\
+             D10*\n\
+             X0Y0D03*\n\
+             X20000Y0D03*\n\
+             D11*\n\
+             X10000Y0D03*\n\
+             D12*\n\
+             X30000Y0D03*\n"
+        );
+
+        assert!(matches!(
+            drill_map(vec![(0.0, 0.0, -1.0)], cf),
+            Err(GerberError::RangeError(_))
+        ));
+    }
+
+    #[test]
+    fn test_paste_layer() {
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 4);
+        let pad = Aperture::Rectangle(Rectangular::new(1.0, 0.5));
+        let commands = paste_layer(
+            vec![
+                (pad.clone(), (0.0, 0.0)),
+                (Aperture::Circle(Circle::new(2.0)), (1.0, 0.0)),
+                (pad, (2.0, 0.0)),
+            ],
+            0.1,
+            Position::Top,
+            cf,
+        )
+        .unwrap();
+        assert_code!(
+            commands,
+            "%TF.FileFunction,Paste,Top*%\n\
+             %ADD10R,0.9X0.45*%\n\
+             %ADD11C,1.8*%\n\
+             D10*\n\
+             X0Y0D03*\n\
+             X20000Y0D03*\n\
+             D11*\n\
+             X10000Y0D03*\n"
+        );
+
+        assert!(matches!(
+            paste_layer(Vec::new(), 1.0, Position::Bottom, cf),
+            Err(GerberError::RangeError(_))
+        ));
+        assert!(paste_layer(Vec::new(), -0.1, Position::Bottom, cf).is_err());
+        assert!(matches!(
+            paste_layer(
+                vec![(Aperture::Macro("PAD".to_string(), None), (0.0, 0.0))],
+                0.1,
+                Position::Top,
+                cf
+            ),
+            Err(GerberError::ValidationError(_))
+        ));
+    }
+
+    #[test]
+    fn test_keepout_layer() {
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 4);
+        let commands = keepout_layer(
+            Position::Top,
+            vec![vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0)]],
+            cf,
+        )
+        .unwrap();
+        assert_code!(
+            commands,
+            "%TF.FileFunction,Keepout,Top*%\n\
+             G36*\n\
+             X0Y0D02*\n\
+             G01*\n\
+             X10000Y0D01*\n\
+             X10000Y10000D01*\n\
+             X0Y0D01*\n\
+             G37*\n"
+        );
+
+        assert!(keepout_layer(Position::Top, vec![vec![(0.0, 0.0), (1.0, 0.0)]], cf).is_err());
+    }
+
+    #[test]
+    fn test_profile_layer() {
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 4);
+        let commands = profile_layer(
+            Some(Profile::NonPlated),
+            vec![(0.0, 0.0), (10.0, 0.0), (10.0, 5.0), (0.0, 5.0)],
+            cf,
+        )
+        .unwrap();
+        assert_code!(
+            commands,
+            "%TF.FileFunction,Profile,NP*%\n\
+             %TA.AperFunction,Profile*%\n\
+             %ADD10C,0*%\n\
+             %TD.AperFunction*%\n\
+             D10*\n\
+             X0Y0D02*\n\
+             G01*\n\
+             X100000Y0D01*\n\
+             X100000Y50000D01*\n\
+             X0Y50000D01*\n\
+             X0Y0D01*\n"
+        );
+    }
+
+    #[test]
+    fn test_empty_gerber() {
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 4, 6);
+        let commands = empty_gerber(cf, Unit::Millimeters);
+        assert_eq!(commands.len(), 3);
+        assert_code!(commands, "%FSLAX46Y46*%\n%MOMM*%\nM02*\n");
+    }
+
+    #[test]
+    fn test_macro_library() {
+        let macros = vec![
+            ApertureMacro::new("CIRC").add_content(CirclePrimitive::new(MacroDecimal::Variable(1))),
+            ApertureMacro::new("RECT").add_content(CenterLinePrimitive::new((
+                MacroDecimal::Variable(1),
+                MacroDecimal::Variable(2),
+            ))),
+        ];
+        let commands = macro_library(&macros);
+        assert_eq!(commands.len(), 3);
+        assert!(matches!(
+            commands[0],
+            Command::FunctionCode(FunctionCode::GCode(GCode::Comment(_)))
+        ));
+        assert!(commands[1..].iter().all(|command| matches!(
+            command,
+            Command::ExtendedCode(ExtendedCode::ApertureMacro(_))
+        )));
+        assert_code!(
+            commands,
+            "G04 Aperture macro library*\n\
+             %AMCIRC*\n1,1,$1,0,0*%\n\
+             %AMRECT*\n21,1,$1,$2,0,0,0*%\n"
+        );
+    }
+
+    #[test]
+    fn test_draw_circle_invalid_radius() {
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 4);
+        assert!(draw_circle(0.0, 0.0, 0.0, cf).is_err());
+        assert!(draw_circle(0.0, 0.0, f64::NAN, cf).is_err());
+    }
+}
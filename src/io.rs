@@ -0,0 +1,140 @@
+//! The writer that Gerber code is generated to.
+//!
+//! With the `std` feature (enabled by default) this is [`std::io::Write`]. Without it, a minimal
+//! replacement is used, which can be implemented for any byte sink, e.g. a file on a flash
+//! filesystem of an embedded target.
+
+#[cfg(feature = "std")]
+pub use std::io::{Error, Write};
+
+#[cfg(not(feature = "std"))]
+pub use self::no_std::{Error, Write};
+
+#[cfg(not(feature = "std"))]
+mod no_std {
+    use alloc::vec::Vec;
+    use core::fmt;
+
+    /// An error of a [`Write`] implementation.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Error {
+        /// The writer did not accept any more bytes.
+        WriteZero,
+        /// A value could not be formatted.
+        Formatter,
+        /// Any other error of the writer.
+        Other(&'static str),
+    }
+
+    impl fmt::Display for Error {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Error::WriteZero => write!(f, "failed to write the whole buffer"),
+                Error::Formatter => write!(f, "formatter error"),
+                Error::Other(message) => write!(f, "{}", message),
+            }
+        }
+    }
+
+    // `core::error::Error` is stable since Rust 1.81, which is the minimum version without `std`.
+    impl core::error::Error for Error {}
+
+    /// A byte sink, the `no_std` counterpart of `std::io::Write`.
+    pub trait Write {
+        /// Writes some of the bytes of `buf` and returns how many were written.
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Error>;
+
+        fn flush(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn write_all(&mut self, mut buf: &[u8]) -> Result<(), Error> {
+            while !buf.is_empty() {
+                match self.write(buf)? {
+                    0 => return Err(Error::WriteZero),
+                    written => buf = &buf[written..],
+                }
+            }
+            Ok(())
+        }
+
+        /// Used by the `write!` macro.
+        fn write_fmt(&mut self, args: fmt::Arguments<'_>) -> Result<(), Error> {
+            struct Adapter<'a, W: ?Sized> {
+                inner: &'a mut W,
+                error: Option<Error>,
+            }
+
+            impl<W: Write + ?Sized> fmt::Write for Adapter<'_, W> {
+                fn write_str(&mut self, s: &str) -> fmt::Result {
+                    self.inner.write_all(s.as_bytes()).map_err(|error| {
+                        self.error = Some(error);
+                        fmt::Error
+                    })
+                }
+            }
+
+            let mut adapter = Adapter {
+                inner: self,
+                error: None,
+            };
+            fmt::write(&mut adapter, args).map_err(|_| adapter.error.unwrap_or(Error::Formatter))
+        }
+    }
+
+    impl Write for Vec<u8> {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+            self.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+    }
+
+    impl<W: Write + ?Sized> Write for &mut W {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+            (**self).write(buf)
+        }
+
+        fn flush(&mut self) -> Result<(), Error> {
+            (**self).flush()
+        }
+    }
+}
+
+#[cfg(all(test, not(feature = "std")))]
+mod test {
+    use super::*;
+    use crate::traits::GerberCode;
+    use crate::{Command, CommentContent, FunctionCode, GCode};
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_write_fmt() {
+        let mut buf = Vec::new();
+        write!(buf, "X{}Y{}", 1, -2).unwrap();
+        assert_eq!(buf, b"X1Y-2");
+    }
+
+    #[test]
+    fn test_serialize_to_vec() {
+        let command = Command::FunctionCode(FunctionCode::GCode(GCode::Comment(
+            CommentContent::String("no_std".into()),
+        )));
+        let mut buf = Vec::new();
+        command.serialize(&mut buf).unwrap();
+        assert_eq!(buf, b"G04 no_std*\n");
+    }
+
+    struct Full;
+
+    impl Write for Full {
+        fn write(&mut self, _buf: &[u8]) -> Result<usize, Error> {
+            Ok(0)
+        }
+    }
+
+    #[test]
+    fn test_write_zero() {
+        assert_eq!(Full.write_all(b"G04*"), Err(Error::WriteZero));
+        assert_eq!(write!(Full, "{}", 1), Err(Error::WriteZero));
+    }
+}
@@ -16,44 +16,134 @@
 //!   terminated with a newline character.
 //! - `PartialGerberCode` (internal only) generates Gerber representation of a
 //!   value, but does not represent a full line of code.
+//!
+//! ## Features
+//!
+//! - `std` (enabled by default): Without it, the crate is `no_std` and only needs `alloc`. Code
+//!   is then generated to the minimal [`io::Write`] trait of this crate instead of
+//!   `std::io::Write`. Parsing, the generators, the analysis helpers and the writers need `std`.
+//!   Building without `std` requires Rust 1.81 or newer.
+#![cfg_attr(not(feature = "std"), no_std)]
 #![allow(clippy::new_without_default)]
 
-#[cfg(test)]
+extern crate alloc;
+
+#[cfg(all(test, feature = "std"))]
 #[macro_use]
 mod test_macros;
 
 #[macro_use]
 mod serialization_macros;
 
+#[cfg(feature = "std")]
+mod analysis;
 mod attributes;
 mod codegen;
 mod coordinates;
+mod document;
 mod errors;
+#[cfg(feature = "excellon")]
+mod excellon;
 mod extended_codes;
 mod function_codes;
+#[cfg(feature = "std")]
+mod generators;
+pub mod io;
 mod macros;
+mod optimize;
+#[cfg(feature = "std")]
+mod parse;
+#[cfg(feature = "std")]
+mod session;
 mod traits;
+mod transforms;
 mod types;
+mod validation;
+#[cfg(feature = "std")]
+mod writer;
+
+/// The items of the `std` prelude that the modules which also build without `std` need.
+mod prelude {
+    pub use alloc::boxed::Box;
+    pub use alloc::string::{String, ToString};
+    pub use alloc::vec::Vec;
+    pub use alloc::{format, vec};
+}
 
+#[cfg(feature = "std")]
+pub use crate::analysis::*;
 pub use crate::attributes::*;
 pub use crate::coordinates::*;
+pub use crate::document::*;
 pub use crate::errors::*;
+#[cfg(feature = "excellon")]
+pub use crate::excellon::*;
 pub use crate::extended_codes::*;
 pub use crate::function_codes::*;
+#[cfg(feature = "std")]
+pub use crate::generators::*;
 pub use crate::macros::*;
-pub use crate::traits::GerberCode;
+pub use crate::optimize::*;
+#[cfg(feature = "std")]
+pub use crate::parse::*;
+#[cfg(feature = "std")]
+pub use crate::session::*;
+pub use crate::traits::{GerberCode, GerberCodeExt, SerializationOptions, StrictValidation};
+pub use crate::transforms::*;
 pub use crate::types::*;
+pub use crate::validation::*;
+#[cfg(feature = "std")]
+pub use crate::writer::*;
 
 // re-export some types
 pub use uuid::Uuid;
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod serialization_tests {
     use super::traits::PartialGerberCode;
     use super::*;
+    use std::fmt::Write as _;
     use std::io::BufWriter;
     use uuid::Uuid;
 
+    #[test]
+    fn test_to_gerber_string() {
+        let commands: Vec<Command> = vec![
+            GCode::Comment(CommentContent::String("hello".to_string())).into(),
+            ExtendedCode::Unit(Unit::Millimeters).into(),
+            MCode::EndOfFile.into(),
+        ];
+        assert_eq!(
+            commands.to_gerber_string().unwrap(),
+            "G04 hello*\n%MOMM*%\nM02*\n"
+        );
+        assert_eq!(
+            MCode::EndOfFile.to_gerber_bytes().unwrap(),
+            b"M02*\n".to_vec()
+        );
+
+        let empty_macro = ApertureMacro::new("EMPTY");
+        assert!(ExtendedCode::ApertureMacro(empty_macro)
+            .to_gerber_string()
+            .is_err());
+    }
+
+    #[test]
+    fn test_display() {
+        let comment: Command = GCode::Comment(CommentContent::String("hello".to_string())).into();
+        assert_eq!(comment.to_string(), "G04 hello*");
+        assert_eq!(
+            format!("{}", ExtendedCode::Unit(Unit::Millimeters)),
+            "%MOMM*%"
+        );
+        assert_eq!(MCode::EndOfFile.to_string(), "M02*");
+        assert_eq!(DCode::SelectAperture(10).to_string(), "D10*");
+
+        let empty_macro = ApertureMacro::new("EMPTY");
+        let mut out = String::new();
+        assert!(write!(out, "{}", ExtendedCode::ApertureMacro(empty_macro)).is_err());
+    }
+
     #[test]
     fn test_comment() {
         //! The serialize method of the GerberCode trait should generate strings.
@@ -63,6 +153,7 @@ mod serialization_tests {
 
     /// `standard comment` is a term defined in the gerber spec. See `2024.05 4.1 Comment (G04)`
     #[test]
+    #[allow(clippy::mixed_attributes_style)]
     fn test_standard_comment_with_standard_attributes() {
         //! Attributes should be able to be stored in G04 comments starting with `#@!`
         let comment = GCode::Comment(CommentContent::Standard(
@@ -122,6 +213,7 @@ mod serialization_tests {
     }
 
     #[test]
+    #[allow(clippy::vec_init_then_push)]
     fn test_vec_of_comments() {
         //! A `Vec<T: GerberCode>` should also implement `GerberCode`.
         let mut v = Vec::new();
@@ -134,6 +226,33 @@ mod serialization_tests {
         assert_code!(v, "G04 comment 1*\nG04 another one*\n");
     }
 
+    #[test]
+    fn test_slice_and_array_of_commands() {
+        //! Slices and arrays of `T: GerberCode` should also implement `GerberCode`.
+        let commands: [Command; 2] = [
+            GCode::Comment(CommentContent::String("comment 1".to_string())).into(),
+            MCode::EndOfFile.into(),
+        ];
+        assert_code!(commands, "G04 comment 1*\nM02*\n");
+
+        let slice: &[Command] = &commands[1..];
+        assert_code!(slice, "M02*\n");
+        assert_eq!(slice.to_gerber_string().unwrap(), "M02*\n");
+
+        let mut buf = Vec::new();
+        commands.serialize_strict(&mut buf).unwrap();
+        assert_eq!(buf, b"G04 comment 1*\nM02*\n");
+        let invalid = [Command::from(DCode::SelectAperture(5))];
+        assert!(invalid[..].serialize_strict(&mut Vec::new()).is_err());
+
+        fn serialize_all<G: GerberCode<Vec<u8>>>(code: G) -> Vec<u8> {
+            let mut buf = Vec::new();
+            code.serialize(&mut buf).unwrap();
+            buf
+        }
+        assert_eq!(serialize_all(&commands[..1]), b"G04 comment 1*\n");
+    }
+
     #[test]
     fn test_single_command() {
         //! A `Command` should implement `GerberCode`
@@ -156,14 +275,16 @@ mod serialization_tests {
     }
 
     #[test]
+    #[allow(clippy::vec_init_then_push)]
     fn test_region_mode() {
         let mut commands = Vec::new();
-        commands.push(GCode::RegionMode(true));
-        commands.push(GCode::RegionMode(false));
+        commands.push(GCode::RegionMode(true.into()));
+        commands.push(GCode::RegionMode(false.into()));
         assert_code!(commands, "G36*\nG37*\n");
     }
 
     #[test]
+    #[allow(clippy::vec_init_then_push)]
     fn test_quadrant_mode() {
         let mut commands = Vec::new();
         commands.push(GCode::QuadrantMode(QuadrantMode::Single));
@@ -348,6 +469,7 @@ mod serialization_tests {
         }
 
         #[test]
+        #[allow(deprecated)]
         fn test_macro_definition() {
             let m1 = ApertureDefinition {
                 code: 42,
@@ -367,6 +489,23 @@ mod serialization_tests {
             assert_partial_code!(m1, "42NO_ARGS1");
             assert_partial_code!(m2, "69With_Args2,$1X0.25X$1x$2");
         }
+
+        #[test]
+        fn test_macro_definition_with_expr() {
+            let m = ApertureDefinition {
+                code: 69,
+                aperture: Aperture::Macro(
+                    "With_Args2".to_string(),
+                    Some(vec![
+                        MacroDecimal::Variable(1),
+                        MacroDecimal::Expr(
+                            MacroExpression::Variable(1) * MacroExpression::Variable(2),
+                        ),
+                    ]),
+                ),
+            };
+            assert_partial_code!(m, "69With_Args2,$1X$1x$2");
+        }
     }
 
     #[test]
@@ -716,7 +855,7 @@ mod serialization_tests {
                     fn $test() {
                         let func = ExtendedCode::FileAttribute(FileAttribute::FileFunction(
                             FileFunction::$ff {
-                                pos: Position::Top,
+                                pos: ExtendedPosition::Top,
                                 layer: 1,
                             },
                         ));
@@ -724,7 +863,15 @@ mod serialization_tests {
 
                         let func = ExtendedCode::FileAttribute(FileAttribute::FileFunction(
                             FileFunction::$ff {
-                                pos: Position::Bottom,
+                                pos: ExtendedPosition::Inner,
+                                layer: 2,
+                            },
+                        ));
+                        assert_code!(func, &format!("%TF.FileFunction,{},L{},Inr*%\n", $value, 2));
+
+                        let func = ExtendedCode::FileAttribute(FileAttribute::FileFunction(
+                            FileFunction::$ff {
+                                pos: ExtendedPosition::Bottom,
                                 layer: 2,
                             },
                         ));
@@ -1685,4 +1832,166 @@ mod serialization_tests {
         });
         assert_code!(value, "%INPANEL_1*%\n");
     }
+
+    #[test]
+    fn test_cow() {
+        use std::borrow::Cow;
+
+        let comment = Command::from(GCode::Comment(CommentContent::String("test".to_string())));
+        let borrowed: Cow<Command> = Cow::Borrowed(&comment);
+        assert_code!(borrowed, "G04 test*\n");
+        let owned: Cow<Command> = Cow::Owned(comment.clone());
+        assert_code!(owned, "G04 test*\n");
+
+        let commands: Vec<Cow<Command>> =
+            vec![Cow::Borrowed(&comment), Cow::Owned(comment.clone())];
+        assert_code!(commands, "G04 test*\nG04 test*\n");
+    }
+
+    #[test]
+    fn test_no_trailing_newline() {
+        let commands: Vec<Command> = vec![
+            ExtendedCode::Unit(Unit::Millimeters).into(),
+            FunctionCode::GCode(GCode::Comment(CommentContent::String("test".to_string()))).into(),
+            ExtendedCode::LoadPolarity(Polarity::Dark).into(),
+        ];
+
+        let mut buf = Vec::new();
+        commands
+            .serialize_with(&mut buf, &SerializationOptions::default())
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "%MOMM*%\nG04 test*\n%LPD*%\n"
+        );
+
+        let options = SerializationOptions {
+            no_trailing_newline: true,
+            ..Default::default()
+        };
+        let mut buf = Vec::new();
+        commands.serialize_with(&mut buf, &options).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), "%MOMM*%G04 test*\n%LPD*%");
+    }
+
+    #[test]
+    fn test_raw_attribute_fields() {
+        let commands: Vec<Command> = vec![
+            ExtendedCode::ObjectAttribute(ObjectAttribute::Component("R,1".to_string())).into(),
+            GCode::Comment(CommentContent::Standard(StandardComment::ObjectAttribute(
+                ObjectAttribute::Component("R%2".to_string()),
+            )))
+            .into(),
+        ];
+        assert_code!(commands, "%TO.C,R\\u002C1*%\nG04 #@! TO.C,R\\u00252*\n");
+
+        let options = SerializationOptions {
+            raw_attribute_fields: true,
+            ..Default::default()
+        };
+        let mut buf = Vec::new();
+        commands.serialize_with(&mut buf, &options).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "%TO.C,R,1*%\nG04 #@! TO.C,R%2*\n"
+        );
+
+        // A field that contains an escape sequence literally is written as is
+        let commands: Vec<Command> = vec![
+            ExtendedCode::ObjectAttribute(ObjectAttribute::Component("R\\u002C1".to_string()))
+                .into(),
+            GCode::Comment(CommentContent::Standard(StandardComment::ObjectAttribute(
+                ObjectAttribute::Component("R\\u00252".to_string()),
+            )))
+            .into(),
+        ];
+        let mut buf = Vec::new();
+        commands.serialize_with(&mut buf, &options).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "%TO.C,R\\u002C1*%\nG04 #@! TO.C,R\\u00252*\n"
+        );
+    }
+
+    #[test]
+    fn test_explicit_positive_sign() {
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 5);
+        let commands: Vec<Command> = vec![
+            DCode::Operation(Operation::Move(Some(Coordinates::new(1, 0, cf)))).into(),
+            DCode::Operation(Operation::Interpolate(
+                Some(Coordinates::new(-1, 2, cf)),
+                Some(CoordinateOffset::new(1, 0, cf)),
+            ))
+            .into(),
+            DCode::SelectAperture(10).into(),
+        ];
+
+        let mut buf = Vec::new();
+        commands
+            .serialize_with(&mut buf, &SerializationOptions::default())
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "X100000Y0D02*\nX-100000Y200000I100000J0D01*\nD10*\n"
+        );
+
+        let options = SerializationOptions {
+            explicit_positive_sign: true,
+            ..Default::default()
+        };
+        let mut buf = Vec::new();
+        commands.serialize_with(&mut buf, &options).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "X+100000Y0D02*\nX-100000Y+200000I+100000J0D01*\nD10*\n"
+        );
+    }
+
+    #[test]
+    fn test_aperture_block_definition_serialize_with() {
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 5);
+        let block = ApertureBlockDefinition {
+            code: 20,
+            commands: vec![
+                ExtendedCode::LoadPolarity(Polarity::Dark).into(),
+                DCode::Operation(Operation::Flash(Some(Coordinates::new(1, -1, cf)))).into(),
+            ],
+        };
+        assert_code!(block, "%AB20*%\n%LPD*%\nX100000Y-100000D03*\n%AB*%\n");
+
+        let options = SerializationOptions {
+            no_trailing_newline: true,
+            explicit_positive_sign: true,
+            ..Default::default()
+        };
+        let mut buf = Vec::new();
+        block.serialize_with(&mut buf, &options).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "%AB20*%%LPD*%X+100000Y-100000D03*\n%AB*%"
+        );
+    }
+
+    #[test]
+    fn test_nested_aperture_block_definition() {
+        let inner = ApertureBlockDefinition {
+            code: 11,
+            commands: vec![DCode::SelectAperture(10).into()],
+        };
+        let outer = ApertureBlockDefinition {
+            code: 12,
+            commands: vec![inner.clone().into(), DCode::SelectAperture(11).into()],
+        };
+        let command: Command = outer.clone().into();
+        assert_code!(
+            vec![command],
+            "%AB12*%\n%AB11*%\nD10*\n%AB*%\nD11*\n%AB*%\n"
+        );
+
+        let mut flat = vec![ExtendedCode::ApertureBlock(ApertureBlock::Open { code: 12 }).into()];
+        flat.extend(inner.into_commands());
+        flat.push(DCode::SelectAperture(11).into());
+        flat.push(ExtendedCode::ApertureBlock(ApertureBlock::Close).into());
+        assert_eq!(outer.into_commands(), flat);
+    }
 }
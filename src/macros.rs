@@ -1,12 +1,16 @@
 //! Aperture Macros.
 
-use std::convert::From;
-use std::io::Write;
+use core::convert::From;
+use core::fmt;
+use core::ops;
 
 use crate::errors::{GerberError, GerberResult};
+use crate::io::Write;
+use crate::prelude::*;
 use crate::traits::PartialGerberCode;
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ApertureMacro {
     pub name: String,
     pub content: Vec<MacroContent>,
@@ -34,6 +38,90 @@ impl ApertureMacro {
     {
         self.content.push(c.into());
     }
+
+    /// Adds a comment (primitive code 0).
+    pub fn with_comment<S: Into<String>>(mut self, comment: S) -> Self {
+        self.content.push(MacroContent::Comment(comment.into()));
+        self
+    }
+
+    /// Returns the macro if its name and variables are valid.
+    ///
+    /// The name must match `[A-Za-z_.$][A-Za-z0-9_.$]*`. Every variable `$n` that is referenced
+    /// must either be one of the `arguments` the macro is used with, i.e. `n <= arguments`, or
+    /// be defined by a variable definition before it is referenced.
+    pub fn try_build(self, arguments: u32) -> GerberResult<ApertureMacro> {
+        let is_name_char = |c: char| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '$');
+        let mut chars = self.name.chars();
+        let valid_name = matches!(chars.next(), Some(c) if is_name_char(c) && !c.is_ascii_digit())
+            && chars.all(is_name_char);
+        if !valid_name {
+            return Err(GerberError::ValidationError(format!(
+                "Invalid aperture macro name {:?}",
+                self.name
+            )));
+        }
+
+        let mut defined: Vec<u32> = Vec::new();
+        for content in &self.content {
+            let referenced = match content {
+                MacroContent::Comment(_) => continue,
+                MacroContent::VariableDefinition(definition) => {
+                    referenced_variables(&definition.expression)
+                }
+                _ => {
+                    let mut code = Vec::new();
+                    content.serialize_partial(&mut code)?;
+                    referenced_variables(&String::from_utf8_lossy(&code))
+                }
+            };
+            if let Some(variable) = referenced
+                .into_iter()
+                .find(|variable| *variable > arguments && !defined.contains(variable))
+            {
+                return Err(GerberError::ValidationError(format!(
+                    "Aperture macro {} references the undefined variable ${}",
+                    self.name, variable
+                )));
+            }
+            if let MacroContent::VariableDefinition(definition) = content {
+                defined.push(definition.number);
+            }
+        }
+        Ok(self)
+    }
+
+    /// Returns a key that is equal for macros with the same primitives and variable definitions,
+    /// regardless of their name or comments.
+    ///
+    /// The key is built from the Gerber code of the content, so macros that differ only in how
+    /// their values are represented (e.g. [`MacroDecimal::Expression`] and [`MacroDecimal::Expr`])
+    /// get the same key. `ApertureMacro` cannot implement `Hash` due to the use of `f64`, use this
+    /// key instead when deduplicating macros.
+    pub fn structural_key(&self) -> GerberResult<String> {
+        let mut code = Vec::new();
+        for content in &self.content {
+            if matches!(content, MacroContent::Comment(_)) {
+                continue;
+            }
+            content.serialize_partial(&mut code)?;
+            code.push(b'\n');
+        }
+        Ok(String::from_utf8_lossy(&code).into_owned())
+    }
+}
+
+/// The numbers of the variables (`$n`) in macro code.
+fn referenced_variables(code: &str) -> Vec<u32> {
+    code.split('$')
+        .skip(1)
+        .filter_map(|rest| {
+            let digits = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            rest[..digits].parse().ok()
+        })
+        .collect()
 }
 
 impl<W: Write> PartialGerberCode<W> for ApertureMacro {
@@ -58,6 +146,7 @@ impl<W: Write> PartialGerberCode<W> for ApertureMacro {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// A macro decimal can either be an f64 or a variable placeholder.
 pub enum MacroDecimal {
     /// A decimal value.
@@ -70,15 +159,76 @@ pub enum MacroDecimal {
     /// "The standard arithmetic precedence rules apply". i.e. *not* Rust/C precedence rules.
     /// Allowed elements: "integer and decimal constants, other variables, arithmetic operators and the brackets '(' and ')'"
     /// Allowed operators: '+', '-', 'x' (lower case), '/'.
+    #[deprecated(
+        since = "0.8.0",
+        note = "Use `MacroDecimal::Expr` with a `MacroExpression` instead"
+    )]
     Expression(String),
+    /// An arithmetic expression, see [`MacroExpression`].
+    Expr(MacroExpression),
 }
 
 impl MacroDecimal {
+    #[allow(deprecated)]
     fn is_negative(&self) -> bool {
         match *self {
             MacroDecimal::Value(v) => v < 0.0,
             MacroDecimal::Variable(_) => false,
             MacroDecimal::Expression(_) => false,
+            MacroDecimal::Expr(ref expression) => {
+                matches!(expression.constant_value(), Some(value) if value < 0.0)
+            }
+        }
+    }
+
+    /// Parses a single macro argument or primitive parameter, e.g. `0.25`, `$1` or `$1x$2`.
+    ///
+    /// Expressions are parsed into a [`MacroExpression`], as [`MacroDecimal::Expr`].
+    pub fn parse(token: &str) -> GerberResult<MacroDecimal> {
+        let token = token.trim();
+        if token.is_empty() {
+            return Err(GerberError::ConversionError(
+                "A macro decimal must not be empty".into(),
+            ));
+        }
+
+        if let Some(number) = token.strip_prefix('$') {
+            if !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()) {
+                let variable = number.parse::<u32>().map_err(|_| {
+                    GerberError::ConversionError(format!("Invalid macro variable: {}", token))
+                })?;
+                return Ok(MacroDecimal::Variable(variable));
+            }
+        }
+
+        if is_decimal(token) {
+            let value = token.parse::<f64>().map_err(|_| {
+                GerberError::ConversionError(format!("Invalid macro decimal: {}", token))
+            })?;
+            return Ok(MacroDecimal::Value(value));
+        }
+
+        MacroExpression::parse(token)
+            .map(MacroDecimal::Expr)
+            .map_err(|_| GerberError::ConversionError(format!("Invalid macro decimal: {}", token)))
+    }
+}
+
+/// Gerber specification (2021.02 - 2024.05) 3.4.2 Decimals
+/// `[+-]?((([0-9]+)(\.[0-9]*)?)|(\.[0-9]+))`
+fn is_decimal(token: &str) -> bool {
+    let unsigned = token.strip_prefix(['+', '-']).unwrap_or(token);
+    let (integer, fraction) = match unsigned.split_once('.') {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (unsigned, None),
+    };
+    let all_digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+    match fraction {
+        None => !integer.is_empty() && all_digits(integer),
+        Some(fraction) => {
+            (!integer.is_empty() || !fraction.is_empty())
+                && all_digits(integer)
+                && all_digits(fraction)
         }
     }
 }
@@ -95,21 +245,295 @@ impl From<f64> for MacroDecimal {
     }
 }
 
+impl From<MacroExpression> for MacroDecimal {
+    fn from(val: MacroExpression) -> Self {
+        MacroDecimal::Expr(val)
+    }
+}
+
 impl<W: Write> PartialGerberCode<W> for MacroDecimal {
+    #[allow(deprecated)]
     fn serialize_partial(&self, writer: &mut W) -> GerberResult<()> {
         match *self {
             MacroDecimal::Value(ref v) => write!(writer, "{}", v)?,
             MacroDecimal::Variable(ref v) => write!(writer, "${}", v)?,
             MacroDecimal::Expression(ref v) => write!(writer, "{}", v)?,
+            MacroDecimal::Expr(ref v) => write!(writer, "{}", v)?,
+        };
+        Ok(())
+    }
+}
+
+/// An arithmetic expression in an aperture macro, e.g. `$1x0.75` or `$3+($4/2)`.
+///
+/// The operators are written with the standard arithmetic precedence of the Gerber
+/// specification (4.5.4.2 Arithmetic Expressions) and only the parentheses that are needed.
+/// Expressions can be built with the arithmetic operators:
+///
+/// ```
+/// use gerber_types::MacroExpression;
+///
+/// let expression = MacroExpression::Variable(1) * MacroExpression::Value(0.75);
+/// assert_eq!(expression.to_string(), "$1x0.75");
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MacroExpression {
+    Value(f64),
+    Variable(u32),
+    Add(Box<MacroExpression>, Box<MacroExpression>),
+    Sub(Box<MacroExpression>, Box<MacroExpression>),
+    Mul(Box<MacroExpression>, Box<MacroExpression>),
+    Div(Box<MacroExpression>, Box<MacroExpression>),
+    Neg(Box<MacroExpression>),
+}
+
+impl MacroExpression {
+    /// Parses an arithmetic expression, e.g. `$1x0.75` or `-$3+($4/2)`, with the standard
+    /// arithmetic precedence.
+    pub fn parse(token: &str) -> GerberResult<MacroExpression> {
+        let mut parser = ExpressionParser {
+            input: token.trim(),
+            position: 0,
+            depth: 0,
+        };
+        let expression = parser.expression()?;
+        if parser.position != parser.input.len() {
+            return Err(parser.error());
+        }
+        Ok(expression)
+    }
+
+    /// The value of an expression without variables.
+    pub fn constant_value(&self) -> Option<f64> {
+        match self {
+            MacroExpression::Value(value) => Some(*value),
+            MacroExpression::Variable(_) => None,
+            MacroExpression::Add(left, right) => {
+                Some(left.constant_value()? + right.constant_value()?)
+            }
+            MacroExpression::Sub(left, right) => {
+                Some(left.constant_value()? - right.constant_value()?)
+            }
+            MacroExpression::Mul(left, right) => {
+                Some(left.constant_value()? * right.constant_value()?)
+            }
+            MacroExpression::Div(left, right) => {
+                Some(left.constant_value()? / right.constant_value()?)
+            }
+            MacroExpression::Neg(operand) => Some(-operand.constant_value()?),
+        }
+    }
+
+    /// Whether the expression is written with a leading `-`.
+    fn is_negated(&self) -> bool {
+        match self {
+            MacroExpression::Value(value) => value.is_sign_negative(),
+            MacroExpression::Neg(_) => true,
+            _ => false,
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            MacroExpression::Add(..) | MacroExpression::Sub(..) => 1,
+            MacroExpression::Mul(..) | MacroExpression::Div(..) => 2,
+            _ if self.is_negated() => 3,
+            _ => 4,
+        }
+    }
+
+    fn fmt_operand(&self, f: &mut fmt::Formatter<'_>, parenthesize: bool) -> fmt::Result {
+        if parenthesize {
+            write!(f, "({})", self)
+        } else {
+            write!(f, "{}", self)
+        }
+    }
+}
+
+impl fmt::Display for MacroExpression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (left, operator, right) = match self {
+            MacroExpression::Value(value) => return write!(f, "{}", value),
+            MacroExpression::Variable(variable) => return write!(f, "${}", variable),
+            MacroExpression::Neg(operand) => {
+                write!(f, "-")?;
+                return operand.fmt_operand(f, operand.precedence() < 4);
+            }
+            MacroExpression::Add(left, right) => (left, "+", right),
+            MacroExpression::Sub(left, right) => (left, "-", right),
+            MacroExpression::Mul(left, right) => (left, "x", right),
+            MacroExpression::Div(left, right) => (left, "/", right),
         };
+        let precedence = self.precedence();
+        // Subtraction and division are not associative, `a-(b-c)` needs the parentheses.
+        let not_associative = matches!(self, MacroExpression::Sub(..) | MacroExpression::Div(..));
+        left.fmt_operand(f, left.precedence() < precedence)?;
+        write!(f, "{}", operator)?;
+        right.fmt_operand(
+            f,
+            right.precedence() < precedence
+                || (right.precedence() == precedence && not_associative)
+                || right.is_negated(),
+        )
+    }
+}
+
+impl<W: Write> PartialGerberCode<W> for MacroExpression {
+    fn serialize_partial(&self, writer: &mut W) -> GerberResult<()> {
+        write!(writer, "{}", self)?;
         Ok(())
     }
 }
 
+impl From<f64> for MacroExpression {
+    fn from(val: f64) -> Self {
+        MacroExpression::Value(val)
+    }
+}
+
+impl ops::Add for MacroExpression {
+    type Output = MacroExpression;
+    fn add(self, rhs: MacroExpression) -> MacroExpression {
+        MacroExpression::Add(Box::new(self), Box::new(rhs))
+    }
+}
+
+impl ops::Sub for MacroExpression {
+    type Output = MacroExpression;
+    fn sub(self, rhs: MacroExpression) -> MacroExpression {
+        MacroExpression::Sub(Box::new(self), Box::new(rhs))
+    }
+}
+
+impl ops::Mul for MacroExpression {
+    type Output = MacroExpression;
+    fn mul(self, rhs: MacroExpression) -> MacroExpression {
+        MacroExpression::Mul(Box::new(self), Box::new(rhs))
+    }
+}
+
+impl ops::Div for MacroExpression {
+    type Output = MacroExpression;
+    fn div(self, rhs: MacroExpression) -> MacroExpression {
+        MacroExpression::Div(Box::new(self), Box::new(rhs))
+    }
+}
+
+impl ops::Neg for MacroExpression {
+    type Output = MacroExpression;
+    fn neg(self) -> MacroExpression {
+        MacroExpression::Neg(Box::new(self))
+    }
+}
+
+/// A recursive descent parser for [`MacroExpression::parse`].
+struct ExpressionParser<'a> {
+    input: &'a str,
+    position: usize,
+    depth: usize,
+}
+
+impl<'a> ExpressionParser<'a> {
+    /// Limits the nesting of parentheses and signs, so that the recursion cannot overflow the
+    /// stack.
+    const MAX_DEPTH: usize = 64;
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.position..].chars().next()
+    }
+
+    fn error(&self) -> GerberError {
+        GerberError::ConversionError(format!("Invalid macro expression: {}", self.input))
+    }
+
+    fn take_while(&mut self, predicate: impl Fn(char) -> bool) -> &'a str {
+        let rest = &self.input[self.position..];
+        let end = rest.find(|c: char| !predicate(c)).unwrap_or(rest.len());
+        self.position += end;
+        &rest[..end]
+    }
+
+    /// `expression = term {('+' | '-') term}`
+    fn expression(&mut self) -> GerberResult<MacroExpression> {
+        let mut left = self.term()?;
+        while let Some(operator @ ('+' | '-')) = self.peek() {
+            self.position += 1;
+            let right = self.term()?;
+            left = match operator {
+                '+' => left + right,
+                _ => left - right,
+            };
+        }
+        Ok(left)
+    }
+
+    /// `term = factor {('x' | '/') factor}`
+    fn term(&mut self) -> GerberResult<MacroExpression> {
+        let mut left = self.factor()?;
+        while let Some(operator @ ('x' | '/')) = self.peek() {
+            self.position += 1;
+            let right = self.factor()?;
+            left = match operator {
+                'x' => left * right,
+                _ => left / right,
+            };
+        }
+        Ok(left)
+    }
+
+    /// `factor = ('+' | '-') factor | '(' expression ')' | '$' integer | decimal`
+    fn factor(&mut self) -> GerberResult<MacroExpression> {
+        match self.peek() {
+            Some(c @ ('+' | '-' | '(')) => {
+                if self.depth == Self::MAX_DEPTH {
+                    return Err(self.error());
+                }
+                self.position += 1;
+                self.depth += 1;
+                let expression = match c {
+                    '+' => self.factor()?,
+                    '-' => -self.factor()?,
+                    _ => {
+                        let expression = self.expression()?;
+                        if self.peek() != Some(')') {
+                            return Err(self.error());
+                        }
+                        self.position += 1;
+                        expression
+                    }
+                };
+                self.depth -= 1;
+                Ok(expression)
+            }
+            Some('$') => {
+                self.position += 1;
+                let digits = self.take_while(|c| c.is_ascii_digit());
+                digits
+                    .parse()
+                    .map(MacroExpression::Variable)
+                    .map_err(|_| self.error())
+            }
+            _ => {
+                let number = self.take_while(|c| c.is_ascii_digit() || c == '.');
+                if !is_decimal(number) {
+                    return Err(self.error());
+                }
+                number
+                    .parse()
+                    .map(MacroExpression::Value)
+                    .map_err(|_| self.error())
+            }
+        }
+    }
+}
+
 /// The gerber specification (2021.02 - 2024.05) 3.4 Data Types does not define a boolean
 /// However, there are various places where they are used in macros whey there are defined
 /// as having a 0 or 1 value.  Such as the 'exposure' flag.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum MacroBoolean {
     Value(bool),
     Variable(u32),
@@ -128,16 +552,19 @@ impl<W: Write> PartialGerberCode<W> for MacroBoolean {
 }
 
 impl From<MacroDecimal> for MacroBoolean {
+    #[allow(deprecated)]
     fn from(value: MacroDecimal) -> Self {
         match value {
             MacroDecimal::Value(decimal) => Self::Value(decimal == 1.0),
             MacroDecimal::Variable(variable) => Self::Variable(variable),
             MacroDecimal::Expression(expressions) => Self::Expression(expressions),
+            MacroDecimal::Expr(expression) => Self::Expression(expression.to_string()),
         }
     }
 }
 
 #[test]
+#[allow(deprecated)]
 fn test_macro_boolean_from_decimal() {
     assert_eq!(
         MacroBoolean::from(MacroDecimal::Value(1.0)),
@@ -157,9 +584,20 @@ fn test_macro_boolean_from_decimal() {
     );
 }
 
+#[test]
+fn test_macro_boolean_from_expr() {
+    assert_eq!(
+        MacroBoolean::from(MacroDecimal::Expr(
+            MacroExpression::Variable(1) * MacroExpression::Variable(2)
+        )),
+        MacroBoolean::Expression("$1x$2".to_string())
+    );
+}
+
 /// Gerber specification (2021.02 - 2024.05) 3.4.1 Integers
 /// "Integers must fit in a 32-bit signed integer"
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum MacroInteger {
     Value(u32),
     Variable(u32),
@@ -189,6 +627,7 @@ impl<W: Write> PartialGerberCode<W> for MacroInteger {
 /// Fortunately for us the [`OutlinePrimitive`] doesn't need to store the amount of vertices since it uses a [`Vec`] to store the points.
 ///
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum MacroContent {
     // Primitives
     Circle(CirclePrimitive),
@@ -253,6 +692,7 @@ impl<T: Into<String>> From<T> for MacroContent {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CirclePrimitive {
     /// Exposure off/on
     pub exposure: MacroBoolean,
@@ -304,6 +744,22 @@ impl CirclePrimitive {
         self.angle = Some(angle);
         self
     }
+
+    /// Like `new`, but returns an error if the diameter is negative.
+    pub fn try_new(diameter: MacroDecimal) -> GerberResult<Self> {
+        let circle = Self::new(diameter);
+        circle.validate()?;
+        Ok(circle)
+    }
+
+    pub fn validate(&self) -> GerberResult<()> {
+        if self.diameter.is_negative() {
+            return Err(GerberError::RangeError(
+                "Diameter of a circle may not be negative".into(),
+            ));
+        }
+        Ok(())
+    }
 }
 
 impl<W: Write> PartialGerberCode<W> for CirclePrimitive {
@@ -326,6 +782,7 @@ impl<W: Write> PartialGerberCode<W> for CirclePrimitive {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct VectorLinePrimitive {
     /// Exposure off/on
     pub exposure: MacroBoolean,
@@ -380,6 +837,19 @@ impl VectorLinePrimitive {
     }
 }
 
+impl VectorLinePrimitive {
+    /// There is no `try_new`, as `new` always creates a line with a width of zero. Use this to
+    /// check a width set with `with_width`.
+    pub fn validate(&self) -> GerberResult<()> {
+        if self.width.is_negative() {
+            return Err(GerberError::RangeError(
+                "Width of a vector line may not be negative".into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
 impl<W: Write> PartialGerberCode<W> for VectorLinePrimitive {
     fn serialize_partial(&self, writer: &mut W) -> GerberResult<()> {
         write!(writer, "20,")?;
@@ -402,6 +872,7 @@ impl<W: Write> PartialGerberCode<W> for VectorLinePrimitive {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CenterLinePrimitive {
     /// Exposure off/on (0/1)
     pub exposure: MacroBoolean,
@@ -452,6 +923,24 @@ impl CenterLinePrimitive {
     }
 }
 
+impl CenterLinePrimitive {
+    /// Like `new`, but returns an error if the width or height is negative.
+    pub fn try_new(dimensions: (MacroDecimal, MacroDecimal)) -> GerberResult<Self> {
+        let center_line = Self::new(dimensions);
+        center_line.validate()?;
+        Ok(center_line)
+    }
+
+    pub fn validate(&self) -> GerberResult<()> {
+        if self.dimensions.0.is_negative() || self.dimensions.1.is_negative() {
+            return Err(GerberError::RangeError(
+                "Dimensions of a center line may not be negative".into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
 impl<W: Write> PartialGerberCode<W> for CenterLinePrimitive {
     fn serialize_partial(&self, writer: &mut W) -> GerberResult<()> {
         write!(writer, "21,")?;
@@ -472,6 +961,7 @@ impl<W: Write> PartialGerberCode<W> for CenterLinePrimitive {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OutlinePrimitive {
     /// Exposure off/on (0/1)
     pub exposure: MacroBoolean,
@@ -515,8 +1005,15 @@ impl OutlinePrimitive {
     }
 }
 
-impl<W: Write> PartialGerberCode<W> for OutlinePrimitive {
-    fn serialize_partial(&self, writer: &mut W) -> GerberResult<()> {
+impl OutlinePrimitive {
+    /// Like `from_points`, but returns an error if the points do not form a valid outline.
+    pub fn try_from_points(points: Vec<(MacroDecimal, MacroDecimal)>) -> GerberResult<Self> {
+        let outline_prim = Self::from_points(points);
+        outline_prim.validate()?;
+        Ok(outline_prim)
+    }
+
+    pub fn validate(&self) -> GerberResult<()> {
         // Points invariants
         if self.points.len() < 2 {
             return Err(GerberError::MissingDataError(
@@ -533,6 +1030,13 @@ impl<W: Write> PartialGerberCode<W> for OutlinePrimitive {
                 "The last point must be equal to the first point".into(),
             ));
         }
+        Ok(())
+    }
+}
+
+impl<W: Write> PartialGerberCode<W> for OutlinePrimitive {
+    fn serialize_partial(&self, writer: &mut W) -> GerberResult<()> {
+        self.validate()?;
 
         write!(writer, "4,")?;
         self.exposure.serialize_partial(writer)?;
@@ -551,6 +1055,7 @@ impl<W: Write> PartialGerberCode<W> for OutlinePrimitive {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// A polygon primitive is a regular polygon defined by the number of vertices,
 /// the center point and the diameter of the circumscribed circle.
 pub struct PolygonPrimitive {
@@ -616,8 +1121,15 @@ impl PolygonPrimitive {
     }
 }
 
-impl<W: Write> PartialGerberCode<W> for PolygonPrimitive {
-    fn serialize_partial(&self, writer: &mut W) -> GerberResult<()> {
+impl PolygonPrimitive {
+    /// Like `new`, but returns an error if the number of vertices is out of range.
+    pub fn try_new(vertices: MacroInteger) -> GerberResult<Self> {
+        let polygon = Self::new(vertices);
+        polygon.validate()?;
+        Ok(polygon)
+    }
+
+    pub fn validate(&self) -> GerberResult<()> {
         // Vertice count invariants
         if matches!(self.vertices, MacroInteger::Value(value) if value < 3) {
             return Err(GerberError::MissingDataError(
@@ -634,6 +1146,13 @@ impl<W: Write> PartialGerberCode<W> for PolygonPrimitive {
                 "The diameter must not be negative".into(),
             ));
         }
+        Ok(())
+    }
+}
+
+impl<W: Write> PartialGerberCode<W> for PolygonPrimitive {
+    fn serialize_partial(&self, writer: &mut W) -> GerberResult<()> {
+        self.validate()?;
         write!(writer, "5,")?;
         self.exposure.serialize_partial(writer)?;
         write!(writer, ",")?;
@@ -654,6 +1173,7 @@ impl<W: Write> PartialGerberCode<W> for PolygonPrimitive {
 /// The moiré primitive is a cross hair centered on concentric rings (annuli).
 /// Exposure is always on.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MoirePrimitive {
     /// X and Y coordinates of center point, decimals
     pub center: (MacroDecimal, MacroDecimal),
@@ -742,8 +1262,10 @@ impl MoirePrimitive {
     }
 }
 
-impl<W: Write> PartialGerberCode<W> for MoirePrimitive {
-    fn serialize_partial(&self, writer: &mut W) -> GerberResult<()> {
+impl MoirePrimitive {
+    /// There is no `try_new`, as `new` always creates a moiré with all sizes zero. Use this to
+    /// check the sizes set with the `with_*` methods.
+    pub fn validate(&self) -> GerberResult<()> {
         // Decimal invariants
         if self.diameter.is_negative() {
             return Err(GerberError::RangeError(
@@ -770,6 +1292,13 @@ impl<W: Write> PartialGerberCode<W> for MoirePrimitive {
                 "Cross hair length of a moiré may not be negative".into(),
             ));
         }
+        Ok(())
+    }
+}
+
+impl<W: Write> PartialGerberCode<W> for MoirePrimitive {
+    fn serialize_partial(&self, writer: &mut W) -> GerberResult<()> {
+        self.validate()?;
         write!(writer, "6,")?;
         self.center.0.serialize_partial(writer)?;
         write!(writer, ",")?;
@@ -794,6 +1323,7 @@ impl<W: Write> PartialGerberCode<W> for MoirePrimitive {
 /// The thermal primitive is a ring (annulus) interrupted by four gaps.
 /// Exposure is always on.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ThermalPrimitive {
     /// X and Y coordinates of center point, decimals
     pub center: (MacroDecimal, MacroDecimal),
@@ -841,6 +1371,47 @@ impl ThermalPrimitive {
     }
 }
 
+impl ThermalPrimitive {
+    /// Like `new`, but returns an error if the diameters and gap are inconsistent.
+    pub fn try_new(
+        inner: MacroDecimal,
+        outer: MacroDecimal,
+        gap: MacroDecimal,
+    ) -> GerberResult<Self> {
+        let thermal = Self::new(inner, outer, gap);
+        thermal.validate()?;
+        Ok(thermal)
+    }
+
+    pub fn validate(&self) -> GerberResult<()> {
+        // Decimal invariants
+        if self.inner_diameter.is_negative() {
+            return Err(GerberError::RangeError(
+                "Inner diameter of a thermal may not be negative".into(),
+            ));
+        }
+        if let (MacroDecimal::Value(outer), MacroDecimal::Value(inner)) =
+            (&self.outer_diameter, &self.inner_diameter)
+        {
+            if outer <= inner {
+                return Err(GerberError::RangeError(
+                    "Outer diameter of a thermal must be larger than the inner diameter".into(),
+                ));
+            }
+        }
+        if let (MacroDecimal::Value(outer), MacroDecimal::Value(gap)) =
+            (&self.outer_diameter, &self.gap)
+        {
+            if *gap >= outer / core::f64::consts::SQRT_2 {
+                return Err(GerberError::RangeError(
+                    "Gap of a thermal must be smaller than the outer diameter / sqrt(2)".into(),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
 impl<W: Write> PartialGerberCode<W> for ThermalPrimitive {
     fn serialize_partial(&self, writer: &mut W) -> GerberResult<()> {
         // Decimal invariants
@@ -867,6 +1438,7 @@ impl<W: Write> PartialGerberCode<W> for ThermalPrimitive {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct VariableDefinition {
     pub number: u32,
     pub expression: String,
@@ -888,13 +1460,15 @@ impl<W: Write> PartialGerberCode<W> for VariableDefinition {
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod test {
     use std::io::BufWriter;
 
     use crate::traits::PartialGerberCode;
 
-    use super::MacroDecimal::{Expression, Value, Variable};
+    #[allow(deprecated)]
+    use super::MacroDecimal::Expression;
+    use super::MacroDecimal::{Expr, Value, Variable};
     use super::*;
 
     macro_rules! assert_partial_code {
@@ -908,6 +1482,44 @@ mod test {
         };
     }
 
+    #[test]
+    fn test_structural_key() {
+        let thermal = ThermalPrimitive::new(Value(0.055), Value(0.08), Value(0.0125));
+        let am1 = ApertureMacro::new("THERMAL1").add_content(thermal.clone());
+        let am2 = ApertureMacro::new("THERMAL2")
+            .add_content("a comment")
+            .add_content(thermal.clone());
+        let am3 = ApertureMacro::new("THERMAL1").add_content(thermal.with_angle(Value(45.0)));
+
+        assert_eq!(am1.structural_key().unwrap(), am2.structural_key().unwrap());
+        assert_ne!(am1.structural_key().unwrap(), am3.structural_key().unwrap());
+
+        let mut map = std::collections::HashMap::new();
+        for am in [&am1, &am2, &am3] {
+            map.entry(am.structural_key().unwrap())
+                .or_insert(am.name.clone());
+        }
+        assert_eq!(map.len(), 2);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_structural_key_uses_code() {
+        let am1 = ApertureMacro::new("A").add_content(CirclePrimitive {
+            exposure: MacroBoolean::Value(true),
+            diameter: Expression("$1x2".to_string()),
+            center: (Value(0.), Value(0.)),
+            angle: None,
+        });
+        let am2 = ApertureMacro::new("B").add_content(CirclePrimitive {
+            exposure: MacroBoolean::Value(true),
+            diameter: Expr(MacroExpression::Variable(1) * MacroExpression::Value(2.0)),
+            center: (Value(0.), Value(0.)),
+            angle: None,
+        });
+        assert_eq!(am1.structural_key().unwrap(), am2.structural_key().unwrap());
+    }
+
     #[test]
     fn test_circle_primitive_codegen() {
         let with_angle = CirclePrimitive {
@@ -1033,8 +1645,108 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_aperture_macro_with_variable_definition() {
+        let am = ApertureMacro::new("RECT75")
+            .add_content(VariableDefinition::new(4, "$1x0.75"))
+            .add_content(VariableDefinition::new(5, "$2x0.75"))
+            .add_content(CenterLinePrimitive::new((Variable(4), Variable(5))));
+        assert_partial_code!(am, "AMRECT75*\n$4=$1x0.75*\n$5=$2x0.75*\n21,1,$4,$5,0,0,0*");
+    }
+
+    #[test]
+    fn test_aperture_macro_try_build() {
+        let am = ApertureMacro::new("RECT75")
+            .with_comment("a rectangle scaled by 0.75")
+            .add_content(VariableDefinition::new(4, "$1x0.75"))
+            .add_content(VariableDefinition::new(5, "$2x0.75"))
+            .add_content(CenterLinePrimitive::new((Variable(4), Variable(5))))
+            .try_build(2)
+            .unwrap();
+        assert_partial_code!(
+            am,
+            "AMRECT75*\n0 a rectangle scaled by 0.75*\n$4=$1x0.75*\n$5=$2x0.75*\n21,1,$4,$5,0,0,0*"
+        );
+
+        assert!(ApertureMacro::new("_.Valid$1")
+            .add_content(CirclePrimitive::new(Variable(1)))
+            .try_build(1)
+            .is_ok());
+
+        for name in ["1ST", "WITH SPACE", "", "A-B"] {
+            let result = ApertureMacro::new(name)
+                .add_content(CirclePrimitive::new(Value(1.0)))
+                .try_build(0);
+            assert!(
+                matches!(result, Err(GerberError::ValidationError(_))),
+                "{:?}",
+                name
+            );
+        }
+
+        // $3 is neither an argument nor defined
+        let result = ApertureMacro::new("RECT75")
+            .add_content(VariableDefinition::new(4, "$3x0.75"))
+            .try_build(2);
+        assert!(result.unwrap_err().to_string().contains("$3"));
+
+        // $4 is referenced before it is defined
+        let result = ApertureMacro::new("RECT75")
+            .add_content(CenterLinePrimitive::new((Variable(4), Variable(1))))
+            .add_content(VariableDefinition::new(4, "$1x0.75"))
+            .try_build(1);
+        assert!(result.unwrap_err().to_string().contains("$4"));
+
+        // Variables in expressions and comments
+        let result = ApertureMacro::new("EXPR")
+            .with_comment("$9 is only mentioned")
+            .add_content(CirclePrimitive::new(MacroDecimal::Expr(
+                MacroExpression::Variable(1) * MacroExpression::Variable(2),
+            )))
+            .try_build(1);
+        assert!(result.unwrap_err().to_string().contains("$2"));
+    }
+
+    #[test]
+    fn test_macro_expression() {
+        use MacroExpression as E;
+
+        let expression = E::Variable(3) + E::Variable(4) / E::Value(2.0);
+        assert_partial_code!(expression, "$3+$4/2");
+        let expression = (E::Variable(3) + E::Variable(4)) / E::Value(2.0);
+        assert_partial_code!(expression, "($3+$4)/2");
+        let expression = E::Variable(1) - (E::Variable(2) - E::Variable(3));
+        assert_partial_code!(expression, "$1-($2-$3)");
+        let expression = (E::Variable(1) - E::Variable(2)) - E::Variable(3);
+        assert_partial_code!(expression, "$1-$2-$3");
+        let expression = E::Variable(1) / (E::Variable(2) * E::Variable(3));
+        assert_partial_code!(expression, "$1/($2x$3)");
+        let expression = E::Variable(1) * (E::Variable(2) / E::Variable(3));
+        assert_partial_code!(expression, "$1x$2/$3");
+        let expression = -(E::Variable(1) + E::Value(1.0));
+        assert_partial_code!(expression, "-($1+1)");
+        let expression = E::Variable(1) - E::Value(-2.0);
+        assert_partial_code!(expression, "$1-(-2)");
+        let expression = -E::Variable(1) * -E::Variable(2);
+        assert_partial_code!(expression, "-$1x(-$2)");
+
+        assert_eq!(
+            (E::Value(3.0) * -E::Value(2.0)).constant_value(),
+            Some(-6.0)
+        );
+        assert_eq!((E::Value(3.0) + E::Variable(1)).constant_value(), None);
+    }
+
+    #[test]
+    fn test_macro_expression_into_decimal() {
+        let decimal: MacroDecimal = (MacroExpression::Variable(1) * 0.75.into()).into();
+        assert_partial_code!(decimal, "$1x0.75");
+        assert!(CirclePrimitive::try_new(MacroExpression::Value(-1.0).into()).is_err());
+    }
+
     /// This test should use at least one each of the enum variants in [`MacroDecimal`]
     #[test]
+    #[allow(deprecated)]
     fn test_codegen_with_variable() {
         let line = VectorLinePrimitive {
             exposure: MacroBoolean::Value(true),
@@ -1046,6 +1758,80 @@ mod test {
         assert_partial_code!(line, "20,1,$0,$1,0.45,12,$2x4,$3*");
     }
 
+    #[test]
+    fn test_codegen_with_expr() {
+        let line = VectorLinePrimitive {
+            exposure: MacroBoolean::Value(true),
+            width: Variable(0),
+            start: (Variable(1), 0.45.into()),
+            end: (
+                Value(12.),
+                Expr(MacroExpression::Variable(2) * MacroExpression::Value(4.0)),
+            ),
+            angle: Variable(3),
+        };
+        assert_partial_code!(line, "20,1,$0,$1,0.45,12,$2x4,$3*");
+    }
+
+    #[test]
+    fn test_macro_decimal_parse() {
+        assert_eq!(MacroDecimal::parse("$1").unwrap(), Variable(1));
+        assert_eq!(MacroDecimal::parse("0.25").unwrap(), Value(0.25));
+        assert_eq!(MacroDecimal::parse("-1").unwrap(), Value(-1.0));
+        assert_eq!(MacroDecimal::parse("+.5").unwrap(), Value(0.5));
+        assert_eq!(MacroDecimal::parse("2.").unwrap(), Value(2.0));
+        assert_eq!(
+            MacroDecimal::parse("$1x$2").unwrap(),
+            Expr(MacroExpression::Variable(1) * MacroExpression::Variable(2))
+        );
+        assert_eq!(
+            MacroDecimal::parse("$3+($4/2)").unwrap(),
+            Expr(
+                MacroExpression::Variable(3)
+                    + MacroExpression::Variable(4) / MacroExpression::Value(2.0)
+            )
+        );
+        assert_eq!(
+            MacroDecimal::parse("-$1").unwrap(),
+            Expr(-MacroExpression::Variable(1))
+        );
+    }
+
+    #[test]
+    fn test_macro_expression_parse() {
+        let roundtrip = |token: &str| MacroExpression::parse(token).unwrap().to_string();
+        assert_eq!(roundtrip("$1x0.75"), "$1x0.75");
+        assert_eq!(roundtrip("1+2x3"), "1+2x3");
+        assert_eq!(roundtrip("(1+2)x3"), "(1+2)x3");
+        assert_eq!(roundtrip("$1-($2-$3)"), "$1-($2-$3)");
+        assert_eq!(roundtrip("$1/$2/$3"), "$1/$2/$3");
+        assert_eq!(roundtrip("-($1+2)"), "-($1+2)");
+        assert_eq!(roundtrip("+$1"), "$1");
+        assert_eq!(roundtrip("(($1))"), "$1");
+        assert_eq!(
+            MacroExpression::parse("1-2x3").unwrap().constant_value(),
+            Some(-5.0)
+        );
+
+        assert!(MacroExpression::parse("").is_err());
+        assert!(MacroExpression::parse("$1+").is_err());
+        assert!(MacroExpression::parse("($1").is_err());
+        assert!(MacroExpression::parse("$1)").is_err());
+        assert!(MacroExpression::parse("$1 x 2").is_err());
+        assert!(MacroExpression::parse(&"(".repeat(100_000)).is_err());
+    }
+
+    #[test]
+    fn test_macro_decimal_parse_invalid() {
+        assert!(MacroDecimal::parse("").is_err());
+        assert!(MacroDecimal::parse("NaN").is_err());
+        assert!(MacroDecimal::parse("inf").is_err());
+        assert!(MacroDecimal::parse("$1*$2").is_err());
+        assert!(MacroDecimal::parse(".").is_err());
+        assert!(MacroDecimal::parse("$").is_err());
+        assert!(MacroDecimal::parse("1.2.3").is_err());
+    }
+
     #[test]
     fn test_macro_decimal_into() {
         let a = Value(1.0);
@@ -1119,6 +1905,36 @@ mod test {
         assert_eq!(cl1, cl2);
     }
 
+    #[test]
+    fn test_primitive_checked_constructors() {
+        let open = vec![
+            (Value(0.0), Value(0.0)),
+            (Value(1.0), Value(0.0)),
+            (Value(1.0), Value(1.0)),
+        ];
+        assert!(matches!(
+            OutlinePrimitive::try_from_points(open),
+            Err(GerberError::RangeError(_))
+        ));
+        let closed = vec![
+            (Value(0.0), Value(0.0)),
+            (Value(1.0), Value(0.0)),
+            (Value(1.0), Value(1.0)),
+            (Value(0.0), Value(0.0)),
+        ];
+        assert!(OutlinePrimitive::try_from_points(closed).is_ok());
+
+        assert!(CirclePrimitive::try_new(Value(-1.0)).is_err());
+        assert!(CirclePrimitive::try_new(Variable(1)).is_ok());
+        assert!(CenterLinePrimitive::try_new((Value(1.0), Value(-1.0))).is_err());
+        assert!(PolygonPrimitive::try_new(MacroInteger::Value(2)).is_err());
+        assert!(PolygonPrimitive::try_new(MacroInteger::Value(13)).is_err());
+        assert!(PolygonPrimitive::try_new(MacroInteger::Value(6)).is_ok());
+        assert!(ThermalPrimitive::try_new(Value(0.8), Value(0.5), Value(0.1)).is_err());
+        assert!(ThermalPrimitive::try_new(Value(0.5), Value(0.8), Value(0.6)).is_err());
+        assert!(ThermalPrimitive::try_new(Value(0.5), Value(0.8), Value(0.1)).is_ok());
+    }
+
     #[test]
     fn test_outline_primitive_new() {
         let op1 = OutlinePrimitive::new()
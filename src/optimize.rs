@@ -0,0 +1,276 @@
+//! Optimizations of command streams.
+//!
+//! The optimizations produce streams that render identically to the input but
+//! contain fewer commands.
+
+use crate::coordinates::{CoordinateMode, CoordinateNumber, Coordinates};
+use crate::extended_codes::{ApertureBlock, StepAndRepeat};
+use crate::function_codes::{DCode, GCode, InterpolationMode, Operation, RegionMode};
+use crate::prelude::*;
+use crate::types::{Command, ExtendedCode, FunctionCode};
+
+/// Removes interpolation mode commands (G01/G02/G03) that set the mode that is already active.
+pub fn dedupe_interpolation_mode(commands: &[Command]) -> Vec<Command> {
+    let mut current: Option<InterpolationMode> = None;
+    commands
+        .iter()
+        .filter(|command| match command {
+            Command::FunctionCode(FunctionCode::GCode(GCode::InterpolationMode(mode))) => {
+                let redundant = current == Some(*mode);
+                current = Some(*mode);
+                !redundant
+            }
+            _ => true,
+        })
+        .cloned()
+        .collect()
+}
+
+/// Replaces runs of flashes that form a regular grid with a step and repeat block (`%SR%`)
+/// that flashes once.
+///
+/// A run is a sequence of consecutive flashes, i.e. with the same aperture, that all have
+/// absolute X and Y coordinates. It is replaced if it has more than three flashes, every point
+/// of an evenly spaced grid is flashed exactly once, and the next operation does not depend on
+/// the current point, because it is undefined after a step and repeat block. Flashes that are
+/// already in a step and repeat block, an aperture block or a region are kept.
+pub fn coalesce_grid_flashes(commands: &[Command]) -> Vec<Command> {
+    let mut result = Vec::with_capacity(commands.len());
+    let mut in_step_and_repeat = false;
+    let mut in_region = false;
+    let mut block_depth = 0;
+    let mut index = 0;
+    while index < commands.len() {
+        match &commands[index] {
+            Command::ExtendedCode(ExtendedCode::StepAndRepeat(sr)) => {
+                in_step_and_repeat = matches!(sr, StepAndRepeat::Open { .. });
+            }
+            Command::ExtendedCode(ExtendedCode::ApertureBlock(ab)) => match ab {
+                ApertureBlock::Open { .. } => block_depth += 1,
+                ApertureBlock::Close => block_depth -= 1,
+            },
+            Command::FunctionCode(FunctionCode::GCode(GCode::RegionMode(mode))) => {
+                in_region = *mode == RegionMode::On;
+            }
+            _ => {}
+        }
+        let run = if in_step_and_repeat || in_region || block_depth > 0 {
+            &commands[index..index]
+        } else {
+            let len = commands[index..]
+                .iter()
+                .take_while(|command| grid_flash(command).is_some())
+                .count();
+            &commands[index..index + len]
+        };
+        if run.len() > 3 && !uses_current_point(&commands[index + run.len()..]) {
+            if let Some(replacement) = grid_step_and_repeat(run) {
+                result.extend(replacement);
+                index += run.len();
+                continue;
+            }
+        }
+        result.push(commands[index].clone());
+        index += 1;
+    }
+    result
+}
+
+/// The coordinates of an absolute flash with both X and Y.
+fn grid_flash(command: &Command) -> Option<(&Coordinates, CoordinateNumber, CoordinateNumber)> {
+    match command {
+        Command::FunctionCode(FunctionCode::DCode(DCode::Operation(Operation::Flash(Some(
+            coordinates,
+        )))))
+            if coordinates.format.coordinate_mode == CoordinateMode::Absolute =>
+        {
+            Some((coordinates, coordinates.x?, coordinates.y?))
+        }
+        _ => None,
+    }
+}
+
+/// Whether the next operation uses the current point, i.e. is an interpolation, which starts at
+/// the current point, or omits X or Y.
+fn uses_current_point(commands: &[Command]) -> bool {
+    let next = commands.iter().find_map(|command| match command {
+        Command::FunctionCode(FunctionCode::DCode(DCode::Operation(operation))) => Some(operation),
+        _ => None,
+    });
+    let coordinates = match next {
+        None => return false,
+        Some(Operation::Interpolate(..)) => return true,
+        Some(Operation::Move(coordinates)) => coordinates,
+        Some(Operation::Flash(coordinates)) => coordinates,
+    };
+    !matches!(
+        coordinates,
+        Some(Coordinates {
+            x: Some(_),
+            y: Some(_),
+            ..
+        })
+    )
+}
+
+/// The step and repeat block that flashes the same points as `run`, if they form a grid.
+fn grid_step_and_repeat(run: &[Command]) -> Option<Vec<Command>> {
+    let flashes: Vec<_> = run.iter().filter_map(grid_flash).collect();
+    let mut xs: Vec<CoordinateNumber> = flashes.iter().map(|(_, x, _)| *x).collect();
+    let mut ys: Vec<CoordinateNumber> = flashes.iter().map(|(_, _, y)| *y).collect();
+    let mut points: Vec<(CoordinateNumber, CoordinateNumber)> =
+        flashes.iter().map(|(_, x, y)| (*x, *y)).collect();
+    xs.sort();
+    xs.dedup();
+    ys.sort();
+    ys.dedup();
+    points.sort();
+    points.dedup();
+    if points.len() != flashes.len() || xs.len() * ys.len() != points.len() {
+        return None;
+    }
+    let distance_x = even_spacing(&xs)?;
+    let distance_y = even_spacing(&ys)?;
+
+    let format = flashes[0].0.format;
+    Some(vec![
+        ExtendedCode::StepAndRepeat(StepAndRepeat::Open {
+            repeat_x: xs.len() as u32,
+            repeat_y: ys.len() as u32,
+            distance_x: f64::from(distance_x),
+            distance_y: f64::from(distance_y),
+        })
+        .into(),
+        DCode::Operation(Operation::Flash(Some(Coordinates::new(
+            xs[0], ys[0], format,
+        ))))
+        .into(),
+        ExtendedCode::StepAndRepeat(StepAndRepeat::Close).into(),
+    ])
+}
+
+/// The distance between sorted, distinct values if it is the same for all of them, zero for a
+/// single value.
+fn even_spacing(values: &[CoordinateNumber]) -> Option<CoordinateNumber> {
+    let distance = match values {
+        [first, second, ..] => *second - *first,
+        _ => return Some(CoordinateNumber::from(0)),
+    };
+    values
+        .windows(2)
+        .all(|pair| pair[1] - pair[0] == distance)
+        .then_some(distance)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{CoordinateFormat, ZeroOmission};
+
+    fn mode(mode: InterpolationMode) -> Command {
+        GCode::InterpolationMode(mode).into()
+    }
+
+    fn interpolate(x: i32, y: i32) -> Command {
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 4);
+        DCode::Operation(Operation::Interpolate(
+            Some(Coordinates::new(x, y, cf)),
+            None,
+        ))
+        .into()
+    }
+
+    #[test]
+    fn test_dedupe_interpolation_mode() {
+        let commands = vec![
+            mode(InterpolationMode::Linear),
+            mode(InterpolationMode::Linear),
+            interpolate(1, 1),
+            mode(InterpolationMode::Linear),
+            interpolate(2, 2),
+            mode(InterpolationMode::ClockwiseCircular),
+            mode(InterpolationMode::Linear),
+            interpolate(3, 3),
+        ];
+
+        let expected = vec![
+            mode(InterpolationMode::Linear),
+            interpolate(1, 1),
+            interpolate(2, 2),
+            mode(InterpolationMode::ClockwiseCircular),
+            mode(InterpolationMode::Linear),
+            interpolate(3, 3),
+        ];
+
+        assert_eq!(dedupe_interpolation_mode(&commands), expected);
+    }
+
+    fn flash(x: i32, y: i32) -> Command {
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 4);
+        DCode::Operation(Operation::Flash(Some(Coordinates::new(x, y, cf)))).into()
+    }
+
+    #[test]
+    fn test_coalesce_grid_flashes() {
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 4);
+        let commands = vec![
+            DCode::SelectAperture(10).into(),
+            flash(1, 1),
+            flash(3, 1),
+            flash(1, 2),
+            flash(3, 2),
+            DCode::SelectAperture(11).into(),
+        ];
+
+        let expected = vec![
+            DCode::SelectAperture(10).into(),
+            ExtendedCode::StepAndRepeat(StepAndRepeat::Open {
+                repeat_x: 2,
+                repeat_y: 2,
+                distance_x: 2.0,
+                distance_y: 1.0,
+            })
+            .into(),
+            DCode::Operation(Operation::Flash(Some(Coordinates::new(1, 1, cf)))).into(),
+            ExtendedCode::StepAndRepeat(StepAndRepeat::Close).into(),
+            DCode::SelectAperture(11).into(),
+        ];
+
+        assert_eq!(coalesce_grid_flashes(&commands), expected);
+    }
+
+    #[test]
+    fn test_coalesce_grid_flashes_keeps_irregular_flashes() {
+        // Not evenly spaced
+        let commands = vec![flash(1, 1), flash(2, 1), flash(4, 1), flash(5, 1)];
+        assert_eq!(coalesce_grid_flashes(&commands), commands);
+
+        // Missing grid point
+        let commands = vec![flash(1, 1), flash(2, 1), flash(1, 2), flash(1, 2)];
+        assert_eq!(coalesce_grid_flashes(&commands), commands);
+
+        // Next flash uses the current point
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 4);
+        let commands = vec![
+            flash(1, 1),
+            flash(2, 1),
+            flash(1, 2),
+            flash(2, 2),
+            DCode::SelectAperture(11).into(),
+            DCode::Operation(Operation::Flash(Some(Coordinates::at_x(5, cf)))).into(),
+        ];
+        assert_eq!(coalesce_grid_flashes(&commands), commands);
+
+        // Next interpolation starts at the current point, even with both X and Y
+        let commands = vec![
+            flash(1, 1),
+            flash(2, 1),
+            flash(1, 2),
+            flash(2, 2),
+            DCode::SelectAperture(11).into(),
+            interpolate(5, 5),
+        ];
+        assert_eq!(coalesce_grid_flashes(&commands), commands);
+    }
+}
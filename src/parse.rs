@@ -0,0 +1,1179 @@
+//! Parsing of Gerber files into commands.
+//!
+//! This is the inverse of the serialization: [`parse_gerber`] reads a file and returns the
+//! `Command`s that produce it. Only the commands and attributes modeled by this crate are
+//! recognized, everything else is an error.
+
+use std::io::BufRead;
+use std::str::FromStr;
+
+use chrono::DateTime;
+use uuid::Uuid;
+
+use crate::attributes::*;
+use crate::coordinates::{parse_format, CoordinateFormat, CoordinateMode};
+use crate::errors::{GerberError, GerberResult};
+use crate::extended_codes::*;
+use crate::function_codes::*;
+use crate::macros::*;
+use crate::types::{Command, ExtendedCode};
+
+/// Parses a Gerber file into a list of commands.
+///
+/// Coordinate data (e.g. `X100Y200D01*`) is decoded with the coordinate format (`%FS...*%`)
+/// that precedes it. Errors contain the number of the line where the offending command starts.
+pub fn parse_gerber<R: BufRead>(reader: R) -> GerberResult<Vec<Command>> {
+    let mut commands = Vec::new();
+    let mut format = None;
+    for (line, statement) in tokenize(reader)? {
+        let result = match statement {
+            Statement::Word(word) => parse_function_code(&word, format.as_ref(), &mut commands),
+            Statement::Extended(words) => parse_extended_code(&words, &mut format, &mut commands),
+        };
+        result.map_err(|error| at_line(error, line))?;
+    }
+    Ok(commands)
+}
+
+enum Statement {
+    /// A function code or coordinate data block, e.g. `D10` for `D10*`
+    Word(String),
+    /// The words of an extended code, e.g. `["FSLAX26Y26"]` for `%FSLAX26Y26*%`
+    Extended(Vec<String>),
+}
+
+/// Splits the input into statements, each with the (1-based) line on which it starts.
+fn tokenize<R: BufRead>(reader: R) -> GerberResult<Vec<(usize, Statement)>> {
+    let mut statements = Vec::new();
+    let mut current = String::new();
+    let mut extended: Option<(usize, Vec<String>)> = None;
+    let mut start = 0;
+    let mut last_line = 0;
+    for (index, line) in reader.lines().enumerate() {
+        let line_number = index + 1;
+        last_line = line_number;
+        for c in line?.chars() {
+            match c {
+                '%' => {
+                    if !current.trim().is_empty() {
+                        return Err(GerberError::ConversionError(format!(
+                            "Line {}: Missing '*' after '{}'",
+                            line_number,
+                            current.trim()
+                        )));
+                    }
+                    current.clear();
+                    match extended.take() {
+                        Some((line, words)) => {
+                            statements.push((line, Statement::Extended(words)));
+                        }
+                        None => extended = Some((line_number, Vec::new())),
+                    }
+                }
+                '*' => {
+                    let word = std::mem::take(&mut current).trim().to_string();
+                    match extended {
+                        Some((_, ref mut words)) => words.push(word),
+                        None => statements.push((start, Statement::Word(word))),
+                    }
+                }
+                '\r' => {}
+                _ => {
+                    if current.is_empty() {
+                        if c.is_whitespace() {
+                            continue;
+                        }
+                        start = line_number;
+                    }
+                    current.push(c);
+                }
+            }
+        }
+    }
+    if let Some((line, _)) = extended {
+        return Err(GerberError::ConversionError(format!(
+            "Line {}: Extended command is not terminated with '%'",
+            line
+        )));
+    }
+    if !current.trim().is_empty() {
+        return Err(GerberError::ConversionError(format!(
+            "Line {}: Missing '*' after '{}'",
+            last_line,
+            current.trim()
+        )));
+    }
+    Ok(statements)
+}
+
+fn at_line(error: GerberError, line: usize) -> GerberError {
+    let message = |message: String| format!("Line {}: {}", line, message);
+    match error {
+        GerberError::ConversionError(m) => GerberError::ConversionError(message(m)),
+        GerberError::CoordinateFormatError(m) => GerberError::CoordinateFormatError(message(m)),
+        GerberError::RangeError(m) => GerberError::RangeError(message(m)),
+        GerberError::MissingDataError(m) => GerberError::MissingDataError(message(m)),
+        GerberError::ValidationError(m) => GerberError::ValidationError(message(m)),
+        other => other,
+    }
+}
+
+// Function codes
+
+fn parse_function_code(
+    word: &str,
+    format: Option<&CoordinateFormat>,
+    commands: &mut Vec<Command>,
+) -> GerberResult<()> {
+    let unknown = || GerberError::ConversionError(format!("Unknown command '{}*'", word));
+
+    if let Some(rest) = word.strip_prefix('G') {
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        let code: u32 = rest[..digits].parse().map_err(|_| unknown())?;
+        let rest = &rest[digits..];
+        let gcode = match code {
+            4 => {
+                let content = rest.strip_prefix(' ').unwrap_or(rest);
+                commands.push(GCode::Comment(parse_comment(content)?).into());
+                return Ok(());
+            }
+            1 => GCode::InterpolationMode(InterpolationMode::Linear),
+            2 => GCode::InterpolationMode(InterpolationMode::ClockwiseCircular),
+            3 => GCode::InterpolationMode(InterpolationMode::CounterclockwiseCircular),
+            36 => GCode::RegionMode(RegionMode::On),
+            37 => GCode::RegionMode(RegionMode::Off),
+            54 => GCode::SelectAperture,
+            70 => GCode::Unit(Unit::Inches),
+            71 => GCode::Unit(Unit::Millimeters),
+            74 => GCode::QuadrantMode(QuadrantMode::Single),
+            75 => GCode::QuadrantMode(QuadrantMode::Multi),
+            90 => GCode::CoordinateMode(CoordinateMode::Absolute),
+            91 => GCode::CoordinateMode(CoordinateMode::Incremental),
+            _ => return Err(unknown()),
+        };
+        commands.push(gcode.into());
+        // Deprecated combinations, e.g. `G01X100Y100D01*` or `G54D10*`
+        return match code {
+            1..=3 | 54 if !rest.is_empty() => parse_function_code(rest, format, commands),
+            _ if !rest.is_empty() => Err(unknown()),
+            _ => Ok(()),
+        };
+    }
+
+    if let Some(rest) = word.strip_prefix('M') {
+        return match rest.parse::<u32>() {
+            Ok(2) => {
+                commands.push(MCode::EndOfFile.into());
+                Ok(())
+            }
+            _ => Err(unknown()),
+        };
+    }
+
+    if let Some(code) = word
+        .strip_prefix('D')
+        .and_then(|rest| rest.parse::<i32>().ok())
+    {
+        if code >= 10 {
+            commands.push(DCode::SelectAperture(code).into());
+            return Ok(());
+        }
+    }
+
+    if word.starts_with(['X', 'Y', 'I', 'J', 'D']) {
+        let format = format.ok_or_else(|| {
+            GerberError::MissingDataError(format!(
+                "Coordinate data '{}*' before the coordinate format (FS)",
+                word
+            ))
+        })?;
+        commands.push(DCode::Operation(Operation::parse(word, format)?).into());
+        return Ok(());
+    }
+
+    Err(unknown())
+}
+
+fn parse_comment(content: &str) -> GerberResult<CommentContent> {
+    let standard = match content.strip_prefix("#@! ") {
+        Some(standard) => standard,
+        None => return Ok(CommentContent::String(content.to_string())),
+    };
+    // A comment that does not start with an ASCII code is free-form text.
+    let (code, value) = match split_code(standard) {
+        Some(split) => split,
+        None => return Ok(CommentContent::String(content.to_string())),
+    };
+    let comment = match code {
+        "TF" => StandardComment::FileAttribute(parse_file_attribute(value)?),
+        "TA" => StandardComment::ApertureAttribute(parse_aperture_attribute(value)?),
+        "TO" => StandardComment::ObjectAttribute(parse_object_attribute(value)?),
+        "TD" => StandardComment::DeleteAttribute(parse_deletion_criterion(value)),
+        _ => return Ok(CommentContent::String(content.to_string())),
+    };
+    Ok(CommentContent::Standard(comment))
+}
+
+// Extended codes
+
+fn parse_extended_code(
+    words: &[String],
+    format: &mut Option<CoordinateFormat>,
+    commands: &mut Vec<Command>,
+) -> GerberResult<()> {
+    if let Some(name) = words.first().and_then(|word| word.strip_prefix("AM")) {
+        commands.push(ExtendedCode::ApertureMacro(parse_aperture_macro(name, &words[1..])?).into());
+        return Ok(());
+    }
+    for word in words.iter().filter(|word| !word.is_empty()) {
+        let code = parse_extended_word(word)?;
+        if let ExtendedCode::CoordinateFormat(ref cf) = code {
+            *format = Some(*cf);
+        }
+        commands.push(code.into());
+    }
+    Ok(())
+}
+
+/// Splits the two letter code off the start of a word. `None` if the code is not followed by a
+/// character boundary, e.g. for non-ASCII words.
+fn split_code(word: &str) -> Option<(&str, &str)> {
+    let end = word.len().min(2);
+    Some((word.get(..end)?, word.get(end..)?))
+}
+
+fn parse_extended_word(word: &str) -> GerberResult<ExtendedCode> {
+    let (code, value) = split_code(word).ok_or_else(|| {
+        GerberError::ConversionError(format!("Unknown extended command '%{}*%'", word))
+    })?;
+    Ok(match code {
+        "FS" => ExtendedCode::CoordinateFormat(parse_format(value)?),
+        "MO" => ExtendedCode::Unit(value.parse()?),
+        "AD" => ExtendedCode::ApertureDefinition(parse_aperture_definition(value)?),
+        "LP" => ExtendedCode::LoadPolarity(value.parse()?),
+        "LM" => ExtendedCode::LoadMirroring(value.parse()?),
+        "LR" => ExtendedCode::LoadRotation(Rotation {
+            rotation: decimal(value)?,
+        }),
+        "LS" => ExtendedCode::LoadScaling(Scaling {
+            scale: decimal(value)?,
+        }),
+        "SR" if value.is_empty() => ExtendedCode::StepAndRepeat(StepAndRepeat::Close),
+        "SR" => {
+            let fields = letter_fields(value, "XYIJ")?;
+            let field = |letter: char| {
+                fields
+                    .iter()
+                    .find(|(l, _)| *l == letter)
+                    .map(|(_, value)| *value)
+                    .ok_or_else(|| {
+                        GerberError::MissingDataError(format!(
+                            "Missing {} in step and repeat",
+                            letter
+                        ))
+                    })
+            };
+            ExtendedCode::StepAndRepeat(StepAndRepeat::Open {
+                repeat_x: number(field('X')?, "repeat count")?,
+                repeat_y: number(field('Y')?, "repeat count")?,
+                distance_x: decimal(field('I')?)?,
+                distance_y: decimal(field('J')?)?,
+            })
+        }
+        "AB" if value.is_empty() => ExtendedCode::ApertureBlock(ApertureBlock::Close),
+        "AB" => ExtendedCode::ApertureBlock(ApertureBlock::Open {
+            code: number(value.strip_prefix('D').unwrap_or(value), "aperture code")?,
+        }),
+        "TF" => ExtendedCode::FileAttribute(parse_file_attribute(value)?),
+        "TA" => ExtendedCode::ApertureAttribute(parse_aperture_attribute(value)?),
+        "TO" => ExtendedCode::ObjectAttribute(parse_object_attribute(value)?),
+        "TD" => ExtendedCode::DeleteAttribute(parse_deletion_criterion(value)),
+        "MI" => {
+            let fields = letter_fields(value, "AB")?;
+            let mirrored = |letter: char| fields.iter().any(|&(l, v)| l == letter && v == "1");
+            ExtendedCode::MirrorImage(match (mirrored('A'), mirrored('B')) {
+                (false, false) => ImageMirroring::None,
+                (true, false) => ImageMirroring::A,
+                (false, true) => ImageMirroring::B,
+                (true, true) => ImageMirroring::AB,
+            })
+        }
+        "OF" => {
+            let (a, b) = parse_a_b(value, 0.0)?;
+            ExtendedCode::OffsetImage(ImageOffset { a, b })
+        }
+        "SF" => {
+            let (a, b) = parse_a_b(value, 1.0)?;
+            ExtendedCode::ScaleImage(ImageScaling { a, b })
+        }
+        "IR" => ExtendedCode::RotateImage(value.parse()?),
+        "IP" => ExtendedCode::ImagePolarity(value.parse()?),
+        "AS" => ExtendedCode::AxisSelect(value.parse()?),
+        "IN" => ExtendedCode::ImageName(ImageName {
+            name: value.to_string(),
+        }),
+        _ => {
+            return Err(GerberError::ConversionError(format!(
+                "Unknown extended command '%{}*%'",
+                word
+            )))
+        }
+    })
+}
+
+fn parse_aperture_definition(value: &str) -> GerberResult<ApertureDefinition> {
+    let value = value.strip_prefix('D').ok_or_else(|| {
+        GerberError::ConversionError(format!("Invalid aperture definition 'AD{}'", value))
+    })?;
+    let digits = value.len() - value.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let code = number(&value[..digits], "aperture code")?;
+    let (template, parameters) = match value[digits..].split_once(',') {
+        Some((template, parameters)) => (template, Some(parameters)),
+        None => (&value[digits..], None),
+    };
+    let parameters: Vec<&str> = parameters
+        .map(|parameters| parameters.split('X').collect())
+        .unwrap_or_default();
+    let decimals = |range: std::ops::Range<usize>| -> GerberResult<Vec<f64>> {
+        if parameters.len() < range.start || parameters.len() > range.end {
+            return Err(GerberError::ConversionError(format!(
+                "Invalid number of parameters for aperture '{}'",
+                template
+            )));
+        }
+        parameters.iter().map(|p| decimal(p)).collect()
+    };
+    let aperture = match template {
+        "C" => {
+            let p = decimals(1..2)?;
+            Aperture::Circle(Circle::with_optional_hole(p[0], p.get(1).copied()))
+        }
+        "R" | "O" => {
+            let p = decimals(2..3)?;
+            let rectangular = Rectangular::with_optional_hole(p[0], p[1], p.get(2).copied());
+            if template == "R" {
+                Aperture::Rectangle(rectangular)
+            } else {
+                Aperture::Obround(rectangular)
+            }
+        }
+        "P" => {
+            let p = decimals(2..4)?;
+            Aperture::Polygon(Polygon {
+                diameter: p[0],
+                vertices: number(parameters[1], "number of vertices")?,
+                rotation: p.get(2).copied(),
+                hole_diameter: p.get(3).copied(),
+            })
+        }
+        "" => {
+            return Err(GerberError::MissingDataError(
+                "Missing aperture template".into(),
+            ))
+        }
+        name => Aperture::Macro(
+            name.to_string(),
+            if parameters.is_empty() {
+                None
+            } else {
+                Some(
+                    parameters
+                        .iter()
+                        .map(|p| MacroDecimal::parse(p))
+                        .collect::<GerberResult<_>>()?,
+                )
+            },
+        ),
+    };
+    Ok(ApertureDefinition::new(code, aperture))
+}
+
+fn parse_aperture_macro(name: &str, words: &[String]) -> GerberResult<ApertureMacro> {
+    let mut aperture_macro = ApertureMacro::new(name);
+    for word in words.iter().filter(|word| !word.is_empty()) {
+        aperture_macro.add_content_mut(parse_macro_content(word)?);
+    }
+    Ok(aperture_macro)
+}
+
+fn parse_macro_content(word: &str) -> GerberResult<MacroContent> {
+    if let Some(comment) = word.strip_prefix('0') {
+        if !comment.starts_with(|c: char| c.is_ascii_digit() || c == '.') {
+            return Ok(MacroContent::Comment(
+                comment.strip_prefix(' ').unwrap_or(comment).to_string(),
+            ));
+        }
+    }
+    if let Some(definition) = word.strip_prefix('$') {
+        if let Some((number_str, expression)) = definition.split_once('=') {
+            return Ok(MacroContent::VariableDefinition(VariableDefinition::new(
+                number(number_str, "macro variable")?,
+                expression,
+            )));
+        }
+    }
+
+    let fields: Vec<&str> = word.split(',').map(str::trim).collect();
+    let count = |expected: usize| {
+        if fields.len() == expected {
+            Ok(())
+        } else {
+            Err(GerberError::ConversionError(format!(
+                "Primitive '{}' must have {} parameters",
+                word,
+                expected - 1
+            )))
+        }
+    };
+    let d = |index: usize| MacroDecimal::parse(fields[index]);
+    Ok(match fields[0] {
+        "1" => {
+            if fields.len() != 6 {
+                count(5)?;
+            }
+            MacroContent::Circle(CirclePrimitive {
+                exposure: macro_boolean(fields[1]),
+                diameter: d(2)?,
+                center: (d(3)?, d(4)?),
+                angle: fields.get(5).map(|a| MacroDecimal::parse(a)).transpose()?,
+            })
+        }
+        "20" => {
+            count(8)?;
+            MacroContent::VectorLine(VectorLinePrimitive {
+                exposure: macro_boolean(fields[1]),
+                width: d(2)?,
+                start: (d(3)?, d(4)?),
+                end: (d(5)?, d(6)?),
+                angle: d(7)?,
+            })
+        }
+        "21" => {
+            count(7)?;
+            MacroContent::CenterLine(CenterLinePrimitive {
+                exposure: macro_boolean(fields[1]),
+                dimensions: (d(2)?, d(3)?),
+                center: (d(4)?, d(5)?),
+                angle: d(6)?,
+            })
+        }
+        "4" => {
+            let vertices: usize = number(fields.get(2).copied().unwrap_or(""), "vertex count")?;
+            let expected = vertices
+                .checked_add(1)
+                .and_then(|points| points.checked_mul(2))
+                .and_then(|fields| fields.checked_add(4))
+                .ok_or_else(|| {
+                    GerberError::ConversionError(format!(
+                        "Outline vertex count {} is too large",
+                        vertices
+                    ))
+                })?;
+            count(expected)?;
+            MacroContent::Outline(OutlinePrimitive {
+                exposure: macro_boolean(fields[1]),
+                points: (0..=vertices)
+                    .map(|i| Ok((d(3 + 2 * i)?, d(4 + 2 * i)?)))
+                    .collect::<GerberResult<_>>()?,
+                angle: d(fields.len() - 1)?,
+            })
+        }
+        "5" => {
+            count(7)?;
+            MacroContent::Polygon(PolygonPrimitive {
+                exposure: macro_boolean(fields[1]),
+                vertices: macro_integer(fields[2]),
+                center: (d(3)?, d(4)?),
+                diameter: d(5)?,
+                angle: d(6)?,
+            })
+        }
+        "6" => {
+            count(10)?;
+            MacroContent::Moire(MoirePrimitive {
+                center: (d(1)?, d(2)?),
+                diameter: d(3)?,
+                ring_thickness: d(4)?,
+                gap: d(5)?,
+                max_rings: number(fields[6], "number of rings")?,
+                cross_hair_thickness: d(7)?,
+                cross_hair_length: d(8)?,
+                angle: d(9)?,
+            })
+        }
+        "7" => {
+            count(7)?;
+            MacroContent::Thermal(ThermalPrimitive {
+                center: (d(1)?, d(2)?),
+                outer_diameter: d(3)?,
+                inner_diameter: d(4)?,
+                gap: d(5)?,
+                angle: d(6)?,
+            })
+        }
+        _ => {
+            return Err(GerberError::ConversionError(format!(
+                "Unknown macro primitive '{}'",
+                word
+            )))
+        }
+    })
+}
+
+fn macro_boolean(value: &str) -> MacroBoolean {
+    match value {
+        "1" => MacroBoolean::Value(true),
+        "0" => MacroBoolean::Value(false),
+        _ => match value.strip_prefix('$').and_then(|v| v.parse().ok()) {
+            Some(variable) => MacroBoolean::Variable(variable),
+            None => MacroBoolean::Expression(value.to_string()),
+        },
+    }
+}
+
+fn macro_integer(value: &str) -> MacroInteger {
+    if let Ok(value) = value.parse() {
+        return MacroInteger::Value(value);
+    }
+    match value.strip_prefix('$').and_then(|v| v.parse().ok()) {
+        Some(variable) => MacroInteger::Variable(variable),
+        None => MacroInteger::Expression(value.to_string()),
+    }
+}
+
+// Attributes
+
+/// Splits `.Name,value1,value2` into the name and the values, with the `\uXXXX` escapes of the
+/// values decoded.
+fn split_attribute(value: &str) -> (&str, Vec<String>) {
+    let mut fields = value.split(',');
+    let name = fields.next().unwrap_or_default();
+    (name, fields.map(unescape_field).collect())
+}
+
+fn field<'a>(values: &[&'a str], index: usize, name: &str) -> GerberResult<&'a str> {
+    values.get(index).copied().ok_or_else(|| {
+        GerberError::MissingDataError(format!("Missing value {} of {}", index + 1, name))
+    })
+}
+
+fn user_defined(name: &str, values: &[&str]) -> (String, Vec<String>) {
+    (
+        name.to_string(),
+        values.iter().map(|value| value.to_string()).collect(),
+    )
+}
+
+fn parse_file_attribute(value: &str) -> GerberResult<FileAttribute> {
+    let (name, values) = split_attribute(value);
+    let values: Vec<&str> = values.iter().map(String::as_str).collect();
+    let f = |index: usize| field(&values, index, name);
+    Ok(match name {
+        ".Part" => FileAttribute::Part(match f(0)? {
+            "Single" => Part::Single,
+            "Array" => Part::Array,
+            "FabricationPanel" => Part::FabricationPanel,
+            "Coupon" => Part::Coupon,
+            "Other" => Part::Other(values[1..].join(",")),
+            other => {
+                return Err(GerberError::ConversionError(format!(
+                    "Unknown part '{}'",
+                    other
+                )))
+            }
+        }),
+        ".FileFunction" => FileAttribute::FileFunction(parse_file_function(&values)?),
+        ".FilePolarity" => FileAttribute::FilePolarity(f(0)?.parse()?),
+        ".SameCoordinates" => FileAttribute::SameCoordinates(values.first().map(|ident| {
+            match Uuid::parse_str(ident) {
+                Ok(uuid) => Ident::Uuid(uuid),
+                Err(_) => Ident::Name(ident.to_string()),
+            }
+        })),
+        ".CreationDate" => {
+            FileAttribute::CreationDate(DateTime::parse_from_rfc3339(f(0)?).map_err(|_| {
+                GerberError::ConversionError(format!("Invalid creation date '{}'", values[0]))
+            })?)
+        }
+        ".GenerationSoftware" => FileAttribute::GenerationSoftware(GenerationSoftware::new(
+            f(0)?,
+            f(1)?,
+            values.get(2).copied(),
+        )),
+        ".ProjectId" => FileAttribute::ProjectId {
+            id: f(0)?.to_string(),
+            uuid: Uuid::parse_str(f(1)?).map_err(|_| {
+                GerberError::ConversionError(format!("Invalid project GUID '{}'", values[1]))
+            })?,
+            revision: f(2)?.to_string(),
+        },
+        ".MD5" => FileAttribute::Md5(f(0)?.to_string()),
+        _ => {
+            let (name, values) = user_defined(name, &values);
+            FileAttribute::UserDefined { name, values }
+        }
+    })
+}
+
+fn parse_file_function(values: &[&str]) -> GerberResult<FileFunction> {
+    let f = |index: usize| field(values, index, ".FileFunction");
+    let layer = |index: usize| -> GerberResult<i32> {
+        let value = f(index)?;
+        number(value.strip_prefix('L').unwrap_or(value), "layer number")
+    };
+    let index = |index: usize| -> GerberResult<Option<i32>> {
+        values
+            .get(index)
+            .map(|value| number(value, "index"))
+            .transpose()
+    };
+    let optional = |index: usize| values.get(index).copied();
+    Ok(match f(0)? {
+        "Copper" => FileFunction::Copper {
+            layer: layer(1)?,
+            pos: f(2)?.parse()?,
+            copper_type: optional(3).map(str::parse).transpose()?,
+        },
+        "Plated" => FileFunction::Plated {
+            from_layer: number(f(1)?, "layer number")?,
+            to_layer: number(f(2)?, "layer number")?,
+            drill: f(3)?.parse()?,
+            label: optional(4).map(str::parse).transpose()?,
+        },
+        "NonPlated" => FileFunction::NonPlated {
+            from_layer: number(f(1)?, "layer number")?,
+            to_layer: number(f(2)?, "layer number")?,
+            drill: f(3)?.parse()?,
+            label: optional(4).map(str::parse).transpose()?,
+        },
+        "Profile" => FileFunction::Profile(optional(1).map(str::parse).transpose()?),
+        "Keepout" => FileFunction::KeepOut(f(1)?.parse()?),
+        "Soldermask" => FileFunction::SolderMask {
+            pos: f(1)?.parse()?,
+            index: index(2)?,
+        },
+        "Legend" => FileFunction::Legend {
+            pos: f(1)?.parse()?,
+            index: index(2)?,
+        },
+        "Component" => FileFunction::Component {
+            layer: layer(1)?,
+            pos: f(2)?.parse()?,
+        },
+        "Paste" => FileFunction::Paste(f(1)?.parse()?),
+        "Glue" => FileFunction::Glue(f(1)?.parse()?),
+        "Carbonmask" => FileFunction::CarbonMask {
+            pos: f(1)?.parse()?,
+            index: index(2)?,
+        },
+        "Goldmask" => FileFunction::GoldMask {
+            pos: f(1)?.parse()?,
+            index: index(2)?,
+        },
+        "Heatsinkmask" => FileFunction::HeatsinkMask {
+            pos: f(1)?.parse()?,
+            index: index(2)?,
+        },
+        "Peelablemask" => FileFunction::PeelableMask {
+            pos: f(1)?.parse()?,
+            index: index(2)?,
+        },
+        "Silvermask" => FileFunction::SilverMask {
+            pos: f(1)?.parse()?,
+            index: index(2)?,
+        },
+        "Tinmask" => FileFunction::TinMask {
+            pos: f(1)?.parse()?,
+            index: index(2)?,
+        },
+        "Depthrout" => FileFunction::DepthRoute(f(1)?.parse()?),
+        "Vcut" => FileFunction::VCut(optional(1).map(str::parse).transpose()?),
+        "Viafill" => FileFunction::ViaFill,
+        "Pads" => FileFunction::Pads(f(1)?.parse()?),
+        "Other" => FileFunction::Other(values[1..].join(",")),
+        "Drillmap" => FileFunction::DrillMap,
+        "FabricationDrawing" => FileFunction::FabricationDrawing,
+        "Vcutmap" => FileFunction::VCutMap,
+        "AssemblyDrawing" => FileFunction::AssemblyDrawing(f(1)?.parse()?),
+        "ArrayDrawing" => FileFunction::ArrayDrawing,
+        "OtherDrawing" => FileFunction::OtherDrawing(values[1..].join(",")),
+        other => {
+            return Err(GerberError::ConversionError(format!(
+                "Unknown file function '{}'",
+                other
+            )))
+        }
+    })
+}
+
+fn parse_aperture_attribute(value: &str) -> GerberResult<ApertureAttribute> {
+    let (name, values) = split_attribute(value);
+    let values: Vec<&str> = values.iter().map(String::as_str).collect();
+    let f = |index: usize| field(&values, index, name);
+    let optional = |index: usize| values.get(index).filter(|value| !value.is_empty()).copied();
+    Ok(match name {
+        ".AperFunction" => ApertureAttribute::ApertureFunction(parse_aperture_function(&values)?),
+        ".DrillTolerance" => ApertureAttribute::DrillTolerance {
+            plus: decimal(f(0)?)?,
+            minus: decimal(f(1)?)?,
+        },
+        ".FlashText" => ApertureAttribute::FlashText {
+            text: f(0)?.to_string(),
+            mode: f(1)?.parse()?,
+            mirroring: optional(2).map(str::parse).transpose()?,
+            font: optional(3).map(str::to_string),
+            size: optional(4).map(|size| number(size, "size")).transpose()?,
+            comment: optional(5).map(str::to_string),
+        },
+        _ => {
+            let (name, values) = user_defined(name, &values);
+            ApertureAttribute::UserDefined { name, values }
+        }
+    })
+}
+
+fn parse_aperture_function(values: &[&str]) -> GerberResult<ApertureFunction> {
+    let f = |index: usize| field(values, index, ".AperFunction");
+    let optional = |index: usize| values.get(index).copied();
+    let rest = || values[1..].join(",");
+    Ok(match f(0)? {
+        "ViaDrill" => ApertureFunction::ViaDrill(optional(1).map(str::parse).transpose()?),
+        "BackDrill" => ApertureFunction::BackDrill,
+        "ComponentDrill" => ApertureFunction::ComponentDrill {
+            function: optional(1).map(str::parse).transpose()?,
+        },
+        "MechanicalDrill" => ApertureFunction::MechanicalDrill {
+            function: optional(1).map(str::parse).transpose()?,
+        },
+        "CastellatedDrill" => ApertureFunction::CastellatedDrill,
+        "OtherDrill" => ApertureFunction::OtherDrill(rest()),
+        "ComponentPad" => ApertureFunction::ComponentPad,
+        "SMDPad" => ApertureFunction::SmdPad(f(1)?.parse()?),
+        "BGAPad" => ApertureFunction::BgaPad(f(1)?.parse()?),
+        "ConnectorPad" => ApertureFunction::ConnectorPad,
+        "HeatsinkPad" => ApertureFunction::HeatsinkPad,
+        "ViaPad" => ApertureFunction::ViaPad,
+        "TestPad" => ApertureFunction::TestPad,
+        "CastellatedPad" => ApertureFunction::CastellatedPad,
+        "FiducialPad" => ApertureFunction::FiducialPad(f(1)?.parse()?),
+        "ThermalReliefPad" => ApertureFunction::ThermalReliefPad,
+        "WasherPad" => ApertureFunction::WasherPad,
+        "AntiPad" => ApertureFunction::AntiPad,
+        "OtherPad" => ApertureFunction::OtherPad(rest()),
+        "Conductor" => ApertureFunction::Conductor,
+        "EtchedComponent" => ApertureFunction::EtchedComponent,
+        "NonConductor" => ApertureFunction::NonConductor,
+        "CopperBalancing" => ApertureFunction::CopperBalancing,
+        "Border" => ApertureFunction::Border,
+        "OtherCopper" => ApertureFunction::OtherCopper(rest()),
+        "ComponentMain" => ApertureFunction::ComponentMain,
+        "ComponentOutline" => ApertureFunction::ComponentOutline(f(1)?.parse()?),
+        "ComponentPin" => ApertureFunction::ComponentPin,
+        "Profile" => ApertureFunction::Profile,
+        "NonMaterial" => ApertureFunction::NonMaterial,
+        "Material" => ApertureFunction::Material,
+        "Other" => ApertureFunction::Other(rest()),
+        "Slot" => ApertureFunction::Slot,
+        "Cavity" => ApertureFunction::Cavity,
+        "CutOut" => ApertureFunction::CutOut,
+        "Drawing" => ApertureFunction::Drawing,
+        other => {
+            return Err(GerberError::ConversionError(format!(
+                "Unknown aperture function '{}'",
+                other
+            )))
+        }
+    })
+}
+
+fn parse_object_attribute(value: &str) -> GerberResult<ObjectAttribute> {
+    let (name, values) = split_attribute(value);
+    let values: Vec<&str> = values.iter().map(String::as_str).collect();
+    let f = |index: usize| field(&values, index, name);
+    let text = || -> GerberResult<String> { Ok(values[f(0).map(|_| 0)?..].join(",")) };
+    let characteristics = match name {
+        ".N" => {
+            return Ok(ObjectAttribute::Net(match values[..] {
+                [] | [""] => Net::None,
+                ["N/C"] => Net::NotConnected,
+                _ => Net::Connected(values.iter().map(|net| net.to_string()).collect()),
+            }))
+        }
+        ".P" => {
+            return Ok(ObjectAttribute::Pin(Pin {
+                refdes: f(0)?.to_string(),
+                name: f(1)?.to_string(),
+                function: values.get(2).map(|function| function.to_string()),
+            }))
+        }
+        ".C" => return Ok(ObjectAttribute::Component(f(0)?.to_string())),
+        ".CRot" => ComponentCharacteristics::Rotation(decimal(f(0)?)?),
+        ".CMfr" => ComponentCharacteristics::Manufacturer(text()?),
+        ".CMPN" => ComponentCharacteristics::MPN(text()?),
+        ".CVal" => ComponentCharacteristics::Value(text()?),
+        ".CMnt" => ComponentCharacteristics::Mount(f(0)?.parse()?),
+        ".CFtp" => ComponentCharacteristics::Footprint(text()?),
+        ".CPgN" => ComponentCharacteristics::PackageName(text()?),
+        ".CPgD" => ComponentCharacteristics::PackageDescription(text()?),
+        ".CHgt" => ComponentCharacteristics::Height(decimal(f(0)?)?),
+        ".CLbN" => ComponentCharacteristics::LibraryName(text()?),
+        ".CLbD" => ComponentCharacteristics::LibraryDescription(text()?),
+        ".CSup" => {
+            if values.len() % 2 != 0 {
+                return Err(GerberError::MissingDataError(
+                    "The values of .CSup must be pairs of supplier and part".into(),
+                ));
+            }
+            ComponentCharacteristics::Supplier(
+                values
+                    .chunks(2)
+                    .map(|pair| SupplierPart {
+                        supplier_name: pair[0].to_string(),
+                        supplier_part_reference: pair[1].to_string(),
+                    })
+                    .collect(),
+            )
+        }
+        _ => {
+            let (name, values) = user_defined(name, &values);
+            return Ok(ObjectAttribute::UserDefined { name, values });
+        }
+    };
+    Ok(ObjectAttribute::ComponentCharacteristics(characteristics))
+}
+
+fn parse_deletion_criterion(value: &str) -> AttributeDeletionCriterion {
+    match value {
+        "" => AttributeDeletionCriterion::AllApertureAndObjectAttributes,
+        ".AperFunction" | ".DrillTolerance" | ".FlashText" => {
+            AttributeDeletionCriterion::SingleApertureAttribute(value.to_string())
+        }
+        _ => AttributeDeletionCriterion::SingleObjectAttribute(value.to_string()),
+    }
+}
+
+// Values
+
+fn number<T: FromStr>(value: &str, name: &str) -> GerberResult<T> {
+    value
+        .parse()
+        .map_err(|_| GerberError::ConversionError(format!("Invalid {} '{}'", name, value)))
+}
+
+fn decimal(value: &str) -> GerberResult<f64> {
+    number(value, "decimal")
+}
+
+/// Splits e.g. `X2Y3I1.5J2` into `[('X', "2"), ('Y', "3"), ('I', "1.5"), ('J', "2")]`.
+fn letter_fields<'a>(value: &'a str, letters: &str) -> GerberResult<Vec<(char, &'a str)>> {
+    let mut fields = Vec::new();
+    let mut rest = value;
+    while let Some(letter) = rest.chars().next() {
+        if !letters.contains(letter) {
+            return Err(GerberError::ConversionError(format!(
+                "Unexpected '{}' in '{}'",
+                letter, value
+            )));
+        }
+        let end = rest[1..]
+            .find(|c: char| letters.contains(c))
+            .map_or(rest.len(), |end| end + 1);
+        fields.push((letter, &rest[1..end]));
+        rest = &rest[end..];
+    }
+    Ok(fields)
+}
+
+fn parse_a_b(value: &str, default: f64) -> GerberResult<(f64, f64)> {
+    let mut a = default;
+    let mut b = default;
+    for (letter, value) in letter_fields(value, "AB")? {
+        match letter {
+            'A' => a = decimal(value)?,
+            _ => b = decimal(value)?,
+        }
+    }
+    Ok((a, b))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::convert::TryFrom;
+
+    use crate::coordinates::{CoordinateNumber, CoordinateOffset, Coordinates, ZeroOmission};
+    use crate::traits::GerberCode;
+
+    fn round_trip(commands: Vec<Command>) {
+        let mut buf = Vec::new();
+        commands.serialize(&mut buf).unwrap();
+        let parsed = parse_gerber(&buf[..]).unwrap();
+        assert_eq!(parsed, commands, "{}", String::from_utf8(buf).unwrap());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 6);
+        let coordinate = |value: f64| CoordinateNumber::try_from(value).unwrap();
+        let thermal = ThermalPrimitive::new(
+            MacroDecimal::Value(0.055),
+            MacroDecimal::Variable(1),
+            MacroDecimal::Value(0.0125),
+        );
+        let outline = OutlinePrimitive::from_points(vec![
+            (MacroDecimal::Value(0.0), MacroDecimal::Value(0.0)),
+            (MacroDecimal::Value(1.0), MacroDecimal::Value(0.0)),
+            (MacroDecimal::Value(1.0), MacroDecimal::Value(1.0)),
+            (MacroDecimal::Value(0.0), MacroDecimal::Value(0.0)),
+        ]);
+        let commands: Vec<Command> = vec![
+            GCode::Comment(CommentContent::String("Round trip".to_string())).into(),
+            GCode::Comment(CommentContent::Standard(StandardComment::FileAttribute(
+                FileAttribute::FilePolarity(FilePolarity::Positive),
+            )))
+            .into(),
+            ExtendedCode::CoordinateFormat(cf).into(),
+            ExtendedCode::Unit(Unit::Millimeters).into(),
+            ExtendedCode::FileAttribute(FileAttribute::GenerationSoftware(
+                GenerationSoftware::new("Vendor", "Application", Some("1.0")),
+            ))
+            .into(),
+            ExtendedCode::FileAttribute(FileAttribute::FileFunction(FileFunction::Copper {
+                layer: 1,
+                pos: ExtendedPosition::Top,
+                copper_type: Some(CopperType::Signal),
+            }))
+            .into(),
+            ExtendedCode::FileAttribute(FileAttribute::Part(Part::Other("test".to_string())))
+                .into(),
+            ExtendedCode::FileAttribute(FileAttribute::UserDefined {
+                name: "Custom".to_string(),
+                values: vec!["a".to_string(), "b".to_string()],
+            })
+            .into(),
+            ExtendedCode::ApertureMacro(
+                ApertureMacro::new("THERMAL")
+                    .add_content("a comment")
+                    .add_content(VariableDefinition::new(2, "$1x0.75"))
+                    .add_content(thermal)
+                    .add_content(outline)
+                    .add_content(CirclePrimitive::new(MacroDecimal::Value(0.5))),
+            )
+            .into(),
+            ExtendedCode::ApertureAttribute(ApertureAttribute::ApertureFunction(
+                ApertureFunction::SmdPad(SmdPadType::CopperDefined),
+            ))
+            .into(),
+            ExtendedCode::ApertureDefinition(ApertureDefinition::new(
+                10,
+                Aperture::Circle(Circle::with_hole(0.5, 0.25)),
+            ))
+            .into(),
+            ExtendedCode::ApertureDefinition(ApertureDefinition::new(
+                11,
+                Aperture::Obround(Rectangular::new(1.0, 0.5)),
+            ))
+            .into(),
+            ExtendedCode::ApertureDefinition(ApertureDefinition::new(
+                12,
+                Aperture::Polygon(Polygon::new(1.0, 6).with_rotation(30.0)),
+            ))
+            .into(),
+            ExtendedCode::ApertureDefinition(ApertureDefinition::new(
+                13,
+                Aperture::Macro("THERMAL".to_string(), Some(vec![MacroDecimal::Value(0.08)])),
+            ))
+            .into(),
+            ExtendedCode::DeleteAttribute(AttributeDeletionCriterion::SingleApertureAttribute(
+                ".AperFunction".to_string(),
+            ))
+            .into(),
+            ExtendedCode::LoadPolarity(Polarity::Dark).into(),
+            DCode::SelectAperture(10).into(),
+            ExtendedCode::ObjectAttribute(ObjectAttribute::Net(Net::Connected(vec![
+                "GND".to_string()
+            ])))
+            .into(),
+            ExtendedCode::ObjectAttribute(ObjectAttribute::ComponentCharacteristics(
+                ComponentCharacteristics::Mount(ComponentMounting::SMD),
+            ))
+            .into(),
+            DCode::Operation(Operation::Move(Some(Coordinates::new(0, 0, cf)))).into(),
+            GCode::InterpolationMode(InterpolationMode::Linear).into(),
+            DCode::Operation(Operation::Interpolate(
+                Some(Coordinates::at_x(coordinate(5.5), cf)),
+                None,
+            ))
+            .into(),
+            GCode::QuadrantMode(QuadrantMode::Multi).into(),
+            GCode::InterpolationMode(InterpolationMode::CounterclockwiseCircular).into(),
+            DCode::Operation(Operation::Interpolate(
+                Some(Coordinates::new(0, coordinate(-5.5), cf)),
+                Some(CoordinateOffset::new(coordinate(-2.75), 0, cf)),
+            ))
+            .into(),
+            ExtendedCode::DeleteAttribute(
+                AttributeDeletionCriterion::AllApertureAndObjectAttributes,
+            )
+            .into(),
+            GCode::RegionMode(RegionMode::On).into(),
+            GCode::RegionMode(RegionMode::Off).into(),
+            ExtendedCode::StepAndRepeat(StepAndRepeat::Open {
+                repeat_x: 2,
+                repeat_y: 3,
+                distance_x: 1.5,
+                distance_y: 2.0,
+            })
+            .into(),
+            ExtendedCode::ApertureBlock(ApertureBlock::Open { code: 100 }).into(),
+            DCode::Operation(Operation::Flash(Some(Coordinates::new(1, 1, cf)))).into(),
+            ExtendedCode::ApertureBlock(ApertureBlock::Close).into(),
+            ExtendedCode::StepAndRepeat(StepAndRepeat::Close).into(),
+            MCode::EndOfFile.into(),
+        ];
+        round_trip(commands);
+    }
+
+    #[test]
+    fn test_aperture_macro_one_primitive_per_line() {
+        let am = ApertureMacro::new("PAD")
+            .add_content("pad with a hole")
+            .add_content(CirclePrimitive::new(MacroDecimal::Value(1.0)))
+            .add_content(
+                CenterLinePrimitive::new((MacroDecimal::Value(2.0), MacroDecimal::Value(0.5)))
+                    .with_angle(MacroDecimal::Value(45.0)),
+            )
+            .add_content(
+                CirclePrimitive::new(MacroDecimal::Value(0.3))
+                    .with_exposure(MacroBoolean::Value(false)),
+            );
+        let mut buf = Vec::new();
+        ExtendedCode::ApertureMacro(am.clone())
+            .serialize(&mut buf)
+            .unwrap();
+        let code = String::from_utf8(buf).unwrap();
+        assert_eq!(
+            code,
+            "%AMPAD*\n0 pad with a hole*\n1,1,1,0,0*\n21,1,2,0.5,0,0,45*\n1,0,0.3,0,0*%\n"
+        );
+
+        // `%` only at the start of the first and the end of the last line
+        let lines: Vec<&str> = code.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[0].starts_with('%'));
+        assert!(lines[4].ends_with("*%"));
+        assert_eq!(code.matches('%').count(), 2);
+        // every line holds exactly one macro word
+        assert!(lines.iter().all(|line| line.matches('*').count() == 1));
+
+        assert_eq!(
+            parse_gerber(code.as_bytes()).unwrap(),
+            vec![Command::from(ExtendedCode::ApertureMacro(am))]
+        );
+    }
+
+    #[test]
+    fn test_round_trip_escaped_fields() {
+        round_trip(vec![
+            ExtendedCode::ObjectAttribute(ObjectAttribute::Component("R,1*".to_string())).into(),
+            ExtendedCode::ObjectAttribute(ObjectAttribute::Pin(Pin {
+                refdes: "U1".to_string(),
+                name: "100%".to_string(),
+                function: Some("A\\B".to_string()),
+            }))
+            .into(),
+        ]);
+    }
+
+    #[test]
+    fn test_parse_legacy_commands() {
+        let gerber =
+            "%FSLAX26Y26*MOIN*%\n%IPPOS*%\n%MIA1*%\n%OFA0.5*%\nG54D10*\nG01X1000000Y0D01*\nM02*\n";
+        let commands = parse_gerber(gerber.as_bytes()).unwrap();
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 6);
+        assert_eq!(
+            commands,
+            vec![
+                Command::from(ExtendedCode::CoordinateFormat(cf)),
+                ExtendedCode::Unit(Unit::Inches).into(),
+                ExtendedCode::ImagePolarity(ImagePolarity::Positive).into(),
+                ExtendedCode::MirrorImage(ImageMirroring::A).into(),
+                ExtendedCode::OffsetImage(ImageOffset { a: 0.5, b: 0.0 }).into(),
+                GCode::SelectAperture.into(),
+                DCode::SelectAperture(10).into(),
+                GCode::InterpolationMode(InterpolationMode::Linear).into(),
+                DCode::Operation(Operation::Interpolate(
+                    Some(Coordinates::new(1, 0, cf)),
+                    None
+                ))
+                .into(),
+                MCode::EndOfFile.into(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = parse_gerber("%FSLAX26Y26*%\nG04 ok*\nG99*\n".as_bytes()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Conversion between two types failed: Line 3: Unknown command 'G99*'"
+        );
+
+        let error = parse_gerber("%MOMM*%\nX100Y100D01*\n".as_bytes()).unwrap_err();
+        assert!(matches!(error, GerberError::MissingDataError(ref m) if m.starts_with("Line 2:")));
+
+        let error = parse_gerber("G04 ok*\n\n%XX1*%\n".as_bytes()).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("Line 3: Unknown extended command '%XX1*%'"));
+
+        let error = parse_gerber("%FSLAX26Y26*%\n%TF.FileFunction,Copper,L1,Middle*%".as_bytes())
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("Line 2: Unknown ExtendedPosition 'Middle', expected one of: Top, Inr, Bot"));
+
+        assert!(parse_gerber("%MOMM*\n".as_bytes()).is_err());
+        assert!(parse_gerber("M02".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_parse_non_ascii() {
+        let commands = parse_gerber("G04 Größe*\nG04 #@! Té*\nG04 é*\n".as_bytes()).unwrap();
+        assert_eq!(
+            commands,
+            vec![
+                Command::from(GCode::Comment(CommentContent::String("Größe".to_string()))),
+                GCode::Comment(CommentContent::String("#@! Té".to_string())).into(),
+                GCode::Comment(CommentContent::String("é".to_string())).into(),
+            ]
+        );
+
+        assert!(matches!(
+            parse_gerber("%Aé*%\n".as_bytes()),
+            Err(GerberError::ConversionError(_))
+        ));
+        for input in [
+            "%é*%\n",
+            "%ADDé*%\n",
+            "é*\n",
+            "%AMé*1,é*%\n",
+            "%FSLAX26Y26*%\nXé*\n",
+            "%FSLAX26Y26*%\nD1é*\n",
+        ] {
+            assert!(parse_gerber(input.as_bytes()).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_outline_huge_vertex_count() {
+        let error = parse_gerber(b"%AMX*4,1,9223372036854775807,0,0*%\n".as_slice()).unwrap_err();
+        assert!(matches!(error, GerberError::ConversionError(_)));
+        assert!(error.to_string().contains("too large"));
+
+        let error = parse_gerber(b"%AMX*4,1,1000,0,0*%\n".as_slice()).unwrap_err();
+        assert!(matches!(error, GerberError::ConversionError(_)));
+    }
+}
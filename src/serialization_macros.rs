@@ -9,3 +9,48 @@ macro_rules! impl_partial_gerber_code_via_strum {
         }
     };
 }
+
+/// Implement `PartialGerberCode` for attributes with free-text fields, which are written
+/// escaped.
+macro_rules! impl_partial_gerber_code_via_fields {
+    ($name:ident) => {
+        impl<W: Write> PartialGerberCode<W> for $name {
+            fn serialize_partial(&self, writer: &mut W) -> GerberResult<()> {
+                self.serialize_fields(writer, false)
+            }
+        }
+    };
+}
+
+/// Implement `FromStr` and `TryFrom<&str>` by looking up the Gerber representation of the
+/// variants.
+macro_rules! impl_from_str_via_strum {
+    ($name:ident) => {
+        impl core::str::FromStr for $name {
+            type Err = $crate::errors::GerberError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                <$name as ::strum::VariantArray>::VARIANTS
+                    .iter()
+                    .find(|variant| <&'static str>::from(*variant) == s)
+                    .cloned()
+                    .ok_or_else(|| {
+                        $crate::errors::GerberError::ConversionError(alloc::format!(
+                            "Unknown {} '{}', expected one of: {}",
+                            stringify!($name),
+                            s,
+                            <$name as ::strum::VariantNames>::VARIANTS.join(", ")
+                        ))
+                    })
+            }
+        }
+
+        impl core::convert::TryFrom<&str> for $name {
+            type Error = $crate::errors::GerberError;
+
+            fn try_from(s: &str) -> Result<Self, Self::Error> {
+                s.parse()
+            }
+        }
+    };
+}
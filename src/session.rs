@@ -0,0 +1,282 @@
+//! Stateful helpers that keep track of the aperture definitions while generating a file.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::coordinates::CoordinateFormat;
+use crate::errors::{GerberError, GerberResult};
+use crate::extended_codes::{Aperture, ApertureDefinition};
+use crate::function_codes::{DCode, GCode, InterpolationMode, Operation};
+use crate::generators::coordinates;
+use crate::types::{Command, FunctionCode};
+
+/// Buffers the commands of a file and, on [`GeneratorSession::finish`], defines only the
+/// apertures that are actually used.
+///
+/// Apertures are registered and selected with [`GeneratorSession::use_aperture`]. An aperture
+/// is used when something is drawn or flashed while it is selected, its definition is then
+/// emitted just before the aperture is selected for the first time. Selections of apertures
+/// that are never drawn or flashed with are dropped as well.
+#[derive(Debug, Clone)]
+pub struct GeneratorSession {
+    format: CoordinateFormat,
+    apertures: Vec<ApertureDefinition>,
+    commands: Vec<Command>,
+    interpolation_mode: Option<InterpolationMode>,
+}
+
+impl GeneratorSession {
+    pub fn new(format: CoordinateFormat) -> Self {
+        GeneratorSession {
+            format,
+            apertures: Vec::new(),
+            commands: Vec::new(),
+            interpolation_mode: None,
+        }
+    }
+
+    /// Registers the aperture and selects it.
+    ///
+    /// Using the same definition again only selects it, using a different aperture with the
+    /// code of an already registered one is an error.
+    pub fn use_aperture(&mut self, definition: ApertureDefinition) -> GerberResult<()> {
+        match self
+            .apertures
+            .iter()
+            .find(|existing| existing.code == definition.code)
+        {
+            Some(existing) if *existing != definition => {
+                return Err(GerberError::ValidationError(format!(
+                    "Aperture D{} is already defined as a different aperture",
+                    definition.code
+                )));
+            }
+            Some(_) => {}
+            None => self.apertures.push(definition.clone()),
+        }
+        self.commands
+            .push(DCode::SelectAperture(definition.code).into());
+        Ok(())
+    }
+
+    /// Moves to `x`,`y` without drawing.
+    pub fn move_to(&mut self, x: f64, y: f64) -> GerberResult<()> {
+        let coords = coordinates(x, y, self.format)?;
+        self.commands
+            .push(DCode::Operation(Operation::Move(Some(coords))).into());
+        Ok(())
+    }
+
+    /// Draws a straight line from the current point to `x`,`y` with the selected aperture.
+    pub fn draw_to(&mut self, x: f64, y: f64) -> GerberResult<()> {
+        let coords = coordinates(x, y, self.format)?;
+        if self.interpolation_mode != Some(InterpolationMode::Linear) {
+            self.commands
+                .push(GCode::InterpolationMode(InterpolationMode::Linear).into());
+            self.interpolation_mode = Some(InterpolationMode::Linear);
+        }
+        self.commands
+            .push(DCode::Operation(Operation::Interpolate(Some(coords), None)).into());
+        Ok(())
+    }
+
+    /// Flashes the selected aperture at `x`,`y`.
+    pub fn flash(&mut self, x: f64, y: f64) -> GerberResult<()> {
+        let coords = coordinates(x, y, self.format)?;
+        self.commands
+            .push(DCode::Operation(Operation::Flash(Some(coords))).into());
+        Ok(())
+    }
+
+    /// Adds any other command, e.g. attributes or polarity changes.
+    ///
+    /// Apertures selected this way that were not registered with `use_aperture` are assumed to
+    /// be defined elsewhere.
+    pub fn push(&mut self, command: Command) {
+        if let Command::FunctionCode(FunctionCode::GCode(GCode::InterpolationMode(mode))) = command
+        {
+            self.interpolation_mode = Some(mode);
+        }
+        self.commands.push(command);
+    }
+
+    /// Generates the commands, with the definitions of the used apertures.
+    pub fn finish(self) -> Vec<Command> {
+        let mut result = Vec::with_capacity(self.commands.len() + self.apertures.len());
+        let mut defined: HashSet<i32> = HashSet::new();
+        let mut pending_selection: Option<i32> = None;
+        let mut selected: Option<i32> = None;
+        let mut region_mode = false;
+
+        for command in self.commands {
+            match command {
+                Command::FunctionCode(FunctionCode::DCode(DCode::SelectAperture(code))) => {
+                    pending_selection = Some(code);
+                }
+                Command::FunctionCode(FunctionCode::GCode(GCode::RegionMode(mode))) => {
+                    region_mode = mode.is_on();
+                    result.push(command);
+                }
+                Command::FunctionCode(FunctionCode::DCode(DCode::Operation(ref operation))) => {
+                    let uses_aperture = match operation {
+                        Operation::Interpolate(..) => !region_mode,
+                        Operation::Move(_) => false,
+                        Operation::Flash(_) => true,
+                    };
+                    if uses_aperture {
+                        if let Some(code) = pending_selection.take() {
+                            if defined.insert(code) {
+                                if let Some(definition) =
+                                    self.apertures.iter().find(|def| def.code == code)
+                                {
+                                    result.push(definition.clone().into());
+                                }
+                            }
+                            if selected != Some(code) {
+                                result.push(DCode::SelectAperture(code).into());
+                                selected = Some(code);
+                            }
+                        }
+                    }
+                    result.push(command);
+                }
+                _ => result.push(command),
+            }
+        }
+        result
+    }
+}
+
+/// The apertures defined so far, for [`define_aperture_once`].
+#[derive(Debug, Clone, Default)]
+pub struct DefinedApertures {
+    apertures: HashMap<i32, Aperture>,
+}
+
+impl DefinedApertures {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_defined(&self, code: i32) -> bool {
+        self.apertures.contains_key(&code)
+    }
+}
+
+/// Defines the aperture, unless it is already defined.
+///
+/// Returns the aperture definition the first time a code is requested and nothing if the same
+/// aperture was already defined with that code. Requesting a different aperture with the code of
+/// an already defined one is an error.
+pub fn define_aperture_once(
+    defined: &mut DefinedApertures,
+    code: i32,
+    aperture: Aperture,
+) -> GerberResult<Vec<Command>> {
+    match defined.apertures.get(&code) {
+        Some(existing) if *existing == aperture => Ok(Vec::new()),
+        Some(_) => Err(GerberError::ValidationError(format!(
+            "Aperture D{} is already defined as a different aperture",
+            code
+        ))),
+        None => {
+            defined.apertures.insert(code, aperture.clone());
+            Ok(vec![ApertureDefinition::new(code, aperture).into()])
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::io::BufWriter;
+
+    use crate::traits::GerberCode;
+    use crate::{Circle, CoordinateMode, Rectangular, ZeroOmission};
+
+    fn format() -> CoordinateFormat {
+        CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 4)
+    }
+
+    #[test]
+    fn test_unused_aperture_is_omitted() {
+        let mut session = GeneratorSession::new(format());
+        session
+            .use_aperture(ApertureDefinition::new(
+                10,
+                Aperture::Circle(Circle::new(0.5)),
+            ))
+            .unwrap();
+        session
+            .use_aperture(ApertureDefinition::new(
+                11,
+                Aperture::Rectangle(Rectangular::new(1.0, 2.0)),
+            ))
+            .unwrap();
+        session.flash(1.0, 1.0).unwrap();
+        session
+            .use_aperture(ApertureDefinition::new(
+                12,
+                Aperture::Circle(Circle::new(0.1)),
+            ))
+            .unwrap();
+        session.move_to(0.0, 0.0).unwrap();
+
+        assert_code!(
+            session.finish(),
+            "%ADD11R,1X2*%\nD11*\nX10000Y10000D03*\nX0Y0D02*\n"
+        );
+    }
+
+    #[test]
+    fn test_aperture_defined_once_before_first_use() {
+        let circle = ApertureDefinition::new(10, Aperture::Circle(Circle::new(0.5)));
+        let mut session = GeneratorSession::new(format());
+        session.use_aperture(circle.clone()).unwrap();
+        session.move_to(0.0, 0.0).unwrap();
+        session.draw_to(1.0, 0.0).unwrap();
+        session.use_aperture(circle).unwrap();
+        session.draw_to(1.0, 1.0).unwrap();
+
+        assert_code!(
+            session.finish(),
+            "X0Y0D02*\nG01*\n%ADD10C,0.5*%\nD10*\nX10000Y0D01*\nX10000Y10000D01*\n"
+        );
+    }
+
+    #[test]
+    fn test_define_aperture_once() {
+        let mut defined = DefinedApertures::new();
+        let circle = Aperture::Circle(Circle::new(0.5));
+
+        let commands = define_aperture_once(&mut defined, 10, circle.clone()).unwrap();
+        assert_code!(commands, "%ADD10C,0.5*%\n");
+        assert!(defined.is_defined(10));
+
+        assert!(define_aperture_once(&mut defined, 10, circle)
+            .unwrap()
+            .is_empty());
+        assert!(define_aperture_once(
+            &mut defined,
+            10,
+            Aperture::Rectangle(Rectangular::new(1.0, 1.0))
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_conflicting_aperture_code() {
+        let mut session = GeneratorSession::new(format());
+        session
+            .use_aperture(ApertureDefinition::new(
+                10,
+                Aperture::Circle(Circle::new(0.5)),
+            ))
+            .unwrap();
+        let result = session.use_aperture(ApertureDefinition::new(
+            10,
+            Aperture::Circle(Circle::new(0.6)),
+        ));
+        assert!(result.is_err());
+    }
+}
@@ -1,13 +1,82 @@
 //! Traits used in gerber-types.
 
-use std::io::Write;
+use crate::io::Write;
+use crate::prelude::*;
+use crate::{GerberError, GerberResult};
 
-use crate::GerberResult;
+/// Options that change how Gerber code is generated, see [`GerberCode::serialize_with`].
+///
+/// The default options generate the same code as [`GerberCode::serialize`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SerializationOptions {
+    /// Omit the newline after the closing `%` of extended codes, e.g. `%MOMM*%` instead of
+    /// `%MOMM*%\n`, for readers that want to append to the same line.
+    pub no_trailing_newline: bool,
+    /// Write a `+` before positive coordinate numbers, e.g. `X+100000` instead of `X100000`, for
+    /// readers that expect a sign on every number.
+    pub explicit_positive_sign: bool,
+    /// Write free-text attribute fields verbatim, without escaping `,`, `*`, `%` and `\` as
+    /// `\uXXXX`, e.g. for fields that are already escaped.
+    pub raw_attribute_fields: bool,
+}
 
 /// All types that implement this trait can be converted to a complete Gerber
 /// Code line. Generated code should end with a newline.
 pub trait GerberCode<W: Write> {
     fn serialize(&self, writer: &mut W) -> GerberResult<()>;
+
+    /// Like `serialize`, but the value is validated first and nothing is written if it is
+    /// invalid. See [`StrictValidation`].
+    fn serialize_strict(&self, writer: &mut W) -> GerberResult<()>
+    where
+        Self: StrictValidation,
+    {
+        self.validate_strict()?;
+        self.serialize(writer)
+    }
+
+    /// Like `serialize`, but with options that change the generated code.
+    ///
+    /// Types that are not affected by any of the options use `serialize`.
+    fn serialize_with(&self, writer: &mut W, _options: &SerializationOptions) -> GerberResult<()> {
+        self.serialize(writer)
+    }
+}
+
+/// Convenience methods to generate Gerber code in memory, for all types that implement
+/// [`GerberCode`].
+///
+/// This is a separate trait because `GerberCode` is generic over the writer, the methods use a
+/// `Vec<u8>`.
+pub trait GerberCodeExt {
+    /// The generated Gerber code as bytes.
+    fn to_gerber_bytes(&self) -> GerberResult<Vec<u8>>;
+
+    /// The generated Gerber code as a string.
+    fn to_gerber_string(&self) -> GerberResult<String>;
+}
+
+impl<T: GerberCode<Vec<u8>> + ?Sized> GerberCodeExt for T {
+    fn to_gerber_bytes(&self) -> GerberResult<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.serialize(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn to_gerber_string(&self) -> GerberResult<String> {
+        let bytes = self.to_gerber_bytes()?;
+        String::from_utf8(bytes).map_err(|error| {
+            GerberError::ConversionError(format!("Generated code is not valid UTF-8: {}", error))
+        })
+    }
+}
+
+/// All types that implement this trait can check that they would generate valid Gerber code.
+///
+/// e.g. aperture codes are in range, decimals are finite and attribute fields do not contain
+/// reserved characters.
+pub trait StrictValidation {
+    fn validate_strict(&self) -> GerberResult<()>;
 }
 
 /// All types that implement this trait can be converted to a Gerber Code
@@ -17,3 +86,11 @@ pub trait GerberCode<W: Write> {
 pub trait PartialGerberCode<W: Write> {
     fn serialize_partial(&self, writer: &mut W) -> GerberResult<()>;
 }
+
+/// Like [`PartialGerberCode`], for attributes with free-text fields. The fields are escaped
+/// unless `raw` is set, see [`SerializationOptions::raw_attribute_fields`].
+///
+/// This is a crate-internal trait.
+pub trait PartialAttributeCode<W: Write> {
+    fn serialize_fields(&self, writer: &mut W, raw: bool) -> GerberResult<()>;
+}
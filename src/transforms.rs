@@ -0,0 +1,554 @@
+//! Geometric transformations of command streams.
+//!
+//! The transformations only support streams with absolute coordinates.
+
+use crate::coordinates::{CoordinateNumber, CoordinateOffset, Coordinates};
+use crate::errors::{GerberError, GerberResult, LocatedError};
+use crate::extended_codes::{
+    Aperture, ApertureDefinition, ImageRotation, Polarity, Rectangular, StepAndRepeat, Unit,
+};
+use crate::function_codes::{DCode, GCode, Operation, QuadrantMode};
+use crate::prelude::*;
+use crate::types::{Command, ExtendedCode, FunctionCode};
+
+fn operation_coordinates_mut(command: &mut Command) -> Option<&mut Coordinates> {
+    match command {
+        Command::FunctionCode(FunctionCode::DCode(DCode::Operation(operation))) => {
+            match operation {
+                Operation::Interpolate(coordinates, _)
+                | Operation::Move(coordinates)
+                | Operation::Flash(coordinates) => coordinates.as_mut(),
+            }
+        }
+        _ => None,
+    }
+}
+
+fn operation_coordinates(command: &Command) -> Option<&Coordinates> {
+    match command {
+        Command::FunctionCode(FunctionCode::DCode(DCode::Operation(operation))) => {
+            match operation {
+                Operation::Interpolate(coordinates, _)
+                | Operation::Move(coordinates)
+                | Operation::Flash(coordinates) => coordinates.as_ref(),
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Moves all coordinates so the lowest X and Y coordinates are at 0.
+///
+/// Only the coordinates of the operations are considered, not the size of the apertures.
+/// Arc center offsets are relative to the start point so they are not changed.
+///
+/// Returns the offset that was added to every coordinate, `(0.0, 0.0)` if there are no
+/// coordinates.
+pub fn translate_to_origin(commands: &mut [Command]) -> (f64, f64) {
+    let mut min_x: Option<i64> = None;
+    let mut min_y: Option<i64> = None;
+    for coordinates in commands.iter().filter_map(operation_coordinates) {
+        if let Some(x) = coordinates.x {
+            min_x = Some(min_x.map_or(x.nano(), |min| min.min(x.nano())));
+        }
+        if let Some(y) = coordinates.y {
+            min_y = Some(min_y.map_or(y.nano(), |min| min.min(y.nano())));
+        }
+    }
+    let (dx, dy) = (-min_x.unwrap_or(0), -min_y.unwrap_or(0));
+
+    for coordinates in commands.iter_mut().filter_map(operation_coordinates_mut) {
+        if let Some(x) = coordinates.x {
+            coordinates.x = Some(CoordinateNumber::new(x.nano() + dx));
+        }
+        if let Some(y) = coordinates.y {
+            coordinates.y = Some(CoordinateNumber::new(y.nano() + dy));
+        }
+    }
+
+    (
+        f64::from(CoordinateNumber::new(dx)),
+        f64::from(CoordinateNumber::new(dy)),
+    )
+}
+
+/// Rotates the point counterclockwise, around the origin.
+///
+/// Coordinates are modal, a missing X or Y coordinate stays missing. It ends up on the other axis
+/// for quarter turns, since the rotated X only depends on the original Y and vice versa.
+fn rotate(
+    x: Option<CoordinateNumber>,
+    y: Option<CoordinateNumber>,
+    rotation: ImageRotation,
+) -> (Option<CoordinateNumber>, Option<CoordinateNumber>) {
+    let negate = |n: Option<CoordinateNumber>| n.map(|n| CoordinateNumber::new(-n.nano()));
+    match rotation {
+        ImageRotation::None => (x, y),
+        ImageRotation::CCW_90 => (negate(y), x),
+        ImageRotation::CCW_180 => (negate(x), negate(y)),
+        ImageRotation::CCW_270 => (y, negate(x)),
+    }
+}
+
+/// Rotates a standard aperture counterclockwise around its center.
+fn rotate_aperture(aperture: &mut Aperture, rotation: ImageRotation) {
+    let quarter_turn = matches!(rotation, ImageRotation::CCW_90 | ImageRotation::CCW_270);
+    match aperture {
+        Aperture::Rectangle(Rectangular { x, y, .. })
+        | Aperture::Obround(Rectangular { x, y, .. })
+            if quarter_turn =>
+        {
+            core::mem::swap(x, y)
+        }
+        Aperture::Polygon(polygon) => {
+            let degrees = match rotation {
+                ImageRotation::None => return,
+                ImageRotation::CCW_90 => 90.0,
+                ImageRotation::CCW_180 => 180.0,
+                ImageRotation::CCW_270 => 270.0,
+            };
+            polygon.rotation = Some((polygon.rotation.unwrap_or(0.0) + degrees) % 360.0);
+        }
+        _ => {}
+    }
+}
+
+/// Rotates all coordinates and arc center offsets counterclockwise around the origin.
+///
+/// A rotation does not change the direction of arcs, so clockwise arcs stay clockwise. In
+/// single quadrant mode (G74) the center offsets are unsigned, they stay unsigned.
+///
+/// Rectangle, obround and polygon aperture definitions are rotated as well, so flashes keep their
+/// orientation relative to the image. Macro apertures and aperture blocks are not rotated, use
+/// a load rotation (`%LR`) for flashes of those.
+pub fn rotate_commands(commands: &[Command], rotation: ImageRotation) -> Vec<Command> {
+    let mut single_quadrant = false;
+    commands
+        .iter()
+        .map(|command| {
+            let mut command = command.clone();
+            match &mut command {
+                Command::FunctionCode(FunctionCode::GCode(GCode::QuadrantMode(mode))) => {
+                    single_quadrant = *mode == QuadrantMode::Single;
+                }
+                Command::ExtendedCode(ExtendedCode::ApertureDefinition(definition)) => {
+                    rotate_aperture(&mut definition.aperture, rotation);
+                }
+                Command::FunctionCode(FunctionCode::DCode(DCode::Operation(operation))) => {
+                    let (coordinates, offset) = match operation {
+                        Operation::Interpolate(coordinates, offset) => {
+                            (coordinates, offset.as_mut())
+                        }
+                        Operation::Move(coordinates) | Operation::Flash(coordinates) => {
+                            (coordinates, None)
+                        }
+                    };
+                    if let Some(Coordinates { x, y, .. }) = coordinates {
+                        let (rx, ry) = rotate(*x, *y, rotation);
+                        *x = rx;
+                        *y = ry;
+                    }
+                    if let Some(CoordinateOffset { x, y, .. }) = offset {
+                        let (mut rx, mut ry) = rotate(*x, *y, rotation);
+                        if single_quadrant {
+                            let abs = |n: Option<CoordinateNumber>| {
+                                n.map(|n| CoordinateNumber::new(n.nano().abs()))
+                            };
+                            rx = abs(rx);
+                            ry = abs(ry);
+                        }
+                        *x = rx;
+                        *y = ry;
+                    }
+                }
+                _ => {}
+            }
+            command
+        })
+        .collect()
+}
+
+/// Splits the stream into the dark and the clear geometry, by the load polarity (`%LP`) that is
+/// active for each operation.
+///
+/// Operations (D01, D02 and D03) go to the partition of the active polarity, dark before the first
+/// `%LP`. All other commands, e.g. aperture definitions and selections, modes and attributes, are
+/// needed to render the operations, so they go to both partitions. The load polarity commands
+/// themselves are left out.
+///
+/// Interpolations start at the current point and coordinates are modal, so when a partition
+/// continues after operations that went to the other partition, a move (D02) to the current
+/// point is inserted first.
+///
+/// Returns the dark and the clear partition.
+pub fn split_by_polarity(commands: &[Command]) -> (Vec<Command>, Vec<Command>) {
+    let mut partitions = [Vec::new(), Vec::new()];
+    let mut in_sync = [true, true];
+    let mut polarity = Polarity::Dark;
+    let mut current: Option<Coordinates> = None;
+    for command in commands {
+        match command {
+            Command::ExtendedCode(ExtendedCode::LoadPolarity(new_polarity)) => {
+                polarity = *new_polarity;
+            }
+            Command::FunctionCode(FunctionCode::DCode(DCode::Operation(_))) => {
+                let index = match polarity {
+                    Polarity::Dark => 0,
+                    Polarity::Clear => 1,
+                };
+                if !in_sync[index] {
+                    if let Some(point) = &current {
+                        partitions[index]
+                            .push(DCode::Operation(Operation::Move(Some(point.clone()))).into());
+                    }
+                    in_sync[index] = true;
+                }
+                partitions[index].push(command.clone());
+                if let Some(coordinates) = operation_coordinates(command) {
+                    current = Some(match current {
+                        Some(point) => Coordinates {
+                            x: coordinates.x.or(point.x),
+                            y: coordinates.y.or(point.y),
+                            format: coordinates.format,
+                        },
+                        None => coordinates.clone(),
+                    });
+                    in_sync[1 - index] = false;
+                }
+            }
+            _ => {
+                for partition in partitions.iter_mut() {
+                    partition.push(command.clone());
+                }
+            }
+        }
+    }
+    let [dark, clear] = partitions;
+    (dark, clear)
+}
+
+/// Converts the dimensions of all aperture definitions (`%AD`) and the distances of all
+/// step-and-repeat blocks (`%SR`) from the unit `from` to the unit `to`, see
+/// [`Aperture::scaled`](crate::Aperture::scaled).
+///
+/// This is the counterpart of [`CoordinateNumber::convert`] for apertures. The unit (`%MO`)
+/// command is not changed. Macro apertures can not be converted, the meaning of their arguments
+/// is defined by the macro, so it is an error if the commands define one. The commands are not
+/// changed in that case.
+pub fn convert_aperture_units(commands: &mut [Command], from: Unit, to: Unit) -> GerberResult<()> {
+    let factor = match (from, to) {
+        (Unit::Inches, Unit::Millimeters) => 25.4,
+        (Unit::Millimeters, Unit::Inches) => 1.0 / 25.4,
+        _ => return Ok(()),
+    };
+    for (index, command) in commands.iter().enumerate() {
+        if let Command::ExtendedCode(ExtendedCode::ApertureDefinition(ApertureDefinition {
+            code,
+            aperture: Aperture::Macro(..),
+        })) = command
+        {
+            return Err(LocatedError::new(
+                index,
+                GerberError::ValidationError(format!(
+                    "The units of macro aperture D{} can not be converted",
+                    code
+                )),
+            )
+            .into());
+        }
+    }
+    for command in commands.iter_mut() {
+        match command {
+            Command::ExtendedCode(ExtendedCode::ApertureDefinition(definition)) => {
+                definition.aperture = definition.aperture.scaled(factor)?;
+            }
+            Command::ExtendedCode(ExtendedCode::StepAndRepeat(StepAndRepeat::Open {
+                distance_x,
+                distance_y,
+                ..
+            })) => {
+                *distance_x *= factor;
+                *distance_y *= factor;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{
+        Circle, CoordinateFormat, CoordinateMode, CoordinateOffset, GCode, InterpolationMode,
+        Polygon, ZeroOmission,
+    };
+
+    fn format() -> CoordinateFormat {
+        CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 6)
+    }
+
+    fn operation(operation: Operation) -> Command {
+        DCode::Operation(operation).into()
+    }
+
+    /// The two square boxes example from the spec, moved by (-2, 3).
+    fn two_boxes() -> Vec<Command> {
+        let cf = format();
+        vec![
+            DCode::SelectAperture(10).into(),
+            operation(Operation::Move(Some(Coordinates::new(-2, 3, cf)))),
+            GCode::InterpolationMode(InterpolationMode::Linear).into(),
+            operation(Operation::Interpolate(
+                Some(Coordinates::new(3, 3, cf)),
+                None,
+            )),
+            operation(Operation::Interpolate(Some(Coordinates::at_y(8, cf)), None)),
+            operation(Operation::Interpolate(
+                Some(Coordinates::at_x(-2, cf)),
+                None,
+            )),
+            operation(Operation::Interpolate(Some(Coordinates::at_y(3, cf)), None)),
+            operation(Operation::Move(Some(Coordinates::at_x(4, cf)))),
+            operation(Operation::Interpolate(Some(Coordinates::at_x(9, cf)), None)),
+            operation(Operation::Interpolate(Some(Coordinates::at_y(8, cf)), None)),
+            operation(Operation::Interpolate(Some(Coordinates::at_x(4, cf)), None)),
+            operation(Operation::Interpolate(Some(Coordinates::at_y(3, cf)), None)),
+        ]
+    }
+
+    #[test]
+    fn test_translate_to_origin() {
+        let cf = format();
+        let mut commands = two_boxes();
+        commands.push(operation(Operation::Interpolate(
+            Some(Coordinates::new(5, 4, cf)),
+            Some(CoordinateOffset::new(1, 0, cf)),
+        )));
+
+        let offset = translate_to_origin(&mut commands);
+        assert_eq!(offset, (2.0, -3.0));
+
+        assert_eq!(
+            commands[1],
+            operation(Operation::Move(Some(Coordinates::new(0, 0, cf))))
+        );
+        assert_eq!(
+            commands[4],
+            operation(Operation::Interpolate(Some(Coordinates::at_y(5, cf)), None))
+        );
+        assert_eq!(
+            commands[8],
+            operation(Operation::Interpolate(
+                Some(Coordinates::at_x(11, cf)),
+                None
+            ))
+        );
+        // the arc offset is relative and not changed
+        assert_eq!(
+            commands[12],
+            operation(Operation::Interpolate(
+                Some(Coordinates::new(7, 1, cf)),
+                Some(CoordinateOffset::new(1, 0, cf)),
+            ))
+        );
+    }
+
+    #[test]
+    fn test_rotate_commands_90() {
+        let cf = format();
+        let rotated = rotate_commands(&two_boxes(), ImageRotation::CCW_90);
+        assert_eq!(rotated.len(), two_boxes().len());
+
+        // (-2, 3) -> (-3, -2)
+        assert_eq!(
+            rotated[1],
+            operation(Operation::Move(Some(Coordinates::new(-3, -2, cf))))
+        );
+        // Y8 -> X-8
+        assert_eq!(
+            rotated[4],
+            operation(Operation::Interpolate(
+                Some(Coordinates::at_x(-8, cf)),
+                None
+            ))
+        );
+        // X4 -> Y4
+        assert_eq!(
+            rotated[7],
+            operation(Operation::Move(Some(Coordinates::at_y(4, cf))))
+        );
+    }
+
+    #[test]
+    fn test_rotate_commands_arc_offsets() {
+        let cf = format();
+        let arc = |x: i32, y: i32, i: i32, j: i32| {
+            operation(Operation::Interpolate(
+                Some(Coordinates::new(x, y, cf)),
+                Some(CoordinateOffset::new(i, j, cf)),
+            ))
+        };
+        let commands = vec![
+            GCode::QuadrantMode(QuadrantMode::Multi).into(),
+            arc(1, 2, 3, -4),
+            GCode::QuadrantMode(QuadrantMode::Single).into(),
+            arc(1, 2, 3, 4),
+        ];
+
+        let rotated = rotate_commands(&commands, ImageRotation::CCW_180);
+        assert_eq!(rotated[1], arc(-1, -2, -3, 4));
+        assert_eq!(rotated[3], arc(-1, -2, 3, 4));
+
+        let rotated = rotate_commands(&commands, ImageRotation::CCW_270);
+        assert_eq!(rotated[1], arc(2, -1, -4, -3));
+        assert_eq!(rotated[3], arc(2, -1, 4, 3));
+    }
+
+    #[test]
+    fn test_rotate_commands_apertures() {
+        let definition = |aperture: Aperture| -> Command {
+            ExtendedCode::ApertureDefinition(ApertureDefinition::new(10, aperture)).into()
+        };
+        let commands = vec![
+            definition(Aperture::Rectangle(Rectangular::new(1.0, 2.0))),
+            definition(Aperture::Obround(Rectangular::new(1.0, 2.0))),
+            definition(Aperture::Polygon(Polygon::new(1.0, 6))),
+            definition(Aperture::Polygon(Polygon::new(1.0, 6).with_rotation(300.0))),
+            definition(Aperture::Circle(Circle::new(1.0))),
+        ];
+
+        let rotated = rotate_commands(&commands, ImageRotation::CCW_90);
+        assert_eq!(
+            rotated[0],
+            definition(Aperture::Rectangle(Rectangular::new(2.0, 1.0)))
+        );
+        assert_eq!(
+            rotated[1],
+            definition(Aperture::Obround(Rectangular::new(2.0, 1.0)))
+        );
+        assert_eq!(
+            rotated[2],
+            definition(Aperture::Polygon(Polygon::new(1.0, 6).with_rotation(90.0)))
+        );
+        assert_eq!(
+            rotated[3],
+            definition(Aperture::Polygon(Polygon::new(1.0, 6).with_rotation(30.0)))
+        );
+        assert_eq!(rotated[4], commands[4]);
+
+        let rotated = rotate_commands(&commands, ImageRotation::CCW_180);
+        assert_eq!(rotated[0], commands[0]);
+        assert_eq!(
+            rotated[2],
+            definition(Aperture::Polygon(Polygon::new(1.0, 6).with_rotation(180.0)))
+        );
+    }
+
+    #[test]
+    fn test_split_by_polarity() {
+        let cf = format();
+        let commands: Vec<Command> = vec![
+            DCode::SelectAperture(10).into(),
+            operation(Operation::Flash(Some(Coordinates::new(0, 0, cf)))),
+            ExtendedCode::LoadPolarity(Polarity::Clear).into(),
+            operation(Operation::Flash(Some(Coordinates::new(1, 1, cf)))),
+            ExtendedCode::LoadPolarity(Polarity::Dark).into(),
+            DCode::SelectAperture(11).into(),
+            GCode::InterpolationMode(InterpolationMode::Linear).into(),
+            operation(Operation::Interpolate(Some(Coordinates::at_x(2, cf)), None)),
+        ];
+        let (dark, clear) = split_by_polarity(&commands);
+        assert_eq!(
+            dark,
+            vec![
+                DCode::SelectAperture(10).into(),
+                operation(Operation::Flash(Some(Coordinates::new(0, 0, cf)))),
+                DCode::SelectAperture(11).into(),
+                GCode::InterpolationMode(InterpolationMode::Linear).into(),
+                operation(Operation::Move(Some(Coordinates::new(1, 1, cf)))),
+                operation(Operation::Interpolate(Some(Coordinates::at_x(2, cf)), None)),
+            ]
+        );
+        assert_eq!(
+            clear,
+            vec![
+                DCode::SelectAperture(10).into(),
+                operation(Operation::Move(Some(Coordinates::new(0, 0, cf)))),
+                operation(Operation::Flash(Some(Coordinates::new(1, 1, cf)))),
+                DCode::SelectAperture(11).into(),
+                GCode::InterpolationMode(InterpolationMode::Linear).into(),
+            ]
+        );
+
+        let (dark, clear) = split_by_polarity(&two_boxes());
+        assert_eq!(dark, two_boxes());
+        assert_eq!(clear.len(), 2);
+    }
+
+    #[test]
+    fn test_translate_to_origin_without_coordinates() {
+        let mut commands: Vec<Command> = vec![DCode::SelectAperture(10).into()];
+        assert_eq!(translate_to_origin(&mut commands), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_convert_aperture_units() {
+        let mut commands: Vec<Command> = vec![
+            ApertureDefinition::new(10, Aperture::Circle(Circle::new(1.0))).into(),
+            DCode::SelectAperture(10).into(),
+            ExtendedCode::StepAndRepeat(StepAndRepeat::Open {
+                repeat_x: 2,
+                repeat_y: 1,
+                distance_x: 1.0,
+                distance_y: 0.0,
+            })
+            .into(),
+        ];
+        let expected = commands.clone();
+
+        convert_aperture_units(&mut commands, Unit::Millimeters, Unit::Millimeters).unwrap();
+        assert_eq!(commands, expected);
+
+        convert_aperture_units(&mut commands, Unit::Inches, Unit::Millimeters).unwrap();
+        assert_eq!(
+            commands[0],
+            ApertureDefinition::new(10, Aperture::Circle(Circle::new(25.4))).into()
+        );
+        assert_eq!(commands[1], expected[1]);
+        assert_eq!(
+            commands[2],
+            ExtendedCode::StepAndRepeat(StepAndRepeat::Open {
+                repeat_x: 2,
+                repeat_y: 1,
+                distance_x: 25.4,
+                distance_y: 0.0,
+            })
+            .into()
+        );
+
+        convert_aperture_units(&mut commands, Unit::Millimeters, Unit::Inches).unwrap();
+        match &commands[0] {
+            Command::ExtendedCode(ExtendedCode::ApertureDefinition(ApertureDefinition {
+                aperture: Aperture::Circle(circle),
+                ..
+            })) => assert!((circle.diameter - 1.0).abs() < 1e-12),
+            command => panic!("unexpected command {:?}", command),
+        }
+    }
+
+    #[test]
+    fn test_convert_aperture_units_macro() {
+        let mut commands: Vec<Command> = vec![
+            ApertureDefinition::new(10, Aperture::Circle(Circle::new(1.0))).into(),
+            ApertureDefinition::new(11, Aperture::Macro("DONUT".to_string(), None)).into(),
+        ];
+        let expected = commands.clone();
+
+        let error =
+            convert_aperture_units(&mut commands, Unit::Inches, Unit::Millimeters).unwrap_err();
+        assert!(matches!(&error, GerberError::Located(located) if located.index == 1));
+        assert_eq!(commands, expected);
+    }
+}
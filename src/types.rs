@@ -10,7 +10,7 @@ use crate::extended_codes;
 use crate::function_codes;
 use crate::macros;
 use chrono::{DateTime, FixedOffset};
-use std::convert::From;
+use core::convert::From;
 
 // Helper macros
 
@@ -27,6 +27,7 @@ macro_rules! impl_from {
 // Root type
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Command {
     FunctionCode(FunctionCode),
     ExtendedCode(ExtendedCode),
@@ -48,6 +49,7 @@ macro_rules! impl_command_fromfrom {
 // Main categories
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FunctionCode {
     DCode(function_codes::DCode),
     GCode(function_codes::GCode),
@@ -63,6 +65,7 @@ impl_command_fromfrom!(function_codes::GCode, FunctionCode::from);
 impl_command_fromfrom!(function_codes::MCode, FunctionCode::from);
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ExtendedCode {
     /// FS
     CoordinateFormat(coordinates::CoordinateFormat),
@@ -84,6 +87,13 @@ pub enum ExtendedCode {
     StepAndRepeat(extended_codes::StepAndRepeat),
     /// AB
     ApertureBlock(extended_codes::ApertureBlock),
+    /// AB, with the content of the block.
+    ///
+    /// Functions that change a stream of commands, e.g. the transformations and `merge_streams`,
+    /// expect a block as separate `%AB` commands. Use
+    /// [`ApertureBlockDefinition::into_commands`](extended_codes::ApertureBlockDefinition::into_commands)
+    /// to get those.
+    ApertureBlockDefinition(extended_codes::ApertureBlockDefinition),
     /// TF
     FileAttribute(attributes::FileAttribute),
     /// TO
@@ -154,6 +164,11 @@ impl_from!(
     ExtendedCode,
     ExtendedCode::ApertureBlock
 );
+impl_from!(
+    extended_codes::ApertureBlockDefinition,
+    ExtendedCode,
+    ExtendedCode::ApertureBlockDefinition
+);
 impl_from!(
     attributes::FileAttribute,
     ExtendedCode,
@@ -175,10 +190,14 @@ impl_command_fromfrom!(extended_codes::Mirroring, ExtendedCode::from);
 impl_command_fromfrom!(extended_codes::Rotation, ExtendedCode::from);
 impl_command_fromfrom!(extended_codes::StepAndRepeat, ExtendedCode::from);
 impl_command_fromfrom!(extended_codes::ApertureBlock, ExtendedCode::from);
+impl_command_fromfrom!(extended_codes::ApertureBlockDefinition, ExtendedCode::from);
 impl_command_fromfrom!(attributes::FileAttribute, ExtendedCode::from);
 impl_command_fromfrom!(attributes::ApertureAttribute, ExtendedCode::from);
 
-#[cfg(test)]
+// Date/Time
+pub type GerberDate = DateTime<FixedOffset>;
+
+#[cfg(all(test, feature = "std"))]
 mod test {
     use super::*;
 
@@ -278,6 +297,3 @@ mod test {
         assert_eq!(e1, e2);
     }
 }
-
-// Date/Time
-pub type GerberDate = DateTime<FixedOffset>;
@@ -0,0 +1,1786 @@
+//! Validation of commands.
+//!
+//! Serialization is permissive, it will happily generate Gerber code for values
+//! that are not valid according to the Gerber specification. The validation in
+//! this module can be used to catch such values before they are written.
+//!
+//! The `validate_*` functions check the structure of a whole command stream and
+//! return all the problems they find, they do not stop at the first one.
+
+use alloc::collections::BTreeSet;
+
+use crate::attributes::{
+    ApertureAttribute, ApertureFunction, AttributeDeletionCriterion, ComponentCharacteristics,
+    ExtendedPosition, FileAttribute, FileFunction, GenerationSoftware, Ident, Net, ObjectAttribute,
+    Part, Pin,
+};
+use crate::coordinates::{CoordinateFormat, CoordinateOffset, Coordinates};
+use crate::errors::{GerberError, GerberResult, LocatedError};
+use crate::extended_codes::{
+    Aperture, ApertureBlock, ApertureBlockDefinition, ApertureDefinition, Circle, ImageName,
+    ImageOffset, ImageScaling, Polygon, Rectangular, Rotation, Scaling, StepAndRepeat,
+};
+#[cfg(feature = "std")]
+use crate::function_codes::QuadrantMode;
+use crate::function_codes::{
+    CommentContent, DCode, GCode, InterpolationMode, MCode, Operation, RegionMode, StandardComment,
+};
+use crate::macros::{
+    ApertureMacro, CenterLinePrimitive, CirclePrimitive, MacroBoolean, MacroContent, MacroDecimal,
+    MacroExpression, MacroInteger, MoirePrimitive, OutlinePrimitive, PolygonPrimitive,
+    ThermalPrimitive, VariableDefinition, VectorLinePrimitive,
+};
+use crate::prelude::*;
+use crate::traits::StrictValidation;
+use crate::types::{Command, ExtendedCode, FunctionCode};
+
+/// Aperture codes 0 to 9 are reserved.
+pub const MIN_APERTURE_CODE: i32 = 10;
+
+/// Some older tools only support aperture codes up to 999, see [`validate_aperture_codes`].
+pub const LEGACY_MAX_APERTURE_CODE: i32 = 999;
+
+fn check_finite(what: &str, value: f64) -> GerberResult<()> {
+    if !value.is_finite() {
+        return Err(GerberError::RangeError(format!(
+            "{} must be finite, got {}",
+            what, value
+        )));
+    }
+    Ok(())
+}
+
+pub(crate) fn check_aperture_code(code: i32) -> GerberResult<()> {
+    if code < MIN_APERTURE_CODE {
+        return Err(GerberError::RangeError(format!(
+            "Aperture codes below {} are reserved, got {}",
+            MIN_APERTURE_CODE, code
+        )));
+    }
+    Ok(())
+}
+
+/// Checks that every aperture block (`%AB`) opened in the commands is closed again, and not
+/// closed more often than it is opened.
+pub(crate) fn check_balanced_blocks(commands: &[Command]) -> GerberResult<()> {
+    match unbalanced_blocks(commands).into_iter().next() {
+        Some(error) => Err(error.into()),
+        None => Ok(()),
+    }
+}
+
+/// Every aperture block that is closed without being opened, followed by every block that is
+/// not closed.
+fn unbalanced_blocks(commands: &[Command]) -> Vec<LocatedError> {
+    let mut errors = Vec::new();
+    let mut open: Vec<(usize, i32)> = Vec::new();
+    for (index, command) in commands.iter().enumerate() {
+        match command {
+            Command::ExtendedCode(ExtendedCode::ApertureBlock(ApertureBlock::Open { code })) => {
+                open.push((index, *code));
+            }
+            Command::ExtendedCode(ExtendedCode::ApertureBlock(ApertureBlock::Close)) => {
+                let opened = open.pop();
+                if opened.is_none() {
+                    errors.push(LocatedError::new(
+                        index,
+                        GerberError::ValidationError(
+                            "aperture block closed without being opened".into(),
+                        ),
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+    for (index, code) in open {
+        errors.push(LocatedError::new(
+            index,
+            GerberError::ValidationError(format!("aperture block D{} is not closed", code)),
+        ));
+    }
+    errors
+}
+
+/// `*` and `%` delimit Gerber words and commands, they cannot appear in data. Neither can line
+/// breaks, which end a command for many readers.
+fn check_data(what: &str, value: &str) -> GerberResult<()> {
+    if let Some(c) = value.chars().find(|c| matches!(c, '*' | '%' | '\n' | '\r')) {
+        return Err(GerberError::ValidationError(format!(
+            "{} contains the reserved character {:?}: {:?}",
+            what, c, value
+        )));
+    }
+    Ok(())
+}
+
+/// Fields are separated by `,` so, in addition to the characters rejected by `check_data`, they
+/// cannot contain `,`. Attribute fields escape these characters when written, see
+/// `attributes::write_field`, but are still checked here for readers that do not decode escapes.
+pub(crate) fn check_field(what: &str, value: &str) -> GerberResult<()> {
+    check_data(what, value)?;
+    if value.contains(',') {
+        return Err(GerberError::ValidationError(format!(
+            "{} contains the field separator ',': {:?}",
+            what, value
+        )));
+    }
+    Ok(())
+}
+
+fn check_fields(what: &str, values: &[String]) -> GerberResult<()> {
+    values.iter().try_for_each(|value| check_field(what, value))
+}
+
+impl<T: StrictValidation> StrictValidation for [T] {
+    fn validate_strict(&self) -> GerberResult<()> {
+        self.iter().try_for_each(StrictValidation::validate_strict)
+    }
+}
+
+impl<T: StrictValidation, const N: usize> StrictValidation for [T; N] {
+    fn validate_strict(&self) -> GerberResult<()> {
+        self.as_slice().validate_strict()
+    }
+}
+
+impl<T: StrictValidation> StrictValidation for Vec<T> {
+    fn validate_strict(&self) -> GerberResult<()> {
+        self.as_slice().validate_strict()
+    }
+}
+
+impl<T: StrictValidation> StrictValidation for Option<T> {
+    fn validate_strict(&self) -> GerberResult<()> {
+        match self {
+            Some(value) => value.validate_strict(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl StrictValidation for Command {
+    fn validate_strict(&self) -> GerberResult<()> {
+        match self {
+            Command::FunctionCode(code) => code.validate_strict(),
+            Command::ExtendedCode(code) => code.validate_strict(),
+        }
+    }
+}
+
+impl StrictValidation for FunctionCode {
+    fn validate_strict(&self) -> GerberResult<()> {
+        match self {
+            FunctionCode::DCode(code) => code.validate_strict(),
+            FunctionCode::GCode(code) => code.validate_strict(),
+            FunctionCode::MCode(code) => code.validate_strict(),
+        }
+    }
+}
+
+impl StrictValidation for DCode {
+    fn validate_strict(&self) -> GerberResult<()> {
+        match self {
+            DCode::Operation(operation) => operation.validate_strict(),
+            DCode::SelectAperture(code) => check_aperture_code(*code),
+        }
+    }
+}
+
+impl StrictValidation for GCode {
+    fn validate_strict(&self) -> GerberResult<()> {
+        match self {
+            GCode::Comment(content) => content.validate_strict(),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl StrictValidation for CommentContent {
+    fn validate_strict(&self) -> GerberResult<()> {
+        match self {
+            CommentContent::String(string) => check_data("Comment", string),
+            CommentContent::Standard(standard) => standard.validate_strict(),
+        }
+    }
+}
+
+impl StrictValidation for StandardComment {
+    fn validate_strict(&self) -> GerberResult<()> {
+        match self {
+            StandardComment::FileAttribute(fa) => fa.validate_strict(),
+            StandardComment::ObjectAttribute(oa) => oa.validate_strict(),
+            StandardComment::ApertureAttribute(aa) => aa.validate_strict(),
+            StandardComment::DeleteAttribute(adc) => adc.validate_strict(),
+        }
+    }
+}
+
+impl StrictValidation for MCode {
+    fn validate_strict(&self) -> GerberResult<()> {
+        Ok(())
+    }
+}
+
+impl StrictValidation for Operation {
+    fn validate_strict(&self) -> GerberResult<()> {
+        match self {
+            Operation::Interpolate(coords, offset) => {
+                coords.validate_strict()?;
+                offset.validate_strict()
+            }
+            Operation::Move(coords) => coords.validate_strict(),
+            Operation::Flash(coords) => coords.validate_strict(),
+        }
+    }
+}
+
+impl StrictValidation for Coordinates {
+    fn validate_strict(&self) -> GerberResult<()> {
+        self.format.validate_strict()?;
+        for number in [self.x, self.y].iter().flatten() {
+            number.validate(&self.format)?;
+        }
+        Ok(())
+    }
+}
+
+impl StrictValidation for CoordinateOffset {
+    fn validate_strict(&self) -> GerberResult<()> {
+        self.format.validate_strict()?;
+        for number in [self.x, self.y].iter().flatten() {
+            number.validate(&self.format)?;
+        }
+        Ok(())
+    }
+}
+
+impl StrictValidation for CoordinateFormat {
+    fn validate_strict(&self) -> GerberResult<()> {
+        if !(1..=6).contains(&self.integer) {
+            return Err(GerberError::CoordinateFormatError(format!(
+                "The number of integer places must be 1 to 6, got {}",
+                self.integer
+            )));
+        }
+        if !(4..=6).contains(&self.decimal) {
+            return Err(GerberError::CoordinateFormatError(format!(
+                "The number of decimal places must be 4 to 6, got {}",
+                self.decimal
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl StrictValidation for ExtendedCode {
+    fn validate_strict(&self) -> GerberResult<()> {
+        match self {
+            ExtendedCode::CoordinateFormat(cf) => cf.validate_strict(),
+            ExtendedCode::Unit(_) => Ok(()),
+            ExtendedCode::ApertureDefinition(def) => def.validate_strict(),
+            ExtendedCode::ApertureMacro(am) => am.validate_strict(),
+            ExtendedCode::LoadPolarity(_) => Ok(()),
+            ExtendedCode::LoadMirroring(_) => Ok(()),
+            ExtendedCode::LoadRotation(rotation) => rotation.validate_strict(),
+            ExtendedCode::LoadScaling(scaling) => scaling.validate_strict(),
+            ExtendedCode::StepAndRepeat(sar) => sar.validate_strict(),
+            ExtendedCode::ApertureBlock(ab) => ab.validate_strict(),
+            ExtendedCode::ApertureBlockDefinition(block) => block.validate_strict(),
+            ExtendedCode::FileAttribute(fa) => fa.validate_strict(),
+            ExtendedCode::ObjectAttribute(oa) => oa.validate_strict(),
+            ExtendedCode::ApertureAttribute(aa) => aa.validate_strict(),
+            ExtendedCode::DeleteAttribute(adc) => adc.validate_strict(),
+            ExtendedCode::MirrorImage(_) => Ok(()),
+            ExtendedCode::OffsetImage(of) => of.validate_strict(),
+            ExtendedCode::ScaleImage(sf) => sf.validate_strict(),
+            ExtendedCode::RotateImage(_) => Ok(()),
+            ExtendedCode::ImagePolarity(_) => Ok(()),
+            ExtendedCode::AxisSelect(_) => Ok(()),
+            ExtendedCode::ImageName(name) => name.validate_strict(),
+        }
+    }
+}
+
+impl StrictValidation for ApertureDefinition {
+    fn validate_strict(&self) -> GerberResult<()> {
+        check_aperture_code(self.code)?;
+        self.aperture.validate_strict()
+    }
+}
+
+impl StrictValidation for Aperture {
+    fn validate_strict(&self) -> GerberResult<()> {
+        match self {
+            Aperture::Circle(circle) => circle.validate_strict(),
+            Aperture::Rectangle(rectangular) => rectangular.validate_strict(),
+            Aperture::Obround(rectangular) => rectangular.validate_strict(),
+            Aperture::Polygon(polygon) => polygon.validate_strict(),
+            Aperture::Macro(name, args) => {
+                check_field("Macro name", name)?;
+                args.validate_strict()
+            }
+        }
+    }
+}
+
+impl StrictValidation for Circle {
+    fn validate_strict(&self) -> GerberResult<()> {
+        check_finite("Circle diameter", self.diameter)?;
+        if let Some(hole_diameter) = self.hole_diameter {
+            check_finite("Circle hole diameter", hole_diameter)?;
+        }
+        Ok(())
+    }
+}
+
+impl StrictValidation for Rectangular {
+    fn validate_strict(&self) -> GerberResult<()> {
+        self.validate()
+    }
+}
+
+impl StrictValidation for Polygon {
+    fn validate_strict(&self) -> GerberResult<()> {
+        self.validate()?;
+        if let Some(rotation) = self.rotation {
+            check_finite("Polygon rotation", rotation)?;
+        }
+        if let Some(hole_diameter) = self.hole_diameter {
+            check_finite("Polygon hole diameter", hole_diameter)?;
+        }
+        Ok(())
+    }
+}
+
+impl StrictValidation for ApertureMacro {
+    fn validate_strict(&self) -> GerberResult<()> {
+        check_field("Macro name", &self.name)?;
+        self.content.validate_strict()
+    }
+}
+
+impl StrictValidation for MacroDecimal {
+    #[allow(deprecated)]
+    fn validate_strict(&self) -> GerberResult<()> {
+        match self {
+            MacroDecimal::Value(value) => check_finite("Macro decimal", *value),
+            MacroDecimal::Variable(_) => Ok(()),
+            MacroDecimal::Expression(expression) => check_field("Macro expression", expression),
+            MacroDecimal::Expr(expression) => expression.validate_strict(),
+        }
+    }
+}
+
+impl StrictValidation for MacroExpression {
+    fn validate_strict(&self) -> GerberResult<()> {
+        match self {
+            MacroExpression::Value(value) => check_finite("Macro expression value", *value),
+            MacroExpression::Variable(_) => Ok(()),
+            MacroExpression::Add(left, right)
+            | MacroExpression::Sub(left, right)
+            | MacroExpression::Mul(left, right)
+            | MacroExpression::Div(left, right) => {
+                left.validate_strict()?;
+                right.validate_strict()
+            }
+            MacroExpression::Neg(operand) => operand.validate_strict(),
+        }
+    }
+}
+
+impl StrictValidation for MacroBoolean {
+    fn validate_strict(&self) -> GerberResult<()> {
+        match self {
+            MacroBoolean::Expression(expression) => check_field("Macro expression", expression),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl StrictValidation for MacroInteger {
+    fn validate_strict(&self) -> GerberResult<()> {
+        match self {
+            MacroInteger::Expression(expression) => check_field("Macro expression", expression),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<T: StrictValidation> StrictValidation for (T, T) {
+    fn validate_strict(&self) -> GerberResult<()> {
+        self.0.validate_strict()?;
+        self.1.validate_strict()
+    }
+}
+
+impl StrictValidation for MacroContent {
+    fn validate_strict(&self) -> GerberResult<()> {
+        match self {
+            MacroContent::Circle(c) => c.validate_strict(),
+            MacroContent::VectorLine(vl) => vl.validate_strict(),
+            MacroContent::CenterLine(cl) => cl.validate_strict(),
+            MacroContent::Outline(o) => o.validate_strict(),
+            MacroContent::Polygon(p) => p.validate_strict(),
+            MacroContent::Moire(m) => m.validate_strict(),
+            MacroContent::Thermal(t) => t.validate_strict(),
+            MacroContent::VariableDefinition(v) => v.validate_strict(),
+            MacroContent::Comment(s) => check_data("Macro comment", s),
+        }
+    }
+}
+
+impl StrictValidation for CirclePrimitive {
+    fn validate_strict(&self) -> GerberResult<()> {
+        self.validate()?;
+        self.exposure.validate_strict()?;
+        self.diameter.validate_strict()?;
+        self.center.validate_strict()?;
+        self.angle.validate_strict()
+    }
+}
+
+impl StrictValidation for VectorLinePrimitive {
+    fn validate_strict(&self) -> GerberResult<()> {
+        self.validate()?;
+        self.exposure.validate_strict()?;
+        self.width.validate_strict()?;
+        self.start.validate_strict()?;
+        self.end.validate_strict()?;
+        self.angle.validate_strict()
+    }
+}
+
+impl StrictValidation for CenterLinePrimitive {
+    fn validate_strict(&self) -> GerberResult<()> {
+        self.validate()?;
+        self.exposure.validate_strict()?;
+        self.dimensions.validate_strict()?;
+        self.center.validate_strict()?;
+        self.angle.validate_strict()
+    }
+}
+
+impl StrictValidation for OutlinePrimitive {
+    fn validate_strict(&self) -> GerberResult<()> {
+        self.validate()?;
+        self.exposure.validate_strict()?;
+        self.points.validate_strict()?;
+        self.angle.validate_strict()
+    }
+}
+
+impl StrictValidation for PolygonPrimitive {
+    fn validate_strict(&self) -> GerberResult<()> {
+        self.validate()?;
+        self.exposure.validate_strict()?;
+        self.vertices.validate_strict()?;
+        self.center.validate_strict()?;
+        self.diameter.validate_strict()?;
+        self.angle.validate_strict()
+    }
+}
+
+impl StrictValidation for MoirePrimitive {
+    fn validate_strict(&self) -> GerberResult<()> {
+        self.validate()?;
+        self.center.validate_strict()?;
+        self.diameter.validate_strict()?;
+        self.ring_thickness.validate_strict()?;
+        self.gap.validate_strict()?;
+        self.cross_hair_thickness.validate_strict()?;
+        self.cross_hair_length.validate_strict()?;
+        self.angle.validate_strict()
+    }
+}
+
+impl StrictValidation for ThermalPrimitive {
+    fn validate_strict(&self) -> GerberResult<()> {
+        self.validate()?;
+        self.center.validate_strict()?;
+        self.outer_diameter.validate_strict()?;
+        self.inner_diameter.validate_strict()?;
+        self.gap.validate_strict()?;
+        self.angle.validate_strict()
+    }
+}
+
+impl StrictValidation for VariableDefinition {
+    fn validate_strict(&self) -> GerberResult<()> {
+        check_field("Macro variable definition", &self.expression)
+    }
+}
+
+impl StrictValidation for Rotation {
+    fn validate_strict(&self) -> GerberResult<()> {
+        check_finite("Rotation", self.rotation)
+    }
+}
+
+impl StrictValidation for Scaling {
+    fn validate_strict(&self) -> GerberResult<()> {
+        check_finite("Scaling", self.scale)
+    }
+}
+
+impl StrictValidation for StepAndRepeat {
+    fn validate_strict(&self) -> GerberResult<()> {
+        match self {
+            StepAndRepeat::Open {
+                distance_x,
+                distance_y,
+                ..
+            } => {
+                check_finite("Step and repeat X distance", *distance_x)?;
+                check_finite("Step and repeat Y distance", *distance_y)
+            }
+            StepAndRepeat::Close => Ok(()),
+        }
+    }
+}
+
+impl StrictValidation for ApertureBlock {
+    fn validate_strict(&self) -> GerberResult<()> {
+        match self {
+            ApertureBlock::Open { code } => check_aperture_code(*code),
+            ApertureBlock::Close => Ok(()),
+        }
+    }
+}
+
+impl StrictValidation for ApertureBlockDefinition {
+    fn validate_strict(&self) -> GerberResult<()> {
+        check_aperture_code(self.code)?;
+        if let Some(index) = self.commands.iter().position(|command| {
+            matches!(
+                command,
+                Command::FunctionCode(FunctionCode::MCode(MCode::EndOfFile))
+            )
+        }) {
+            return Err(LocatedError::new(
+                index,
+                GerberError::ValidationError(
+                    "an aperture block cannot contain an end of file (M02) command".into(),
+                ),
+            )
+            .into());
+        }
+        check_balanced_blocks(&self.commands)?;
+        self.commands.validate_strict()
+    }
+}
+
+impl StrictValidation for ImageOffset {
+    fn validate_strict(&self) -> GerberResult<()> {
+        check_finite("Image offset", self.a)?;
+        check_finite("Image offset", self.b)
+    }
+}
+
+impl StrictValidation for ImageScaling {
+    fn validate_strict(&self) -> GerberResult<()> {
+        check_finite("Image scaling", self.a)?;
+        check_finite("Image scaling", self.b)
+    }
+}
+
+impl StrictValidation for ImageName {
+    fn validate_strict(&self) -> GerberResult<()> {
+        check_data("Image name", &self.name)
+    }
+}
+
+impl StrictValidation for Ident {
+    fn validate_strict(&self) -> GerberResult<()> {
+        match self {
+            Ident::Uuid(_) => Ok(()),
+            Ident::Name(name) => check_field("Ident", name),
+        }
+    }
+}
+
+impl StrictValidation for GenerationSoftware {
+    fn validate_strict(&self) -> GerberResult<()> {
+        check_field("Generation software vendor", &self.vendor)?;
+        check_field("Generation software application", &self.application)?;
+        if let Some(version) = &self.version {
+            check_field("Generation software version", version)?;
+        }
+        Ok(())
+    }
+}
+
+impl StrictValidation for FileAttribute {
+    fn validate_strict(&self) -> GerberResult<()> {
+        match self {
+            FileAttribute::Part(Part::Other(description)) => check_field("Part", description),
+            FileAttribute::Part(_) => Ok(()),
+            FileAttribute::FileFunction(function) => function.validate_strict(),
+            FileAttribute::FilePolarity(_) => Ok(()),
+            FileAttribute::SameCoordinates(ident) => ident.validate_strict(),
+            FileAttribute::CreationDate(_) => Ok(()),
+            FileAttribute::GenerationSoftware(gs) => gs.validate_strict(),
+            FileAttribute::ProjectId { id, revision, .. } => {
+                check_field("Project id", id)?;
+                check_field("Project revision", revision)
+            }
+            FileAttribute::Md5(hash) => check_field("MD5", hash),
+            FileAttribute::UserDefined { name, values } => {
+                check_field("Attribute name", name)?;
+                check_fields("Attribute value", values)
+            }
+        }
+    }
+}
+
+/// Layers are numbered from 1, the top layer. An inner layer can not be the top layer.
+fn check_layer(layer: i32, pos: ExtendedPosition) -> GerberResult<()> {
+    let min = match pos {
+        ExtendedPosition::Inner => 2,
+        _ => 1,
+    };
+    if layer < min {
+        return Err(GerberError::RangeError(format!(
+            "Layer L{} is not valid for position {:?}, the minimum is L{}",
+            layer, pos, min
+        )));
+    }
+    Ok(())
+}
+
+impl StrictValidation for FileFunction {
+    fn validate_strict(&self) -> GerberResult<()> {
+        match self {
+            FileFunction::Other(value) | FileFunction::OtherDrawing(value) => {
+                check_field("File function", value)?;
+                FileFunction::check_other_value(value)
+            }
+            FileFunction::Copper { layer, pos, .. } | FileFunction::Component { layer, pos } => {
+                check_layer(*layer, *pos)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl StrictValidation for ApertureAttribute {
+    fn validate_strict(&self) -> GerberResult<()> {
+        match self {
+            ApertureAttribute::ApertureFunction(function) => function.validate_strict(),
+            ApertureAttribute::DrillTolerance { plus, minus } => {
+                check_finite("Drill tolerance", *plus)?;
+                check_finite("Drill tolerance", *minus)
+            }
+            ApertureAttribute::FlashText {
+                text,
+                font,
+                comment,
+                ..
+            } => {
+                check_field("Flash text", text)?;
+                if let Some(font) = font {
+                    check_field("Flash text font", font)?;
+                }
+                if let Some(comment) = comment {
+                    check_field("Flash text comment", comment)?;
+                }
+                Ok(())
+            }
+            ApertureAttribute::UserDefined { name, values } => {
+                check_field("Attribute name", name)?;
+                check_fields("Attribute value", values)
+            }
+        }
+    }
+}
+
+impl StrictValidation for ApertureFunction {
+    fn validate_strict(&self) -> GerberResult<()> {
+        match self {
+            ApertureFunction::OtherDrill(value)
+            | ApertureFunction::OtherPad(value)
+            | ApertureFunction::OtherCopper(value)
+            | ApertureFunction::Other(value) => check_field("Aperture function", value),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl StrictValidation for ObjectAttribute {
+    fn validate_strict(&self) -> GerberResult<()> {
+        match self {
+            ObjectAttribute::Net(net) => net.validate_strict(),
+            ObjectAttribute::Pin(pin) => pin.validate_strict(),
+            ObjectAttribute::Component(refdes) => {
+                ObjectAttribute::check_refdes(refdes)?;
+                check_field("Component reference designator", refdes)
+            }
+            ObjectAttribute::ComponentCharacteristics(cc) => cc.validate_strict(),
+            ObjectAttribute::UserDefined { name, values } => {
+                check_field("Attribute name", name)?;
+                check_fields("Attribute value", values)
+            }
+        }
+    }
+}
+
+impl StrictValidation for Net {
+    fn validate_strict(&self) -> GerberResult<()> {
+        match self {
+            Net::Connected(names) => {
+                if names.iter().any(String::is_empty) {
+                    return Err(GerberError::ValidationError(
+                        "Net names must not be empty".into(),
+                    ));
+                }
+                check_fields("Net name", names)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl StrictValidation for Pin {
+    fn validate_strict(&self) -> GerberResult<()> {
+        check_field("Pin reference", &self.refdes)?;
+        check_field("Pin name", &self.name)?;
+        if let Some(function) = &self.function {
+            check_field("Pin function", function)?;
+        }
+        Ok(())
+    }
+}
+
+impl StrictValidation for ComponentCharacteristics {
+    fn validate_strict(&self) -> GerberResult<()> {
+        match self {
+            ComponentCharacteristics::Rotation(value) => check_finite("Component rotation", *value),
+            ComponentCharacteristics::Height(value) => check_finite("Component height", *value),
+            ComponentCharacteristics::Manufacturer(value)
+            | ComponentCharacteristics::MPN(value)
+            | ComponentCharacteristics::Value(value)
+            | ComponentCharacteristics::Footprint(value)
+            | ComponentCharacteristics::PackageName(value)
+            | ComponentCharacteristics::PackageDescription(value)
+            | ComponentCharacteristics::LibraryName(value)
+            | ComponentCharacteristics::LibraryDescription(value) => {
+                check_field("Component characteristic", value)
+            }
+            ComponentCharacteristics::Mount(_) => Ok(()),
+            ComponentCharacteristics::Supplier(parts) => {
+                for part in parts {
+                    check_field("Supplier name", &part.supplier_name)?;
+                    check_field("Supplier part reference", &part.supplier_part_reference)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl StrictValidation for AttributeDeletionCriterion {
+    fn validate_strict(&self) -> GerberResult<()> {
+        match self {
+            AttributeDeletionCriterion::AllApertureAndObjectAttributes => Ok(()),
+            AttributeDeletionCriterion::SingleObjectAttribute(name)
+            | AttributeDeletionCriterion::SingleApertureAttribute(name) => {
+                check_field("Attribute name", name)
+            }
+        }
+    }
+}
+
+// Arcs
+
+/// Checks that a circular interpolation from `start` to `end`, with the center `offset` from
+/// `start`, describes a valid arc.
+///
+/// The distances of `start` and `end` from the center must match within two units in the last
+/// decimal place of the coordinate format. In single quadrant mode (G74) the offset must be
+/// unsigned and one of the four possible centers must give an arc of at most 90° in the
+/// direction of `mode`. In multi quadrant mode (G75) the offset is signed and an arc with
+/// `start` equal to `end` is a full circle.
+///
+/// Missing I or J offsets are 0, `start` and `end` must have both X and Y.
+#[cfg(feature = "std")]
+pub fn validate_arc(
+    start: &Coordinates,
+    end: &Coordinates,
+    offset: &CoordinateOffset,
+    quadrant: QuadrantMode,
+    mode: InterpolationMode,
+) -> GerberResult<()> {
+    let clockwise = match mode {
+        InterpolationMode::ClockwiseCircular => true,
+        InterpolationMode::CounterclockwiseCircular => false,
+        InterpolationMode::Linear => {
+            return Err(GerberError::ValidationError(
+                "An arc needs a circular interpolation mode (G02/G03)".into(),
+            ));
+        }
+    };
+    let point = |what: &str, coordinates: &Coordinates| match (coordinates.x, coordinates.y) {
+        (Some(x), Some(y)) => Ok((f64::from(x), f64::from(y))),
+        _ => Err(GerberError::MissingDataError(format!(
+            "The {} point of an arc needs both X and Y",
+            what
+        ))),
+    };
+    let start_point = point("start", start)?;
+    let end_point = point("end", end)?;
+    let i = offset.x.map(f64::from).unwrap_or(0.0);
+    let j = offset.y.map(f64::from).unwrap_or(0.0);
+    let tolerance = 2.0 * 10_f64.powi(-i32::from(start.format.decimal));
+
+    let radius_error = |center: (f64, f64)| {
+        let start_radius = (start_point.0 - center.0).hypot(start_point.1 - center.1);
+        let end_radius = (end_point.0 - center.0).hypot(end_point.1 - center.1);
+        (start_radius - end_radius).abs()
+    };
+    match quadrant {
+        QuadrantMode::Multi => {
+            let center = (start_point.0 + i, start_point.1 + j);
+            let error = radius_error(center);
+            if error > tolerance {
+                return Err(GerberError::ValidationError(format!(
+                    "The start and end radius of the arc differ by {}",
+                    error
+                )));
+            }
+            Ok(())
+        }
+        QuadrantMode::Single => {
+            if i < 0.0 || j < 0.0 {
+                return Err(GerberError::ValidationError(
+                    "The offset of an arc in single quadrant mode (G74) must be unsigned".into(),
+                ));
+            }
+            let valid = [(1.0, 1.0), (1.0, -1.0), (-1.0, 1.0), (-1.0, -1.0)]
+                .iter()
+                .map(|(sign_i, sign_j)| (start_point.0 + sign_i * i, start_point.1 + sign_j * j))
+                .any(|center| {
+                    radius_error(center) <= tolerance
+                        && sweep(start_point, end_point, center, clockwise)
+                            <= core::f64::consts::FRAC_PI_2 + 1e-9
+                });
+            if !valid {
+                return Err(GerberError::ValidationError(
+                    "No center gives an arc of at most 90° in single quadrant mode (G74)".into(),
+                ));
+            }
+            Ok(())
+        }
+    }
+}
+
+/// The angle swept from `start` to `end` around `center` in the given direction, from 0 up
+/// to, but excluding, 2π.
+#[cfg(feature = "std")]
+fn sweep(start: (f64, f64), end: (f64, f64), center: (f64, f64), clockwise: bool) -> f64 {
+    let start_angle = (start.1 - center.1).atan2(start.0 - center.0);
+    let end_angle = (end.1 - center.1).atan2(end.0 - center.0);
+    let angle = if clockwise {
+        start_angle - end_angle
+    } else {
+        end_angle - start_angle
+    };
+    angle.rem_euclid(2.0 * core::f64::consts::PI)
+}
+
+// Stream validation
+//
+// The stream validators report the index of the offending command with a `LocatedError`.
+
+/// Checks that a quadrant mode (G74/G75) is set before the first circular interpolation.
+///
+/// Without it the arc is interpreted according to the reader's default, which differs between
+/// readers and spec versions. Every circular interpolation before the first quadrant mode
+/// command is reported.
+pub fn validate_quadrant_mode(commands: &[Command]) -> Vec<LocatedError> {
+    let mut errors = Vec::new();
+    let mut quadrant_mode_set = false;
+    let mut interpolation_mode: Option<InterpolationMode> = None;
+    for (index, command) in commands.iter().enumerate() {
+        match command {
+            Command::FunctionCode(FunctionCode::GCode(GCode::QuadrantMode(_))) => {
+                quadrant_mode_set = true;
+            }
+            Command::FunctionCode(FunctionCode::GCode(GCode::InterpolationMode(mode))) => {
+                interpolation_mode = Some(*mode);
+            }
+            Command::FunctionCode(FunctionCode::DCode(DCode::Operation(
+                Operation::Interpolate(..),
+            ))) if !quadrant_mode_set
+                && matches!(
+                    interpolation_mode,
+                    Some(InterpolationMode::ClockwiseCircular)
+                        | Some(InterpolationMode::CounterclockwiseCircular)
+                ) =>
+            {
+                errors.push(LocatedError::new(
+                    index,
+                    GerberError::ValidationError(
+                        "circular interpolation before any quadrant mode (G74/G75)".into(),
+                    ),
+                ));
+            }
+            _ => {}
+        }
+    }
+    errors
+}
+
+/// Checks that the stream contains exactly one end of file command (M02) and that it is the
+/// last command.
+///
+/// A missing end of file is reported at index `commands.len()`, where it is expected.
+pub fn validate_eof(commands: &[Command]) -> Result<(), LocatedError> {
+    let is_eof = |command: &Command| {
+        matches!(
+            command,
+            Command::FunctionCode(FunctionCode::MCode(MCode::EndOfFile))
+        )
+    };
+    if let Some(index) = commands.iter().position(is_eof) {
+        if index != commands.len() - 1 {
+            return Err(LocatedError::new(
+                index,
+                GerberError::ValidationError("end of file (M02) must be the last command".into()),
+            ));
+        }
+        Ok(())
+    } else {
+        Err(LocatedError::new(
+            commands.len(),
+            GerberError::MissingDataError("The end of file command (M02) is missing".into()),
+        ))
+    }
+}
+
+/// Checks that the stream has at most one coordinate format (FS) and one unit (MO) command and
+/// that they come before the first operation (D01, D02 or D03).
+///
+/// A missing FS or MO is not reported.
+pub fn validate_single_header(commands: &[Command]) -> Result<(), LocatedError> {
+    let mut first_operation = None;
+    let mut format_seen = false;
+    let mut unit_seen = false;
+    for (index, command) in commands.iter().enumerate() {
+        let (seen, name) = match command {
+            Command::FunctionCode(FunctionCode::DCode(DCode::Operation(_))) => {
+                first_operation.get_or_insert(index);
+                continue;
+            }
+            Command::ExtendedCode(ExtendedCode::CoordinateFormat(_)) => {
+                (&mut format_seen, "coordinate format (FS)")
+            }
+            Command::ExtendedCode(ExtendedCode::Unit(_)) => (&mut unit_seen, "unit (MO)"),
+            _ => continue,
+        };
+        if *seen {
+            return Err(LocatedError::new(
+                index,
+                GerberError::ValidationError(format!("the {} must only be set once", name)),
+            ));
+        }
+        *seen = true;
+        if let Some(operation) = first_operation {
+            return Err(LocatedError::new(
+                index,
+                GerberError::ValidationError(format!(
+                    "the {} must be set before the first operation (command {})",
+                    name, operation
+                )),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Checks that no aperture code exceeds `max_code`, if given.
+///
+/// The specification does not limit aperture codes, but some implementations do, e.g. older
+/// tools only support codes up to [`LEGACY_MAX_APERTURE_CODE`]. Aperture definitions,
+/// selections and block apertures are checked.
+pub fn validate_aperture_codes(commands: &[Command], max_code: Option<i32>) -> Vec<LocatedError> {
+    let max_code = match max_code {
+        Some(max_code) => max_code,
+        None => return Vec::new(),
+    };
+    commands
+        .iter()
+        .enumerate()
+        .filter_map(|(index, command)| {
+            let code = match command {
+                Command::ExtendedCode(ExtendedCode::ApertureDefinition(def)) => def.code,
+                Command::ExtendedCode(ExtendedCode::ApertureBlock(ApertureBlock::Open {
+                    code,
+                })) => *code,
+                Command::ExtendedCode(ExtendedCode::ApertureBlockDefinition(block)) => block.code,
+                Command::FunctionCode(FunctionCode::DCode(DCode::SelectAperture(code))) => *code,
+                _ => return None,
+            };
+            if code > max_code {
+                Some(LocatedError::new(
+                    index,
+                    GerberError::RangeError(format!(
+                        "aperture code {} exceeds the maximum of {}",
+                        code, max_code
+                    )),
+                ))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Checks that every selected aperture (`Dnn`) is defined before it is selected, either with an
+/// aperture definition (`%AD`) or as an aperture block (`%AB`).
+///
+/// Every selection of an aperture that is not defined yet is reported.
+pub fn validate_defined_apertures(commands: &[Command]) -> Vec<LocatedError> {
+    let mut defined = BTreeSet::new();
+    let mut errors = Vec::new();
+    for (index, command) in commands.iter().enumerate() {
+        match command {
+            Command::ExtendedCode(ExtendedCode::ApertureDefinition(def)) => {
+                defined.insert(def.code);
+            }
+            Command::ExtendedCode(ExtendedCode::ApertureBlock(ApertureBlock::Open { code })) => {
+                defined.insert(*code);
+            }
+            Command::ExtendedCode(ExtendedCode::ApertureBlockDefinition(block)) => {
+                defined.insert(block.code);
+            }
+            Command::FunctionCode(FunctionCode::DCode(DCode::SelectAperture(code)))
+                if !defined.contains(code) =>
+            {
+                errors.push(LocatedError::new(
+                    index,
+                    GerberError::ValidationError(format!(
+                        "aperture D{} is selected before it is defined",
+                        code
+                    )),
+                ));
+            }
+            _ => {}
+        }
+    }
+    errors
+}
+
+/// Checks that every macro aperture definition (`%ADD10NAME,...*%`) uses a macro that is defined
+/// (`%AMNAME*...%`) before it.
+///
+/// Every aperture definition that uses a macro that is not defined yet is reported.
+pub fn validate_macro_references(commands: &[Command]) -> Vec<LocatedError> {
+    let mut defined = BTreeSet::new();
+    let mut errors = Vec::new();
+    for (index, command) in commands.iter().enumerate() {
+        match command {
+            Command::ExtendedCode(ExtendedCode::ApertureMacro(aperture_macro)) => {
+                defined.insert(aperture_macro.name.as_str());
+            }
+            Command::ExtendedCode(ExtendedCode::ApertureDefinition(ApertureDefinition {
+                code,
+                aperture: Aperture::Macro(name, _),
+            })) if !defined.contains(name.as_str()) => {
+                errors.push(LocatedError::new(
+                    index,
+                    GerberError::ValidationError(format!(
+                        "aperture D{} uses the macro '{}' before it is defined",
+                        code, name
+                    )),
+                ));
+            }
+            _ => {}
+        }
+    }
+    errors
+}
+
+/// Checks that the image name (`%IN`) is set at most once.
+///
+/// The command is deprecated, but still emitted by some tools. A file has a single image name,
+/// so every image name after the first one is reported.
+pub fn validate_single_image_name(commands: &[Command]) -> Vec<LocatedError> {
+    let mut first: Option<usize> = None;
+    let mut errors = Vec::new();
+    for (index, command) in commands.iter().enumerate() {
+        if let Command::ExtendedCode(ExtendedCode::ImageName(_)) = command {
+            match first {
+                None => first = Some(index),
+                Some(first) => errors.push(LocatedError::new(
+                    index,
+                    GerberError::ValidationError(format!(
+                        "image name (IN) already set by command {}",
+                        first
+                    )),
+                )),
+            }
+        }
+    }
+    errors
+}
+
+/// Checks that region mode, aperture blocks and step and repeat blocks are all closed at the end
+/// of file (M02), or at the end of the stream if there is no end of file command.
+///
+/// A region that is left open is never filled, an unclosed block is never instantiated. Every
+/// open region or block is reported, and so is every block that is closed without being opened.
+pub fn validate_closed_at_eof(commands: &[Command]) -> Vec<LocatedError> {
+    let end = commands
+        .iter()
+        .position(|command| {
+            matches!(
+                command,
+                Command::FunctionCode(FunctionCode::MCode(MCode::EndOfFile))
+            )
+        })
+        .unwrap_or(commands.len());
+    let commands = &commands[..end];
+
+    let mut errors = Vec::new();
+    let mut region: Option<usize> = None;
+    let mut step_and_repeat: Option<usize> = None;
+    for (index, command) in commands.iter().enumerate() {
+        match command {
+            Command::FunctionCode(FunctionCode::GCode(GCode::RegionMode(RegionMode::On))) => {
+                region.get_or_insert(index);
+            }
+            Command::FunctionCode(FunctionCode::GCode(GCode::RegionMode(RegionMode::Off))) => {
+                region = None;
+            }
+            Command::ExtendedCode(ExtendedCode::StepAndRepeat(StepAndRepeat::Open { .. })) => {
+                step_and_repeat.get_or_insert(index);
+            }
+            Command::ExtendedCode(ExtendedCode::StepAndRepeat(StepAndRepeat::Close)) => {
+                let opened = step_and_repeat.take();
+                if opened.is_none() {
+                    errors.push(LocatedError::new(
+                        index,
+                        GerberError::ValidationError(
+                            "step and repeat closed without being opened".into(),
+                        ),
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+    if let Some(index) = region {
+        errors.push(LocatedError::new(
+            index,
+            GerberError::ValidationError(
+                "region mode (G36) is not closed (G37) at the end of file".into(),
+            ),
+        ));
+    }
+    if let Some(index) = step_and_repeat {
+        errors.push(LocatedError::new(
+            index,
+            GerberError::ValidationError("step and repeat is not closed at the end of file".into()),
+        ));
+    }
+    errors.extend(unbalanced_blocks(commands));
+    errors
+}
+
+/// The file function of a `.FileFunction` attribute, also in a standard comment.
+pub(crate) fn file_function(command: &Command) -> Option<&FileFunction> {
+    match command {
+        Command::ExtendedCode(ExtendedCode::FileAttribute(FileAttribute::FileFunction(
+            function,
+        ))) => Some(function),
+        Command::FunctionCode(FunctionCode::GCode(GCode::Comment(CommentContent::Standard(
+            StandardComment::FileAttribute(FileAttribute::FileFunction(function)),
+        )))) => Some(function),
+        _ => None,
+    }
+}
+
+/// Checks that the file function (`.FileFunction`) is set at most once.
+///
+/// A file has a single function, every file function attribute after the first one is
+/// reported, including attributes in standard comments.
+pub fn validate_single_file_function(commands: &[Command]) -> Vec<LocatedError> {
+    let mut first: Option<usize> = None;
+    let mut errors = Vec::new();
+    for (index, command) in commands.iter().enumerate() {
+        if file_function(command).is_some() {
+            match first {
+                None => first = Some(index),
+                Some(first) => errors.push(LocatedError::new(
+                    index,
+                    GerberError::ValidationError(format!(
+                        "file function already set by command {}",
+                        first
+                    )),
+                )),
+            }
+        }
+    }
+    errors
+}
+
+fn describe_format(format: &CoordinateFormat) -> String {
+    format!(
+        "{},{} ({:?}, {:?})",
+        format.integer, format.decimal, format.zero_omission, format.coordinate_mode
+    )
+}
+
+/// Checks that the coordinates and offsets of all operations use the format declared with `%FS`.
+///
+/// Every [`Coordinates`] and [`CoordinateOffset`] embeds its own format, but the file declares
+/// a single one, so a coordinate in any other format is written incorrectly. Without a `%FS`
+/// command, the coordinates are checked against the format of the first coordinate.
+pub fn validate_uniform_format(commands: &[Command]) -> Result<(), LocatedError> {
+    let declared = commands.iter().find_map(|command| match command {
+        Command::ExtendedCode(ExtendedCode::CoordinateFormat(format)) => Some(*format),
+        _ => None,
+    });
+    let mut expected = declared;
+    for (index, command) in commands.iter().enumerate() {
+        let formats = match command {
+            Command::FunctionCode(FunctionCode::DCode(DCode::Operation(operation))) => {
+                match operation {
+                    Operation::Interpolate(coordinates, offset) => [
+                        coordinates.as_ref().map(|c| c.format),
+                        offset.as_ref().map(|o| o.format),
+                    ],
+                    Operation::Move(coordinates) | Operation::Flash(coordinates) => {
+                        [coordinates.as_ref().map(|c| c.format), None]
+                    }
+                }
+            }
+            _ => continue,
+        };
+        for format in formats.iter().flatten() {
+            match expected {
+                None => expected = Some(*format),
+                Some(expected) if expected != *format => {
+                    return Err(LocatedError::new(
+                        index,
+                        GerberError::CoordinateFormatError(format!(
+                            "format {} differs from the {} format {}",
+                            describe_format(format),
+                            if declared.is_some() {
+                                "declared"
+                            } else {
+                                "first"
+                            },
+                            describe_format(&expected)
+                        )),
+                    ));
+                }
+                Some(_) => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+
+    use std::convert::TryFrom;
+    use std::io::BufWriter;
+
+    use crate::traits::GerberCode;
+    use crate::{CoordinateMode, CoordinateNumber, Unit, ZeroOmission};
+
+    fn nan_circle() -> ExtendedCode {
+        ExtendedCode::ApertureDefinition(ApertureDefinition::new(
+            10,
+            Aperture::Circle(Circle::new(f64::NAN)),
+        ))
+    }
+
+    #[test]
+    fn test_permissive_serialize_emits_nan() {
+        assert_code!(nan_circle(), "%ADD10C,NaN*%\n");
+    }
+
+    #[test]
+    fn test_strict_serialize_rejects_nan() {
+        let mut buf = Vec::new();
+        let result = nan_circle().serialize_strict(&mut buf);
+        assert!(matches!(result, Err(GerberError::RangeError(_))));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_strict_serialize_valid() {
+        let commands: Vec<Command> = vec![
+            ExtendedCode::ApertureDefinition(ApertureDefinition::new(
+                10,
+                Aperture::Circle(Circle::new(0.5)),
+            ))
+            .into(),
+            DCode::SelectAperture(10).into(),
+        ];
+        let mut buf = Vec::new();
+        commands.serialize_strict(&mut buf).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), "%ADD10C,0.5*%\nD10*\n");
+    }
+
+    #[test]
+    fn test_validate_macro_references() {
+        let macro_aperture = |code, name: &str| {
+            ApertureDefinition::new(code, Aperture::Macro(name.to_string(), None))
+        };
+        let commands: Vec<Command> = vec![
+            ExtendedCode::ApertureDefinition(macro_aperture(10, "DONUT")).into(),
+            ExtendedCode::ApertureMacro(ApertureMacro::new("DONUT")).into(),
+            ExtendedCode::ApertureDefinition(macro_aperture(11, "DONUT")).into(),
+            ExtendedCode::ApertureDefinition(macro_aperture(12, "THERMAL")).into(),
+            ExtendedCode::ApertureDefinition(ApertureDefinition::new(
+                13,
+                Aperture::Circle(Circle::new(0.5)),
+            ))
+            .into(),
+        ];
+        let errors = validate_macro_references(&commands);
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0]
+            .to_string()
+            .contains("Command 0: Validation failed: aperture D10 uses the macro 'DONUT' before it is defined"));
+        assert!(errors[1].to_string().contains("'THERMAL'"));
+    }
+
+    #[test]
+    fn test_strict_aperture_block_definition() {
+        let inner = ApertureBlockDefinition {
+            code: 11,
+            commands: vec![DCode::SelectAperture(10).into()],
+        };
+        let mut outer = ApertureBlockDefinition {
+            code: 12,
+            commands: inner.into_commands(),
+        };
+        assert!(outer.validate_strict().is_ok());
+
+        outer.commands.pop();
+        let error = outer.validate_strict().unwrap_err();
+        assert!(matches!(&error, GerberError::Located(located) if located.index == 0));
+        assert!(error
+            .to_string()
+            .contains("aperture block D11 is not closed"));
+
+        outer.commands = vec![ExtendedCode::ApertureBlock(ApertureBlock::Close).into()];
+        assert!(outer.validate_strict().is_err());
+
+        outer.commands = vec![MCode::EndOfFile.into()];
+        let error = outer.validate_strict().unwrap_err();
+        assert!(error.to_string().contains("end of file (M02)"));
+    }
+
+    #[test]
+    fn test_strict_aperture_codes() {
+        let reserved = ApertureDefinition::new(9, Aperture::Circle(Circle::new(0.5)));
+        assert!(reserved.validate_strict().is_err());
+        assert!(DCode::SelectAperture(3).validate_strict().is_err());
+        assert!(ExtendedCode::ApertureBlock(ApertureBlock::Open { code: 0 })
+            .validate_strict()
+            .is_err());
+    }
+
+    #[test]
+    fn test_strict_attribute_characters() {
+        let component = ObjectAttribute::Component("R,1".to_string());
+        assert!(matches!(
+            component.validate_strict(),
+            Err(GerberError::ValidationError(_))
+        ));
+        let comment = GCode::Comment(CommentContent::String("100%".to_string()));
+        assert!(comment.validate_strict().is_err());
+        let net = ObjectAttribute::Net(Net::Connected(vec!["GND".into(), "VCC*".into()]));
+        assert!(net.validate_strict().is_err());
+        let net = ObjectAttribute::Net(Net::Connected(vec!["GND".into(), "VCC".into()]));
+        assert!(net.validate_strict().is_ok());
+        let net = Net::Connected(vec!["Net1".into(), "".into(), "Net3".into()]);
+        assert!(matches!(
+            net.validate_strict(),
+            Err(GerberError::ValidationError(_))
+        ));
+        let same = FileAttribute::SameCoordinates(Some(Ident::Name("PANEL\n1".to_string())));
+        assert!(same.validate_strict().is_err());
+        let same = FileAttribute::SameCoordinates(Some(Ident::Name("PANEL_1".to_string())));
+        assert!(same.validate_strict().is_ok());
+    }
+
+    #[test]
+    fn test_strict_file_function_layer() {
+        let component = |layer, pos| FileFunction::Component { layer, pos };
+        assert!(component(1, ExtendedPosition::Top)
+            .validate_strict()
+            .is_ok());
+        assert!(component(3, ExtendedPosition::Inner)
+            .validate_strict()
+            .is_ok());
+        assert!(matches!(
+            component(1, ExtendedPosition::Inner).validate_strict(),
+            Err(GerberError::RangeError(_))
+        ));
+        assert!(component(0, ExtendedPosition::Bottom)
+            .validate_strict()
+            .is_err());
+        let copper = FileFunction::Copper {
+            layer: -1,
+            pos: ExtendedPosition::Top,
+            copper_type: None,
+        };
+        assert!(copper.validate_strict().is_err());
+    }
+
+    #[test]
+    fn test_strict_coordinates() {
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 4);
+        let ok = Operation::Flash(Some(Coordinates::at_x(99, cf)));
+        assert!(ok.validate_strict().is_ok());
+        let too_large = Operation::Flash(Some(Coordinates::at_x(100, cf)));
+        assert!(too_large.validate_strict().is_err());
+
+        let cf23 = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 3);
+        assert!(ExtendedCode::CoordinateFormat(cf23)
+            .validate_strict()
+            .is_err());
+    }
+
+    #[test]
+    fn test_validate_quadrant_mode() {
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 4);
+        let arc: Command = DCode::Operation(Operation::Interpolate(
+            Some(Coordinates::new(1, 1, cf)),
+            Some(CoordinateOffset::new(1, 0, cf)),
+        ))
+        .into();
+        let line: Command = DCode::Operation(Operation::Interpolate(
+            Some(Coordinates::new(2, 2, cf)),
+            None,
+        ))
+        .into();
+
+        let commands: Vec<Command> = vec![
+            GCode::InterpolationMode(InterpolationMode::Linear).into(),
+            line.clone(),
+            GCode::InterpolationMode(InterpolationMode::ClockwiseCircular).into(),
+            arc.clone(),
+            GCode::QuadrantMode(QuadrantMode::Multi).into(),
+            arc,
+        ];
+        let errors = validate_quadrant_mode(&commands);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].index, 3);
+        assert!(errors[0].to_string().contains("Command 3"));
+
+        assert!(validate_quadrant_mode(&commands[4..]).is_empty());
+    }
+
+    #[test]
+    fn test_validate_eof() {
+        let eof: Command = MCode::EndOfFile.into();
+        let select: Command = DCode::SelectAperture(10).into();
+
+        assert!(validate_eof(&[select.clone(), eof.clone()]).is_ok());
+        assert!(matches!(
+            validate_eof(std::slice::from_ref(&select)),
+            Err(LocatedError {
+                index: 1,
+                error: GerberError::MissingDataError(_)
+            })
+        ));
+        assert!(validate_eof(&[]).is_err());
+        assert!(matches!(
+            validate_eof(&[eof.clone(), select]),
+            Err(LocatedError {
+                index: 0,
+                error: GerberError::ValidationError(_)
+            })
+        ));
+        assert!(validate_eof(&[eof.clone(), eof]).is_err());
+    }
+
+    #[test]
+    fn test_validate_single_header() {
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 6);
+        let flash: Command =
+            DCode::Operation(Operation::Flash(Some(Coordinates::new(1, 1, cf)))).into();
+        let mut commands: Vec<Command> = vec![
+            ExtendedCode::CoordinateFormat(cf).into(),
+            ExtendedCode::Unit(Unit::Millimeters).into(),
+            DCode::SelectAperture(10).into(),
+            flash.clone(),
+            MCode::EndOfFile.into(),
+        ];
+        assert!(validate_single_header(&commands).is_ok());
+        assert!(validate_single_header(&commands[2..]).is_ok());
+
+        // a second FS mid-stream
+        commands.insert(4, ExtendedCode::CoordinateFormat(cf).into());
+        let error = validate_single_header(&commands).unwrap_err();
+        assert_eq!(error.index, 4);
+        assert!(matches!(error.error, GerberError::ValidationError(_)));
+        assert!(error
+            .to_string()
+            .contains("Command 4: Validation failed: the coordinate format (FS)"));
+
+        let commands: Vec<Command> = vec![
+            ExtendedCode::Unit(Unit::Millimeters).into(),
+            ExtendedCode::Unit(Unit::Inches).into(),
+        ];
+        assert!(validate_single_header(&commands).is_err());
+
+        // a single MO, but after the first operation
+        let commands: Vec<Command> = vec![
+            ExtendedCode::CoordinateFormat(cf).into(),
+            flash,
+            ExtendedCode::Unit(Unit::Millimeters).into(),
+        ];
+        let error = validate_single_header(&commands).unwrap_err();
+        assert!(error.to_string().contains(
+            "Command 2: Validation failed: the unit (MO) must be set before the first operation (command 1)"
+        ));
+    }
+
+    #[test]
+    fn test_validate_defined_apertures() {
+        let commands: Vec<Command> = vec![
+            DCode::SelectAperture(10).into(),
+            ExtendedCode::ApertureDefinition(ApertureDefinition::new(
+                10,
+                Aperture::Circle(Circle::new(0.5)),
+            ))
+            .into(),
+            DCode::SelectAperture(10).into(),
+            ExtendedCode::ApertureBlock(ApertureBlock::Open { code: 11 }).into(),
+            ExtendedCode::ApertureBlock(ApertureBlock::Close).into(),
+            DCode::SelectAperture(11).into(),
+            DCode::SelectAperture(12).into(),
+        ];
+        let errors = validate_defined_apertures(&commands);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].index, 0);
+        assert_eq!(errors[1].index, 6);
+        assert!(errors[0].to_string().contains(
+            "Command 0: Validation failed: aperture D10 is selected before it is defined"
+        ));
+        assert!(errors[1]
+            .to_string()
+            .contains("Command 6: Validation failed: aperture D12"));
+
+        let commands: Vec<Command> = vec![
+            ApertureBlockDefinition {
+                code: 13,
+                commands: Vec::new(),
+            }
+            .into(),
+            DCode::SelectAperture(13).into(),
+        ];
+        assert!(validate_defined_apertures(&commands).is_empty());
+    }
+
+    #[test]
+    fn test_validate_aperture_codes() {
+        let commands: Vec<Command> = vec![
+            ApertureDefinition::new(999, Aperture::Circle(Circle::new(0.5))).into(),
+            ApertureDefinition::new(1000, Aperture::Circle(Circle::new(0.5))).into(),
+            DCode::SelectAperture(1000).into(),
+        ];
+        assert!(validate_aperture_codes(&commands, None).is_empty());
+
+        let errors = validate_aperture_codes(&commands, Some(LEGACY_MAX_APERTURE_CODE));
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].index, 1);
+        assert!(matches!(errors[0].error, GerberError::RangeError(_)));
+        assert!(errors[0].to_string().contains("Command 1"));
+        assert!(errors[1].to_string().contains("Command 2"));
+    }
+
+    #[test]
+    fn test_validate_single_file_function() {
+        let copper: Command =
+            ExtendedCode::FileAttribute(FileAttribute::FileFunction(FileFunction::Copper {
+                layer: 1,
+                pos: ExtendedPosition::Top,
+                copper_type: None,
+            }))
+            .into();
+        let profile: Command =
+            GCode::Comment(CommentContent::Standard(StandardComment::FileAttribute(
+                FileAttribute::FileFunction(FileFunction::Profile(None)),
+            )))
+            .into();
+
+        assert!(validate_single_file_function(std::slice::from_ref(&copper)).is_empty());
+        let errors = validate_single_file_function(&[copper, MCode::EndOfFile.into(), profile]);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].to_string().contains("Command 2"));
+    }
+
+    #[test]
+    fn test_validate_single_image_name() {
+        let name = |name: &str| -> Command {
+            ExtendedCode::ImageName(ImageName {
+                name: name.to_string(),
+            })
+            .into()
+        };
+        let commands = vec![name("PANEL_1"), MCode::EndOfFile.into()];
+        assert!(validate_single_image_name(&commands).is_empty());
+
+        let commands = vec![name("PANEL_1"), name("PANEL_2"), MCode::EndOfFile.into()];
+        let errors = validate_single_image_name(&commands);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].to_string().contains("Command 1"));
+    }
+
+    #[test]
+    fn test_validate_closed_at_eof() {
+        let commands: Vec<Command> = vec![
+            GCode::RegionMode(RegionMode::On).into(),
+            GCode::RegionMode(RegionMode::Off).into(),
+            ExtendedCode::ApertureBlock(ApertureBlock::Open { code: 10 }).into(),
+            ExtendedCode::ApertureBlock(ApertureBlock::Close).into(),
+            MCode::EndOfFile.into(),
+        ];
+        assert!(validate_closed_at_eof(&commands).is_empty());
+
+        let commands: Vec<Command> = vec![
+            DCode::SelectAperture(10).into(),
+            GCode::RegionMode(RegionMode::On).into(),
+            MCode::EndOfFile.into(),
+            GCode::RegionMode(RegionMode::Off).into(),
+        ];
+        let errors = validate_closed_at_eof(&commands);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0]
+            .to_string()
+            .contains("Command 1: Validation failed: region mode"));
+
+        let commands: Vec<Command> = vec![
+            ExtendedCode::ApertureBlock(ApertureBlock::Open { code: 10 }).into(),
+            ExtendedCode::StepAndRepeat(StepAndRepeat::Close).into(),
+            GCode::RegionMode(RegionMode::On).into(),
+        ];
+        let errors = validate_closed_at_eof(&commands);
+        assert_eq!(errors.len(), 3);
+        assert!(errors[0]
+            .to_string()
+            .contains("Command 1: Validation failed: step and repeat closed"));
+        assert!(errors[1]
+            .to_string()
+            .contains("Command 2: Validation failed: region mode"));
+        assert!(errors[2]
+            .to_string()
+            .contains("Command 0: Validation failed: aperture block D10"));
+    }
+
+    #[test]
+    fn test_validate_uniform_format() {
+        let cf24 = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 4);
+        let cf26 = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 6);
+        let flash = |format: CoordinateFormat| -> Command {
+            DCode::Operation(Operation::Flash(Some(Coordinates::new(1, 2, format)))).into()
+        };
+
+        let commands = vec![cf24.into(), flash(cf24), flash(cf24)];
+        assert!(validate_uniform_format(&commands).is_ok());
+
+        let commands = vec![cf24.into(), flash(cf24), flash(cf26)];
+        let error = validate_uniform_format(&commands).unwrap_err();
+        assert_eq!(error.index, 2);
+        assert!(matches!(error.error, GerberError::CoordinateFormatError(_)));
+        assert!(error.to_string().contains("Command 2"));
+
+        let commands = vec![cf24.into(), flash(cf26)];
+        assert!(validate_uniform_format(&commands).is_err());
+
+        let arc: Command = DCode::Operation(Operation::Interpolate(
+            Some(Coordinates::new(1, 2, cf24)),
+            Some(CoordinateOffset::new(1, 0, cf26)),
+        ))
+        .into();
+        assert!(validate_uniform_format(&[flash(cf24), arc]).is_err());
+    }
+
+    #[test]
+    fn test_strict_macro() {
+        let am = ApertureMacro::new("BAD")
+            .add_content(CirclePrimitive::new(MacroDecimal::Value(f64::INFINITY)));
+        assert!(am.validate_strict().is_err());
+        let am =
+            ApertureMacro::new("GOOD").add_content(CirclePrimitive::new(MacroDecimal::Value(1.0)));
+        assert!(am.validate_strict().is_ok());
+
+        // Out of range primitives are still serialized, but rejected by strict validation
+        let am = ApertureMacro::new("NEG")
+            .add_content(CirclePrimitive::new(MacroDecimal::Value(-1.0)))
+            .add_content(ThermalPrimitive::new(
+                MacroDecimal::Value(0.8),
+                MacroDecimal::Value(0.5),
+                MacroDecimal::Value(0.1),
+            ));
+        let code = ExtendedCode::ApertureMacro(am);
+        let mut buf = Vec::new();
+        code.serialize(&mut buf).unwrap();
+        assert!(code.validate_strict().is_err());
+        assert!(code.serialize_strict(&mut buf).is_err());
+    }
+
+    #[test]
+    fn test_validate_arc() {
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 4);
+        let start = Coordinates::new(1, 0, cf);
+        let quarter_end = Coordinates::new(0, 1, cf);
+        let ccw = InterpolationMode::CounterclockwiseCircular;
+        let cw = InterpolationMode::ClockwiseCircular;
+
+        // A 90° arc around the origin, the center is at (-1, 0) from the start.
+        let signed = CoordinateOffset::new(-1, 0, cf);
+        assert!(validate_arc(&start, &quarter_end, &signed, QuadrantMode::Multi, ccw).is_ok());
+        let unsigned = CoordinateOffset::new(1, 0, cf);
+        assert!(validate_arc(&start, &quarter_end, &unsigned, QuadrantMode::Single, ccw).is_ok());
+
+        // The same end point clockwise is a 270° arc.
+        assert!(validate_arc(&start, &quarter_end, &signed, QuadrantMode::Multi, cw).is_ok());
+        assert!(validate_arc(&start, &quarter_end, &unsigned, QuadrantMode::Single, cw).is_err());
+
+        // A signed offset in single quadrant mode.
+        assert!(validate_arc(&start, &quarter_end, &signed, QuadrantMode::Single, ccw).is_err());
+
+        // A full circle is only possible in multi quadrant mode.
+        assert!(validate_arc(&start, &start, &signed, QuadrantMode::Multi, ccw).is_ok());
+
+        // The end point is not on the circle.
+        let off_circle = Coordinates::new(0, 2, cf);
+        let error =
+            validate_arc(&start, &off_circle, &signed, QuadrantMode::Multi, ccw).unwrap_err();
+        assert!(error.to_string().contains("radius"));
+
+        // Rounding of the coordinates is tolerated.
+        let rounded = Coordinates::new(
+            CoordinateNumber::try_from(0.7072).unwrap(),
+            CoordinateNumber::try_from(0.7072).unwrap(),
+            cf,
+        );
+        assert!(validate_arc(&start, &rounded, &signed, QuadrantMode::Multi, ccw).is_ok());
+
+        let missing_y = Coordinates::at_x(0, cf);
+        assert!(matches!(
+            validate_arc(&start, &missing_y, &signed, QuadrantMode::Multi, ccw),
+            Err(GerberError::MissingDataError(_))
+        ));
+        assert!(validate_arc(
+            &start,
+            &quarter_end,
+            &signed,
+            QuadrantMode::Multi,
+            InterpolationMode::Linear
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,371 @@
+//! Writers that can be used as the target of `GerberCode::serialize`.
+
+use std::io::{Error as IoError, ErrorKind, Write};
+
+use crate::errors::GerberResult;
+use crate::extended_codes::{ApertureBlock, Polarity};
+use crate::function_codes::{DCode, GCode, InterpolationMode, QuadrantMode};
+use crate::traits::GerberCode;
+use crate::types::{Command, ExtendedCode, FunctionCode};
+
+/// Some older readers reject lines longer than 255 characters.
+pub const DEFAULT_MAX_LINE_LENGTH: usize = 255;
+
+/// Details of a line that exceeded the maximum line length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineLengthViolation {
+    /// 1-based line number
+    pub line: usize,
+    /// Length of the line when the violation was detected, excluding the line terminator
+    pub length: usize,
+}
+
+/// A writer that checks the length of every line written to the inner writer.
+///
+/// By default, a line that exceeds the limit results in an I/O error (of kind
+/// `InvalidData`) and the offending data is not passed to the inner writer.
+/// Use [`LineLengthWriter::warn_only`] to write everything and collect the
+/// violations instead.
+#[derive(Debug)]
+pub struct LineLengthWriter<W: Write> {
+    inner: W,
+    max_line_length: usize,
+    warn_only: bool,
+    line: usize,
+    current_length: usize,
+    violations: Vec<LineLengthViolation>,
+}
+
+impl<W: Write> LineLengthWriter<W> {
+    pub fn new(inner: W, max_line_length: usize) -> Self {
+        LineLengthWriter {
+            inner,
+            max_line_length,
+            warn_only: false,
+            line: 1,
+            current_length: 0,
+            violations: Vec::new(),
+        }
+    }
+
+    pub fn warn_only(mut self) -> Self {
+        self.warn_only = true;
+        self
+    }
+
+    pub fn violations(&self) -> &[LineLengthViolation] {
+        &self.violations
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    fn check(&mut self, buf: &[u8]) -> Vec<LineLengthViolation> {
+        let mut violations = Vec::new();
+        let mut line = self.line;
+        let mut length = self.current_length;
+        let mut reported = length > self.max_line_length;
+        for byte in buf {
+            match byte {
+                b'\n' => {
+                    line += 1;
+                    length = 0;
+                    reported = false;
+                }
+                b'\r' => {}
+                _ => {
+                    length += 1;
+                    if length > self.max_line_length && !reported {
+                        violations.push(LineLengthViolation { line, length });
+                        reported = true;
+                    }
+                }
+            }
+        }
+        // update the length of violations that are still being written to
+        if let Some(last) = violations.last_mut() {
+            if last.line == line {
+                last.length = length;
+            }
+        }
+
+        self.line = line;
+        self.current_length = length;
+        violations
+    }
+}
+
+impl<W: Write> Write for LineLengthWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let (line, current_length) = (self.line, self.current_length);
+        let violations = self.check(buf);
+        if let Some(violation) = violations.first() {
+            if !self.warn_only {
+                self.line = line;
+                self.current_length = current_length;
+                return Err(IoError::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Line {} exceeds the maximum line length of {} characters",
+                        violation.line, self.max_line_length
+                    ),
+                ));
+            }
+            self.violations.extend(violations);
+        }
+        self.inner.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Serializes commands to the inner writer, leaving out the commands that would not change the
+/// current interpolation mode (G01/G02/G03), quadrant mode (G74/G75), selected aperture (Dnn)
+/// or polarity (LP).
+///
+/// Every category is suppressed by default, each one can be turned off to write its commands
+/// verbatim. Since the commands in an aperture block (AB) may change any of these, the tracked
+/// state is forgotten when a block is opened or closed.
+#[derive(Debug)]
+pub struct ModalWriter<W: Write> {
+    inner: W,
+    suppress_interpolation_mode: bool,
+    suppress_quadrant_mode: bool,
+    suppress_aperture: bool,
+    suppress_polarity: bool,
+    interpolation_mode: Option<InterpolationMode>,
+    quadrant_mode: Option<QuadrantMode>,
+    aperture: Option<i32>,
+    polarity: Option<Polarity>,
+}
+
+impl<W: Write> ModalWriter<W> {
+    pub fn new(inner: W) -> Self {
+        ModalWriter {
+            inner,
+            suppress_interpolation_mode: true,
+            suppress_quadrant_mode: true,
+            suppress_aperture: true,
+            suppress_polarity: true,
+            interpolation_mode: None,
+            quadrant_mode: None,
+            aperture: None,
+            polarity: None,
+        }
+    }
+
+    pub fn suppress_interpolation_mode(mut self, suppress: bool) -> Self {
+        self.suppress_interpolation_mode = suppress;
+        self
+    }
+
+    pub fn suppress_quadrant_mode(mut self, suppress: bool) -> Self {
+        self.suppress_quadrant_mode = suppress;
+        self
+    }
+
+    pub fn suppress_aperture(mut self, suppress: bool) -> Self {
+        self.suppress_aperture = suppress;
+        self
+    }
+
+    pub fn suppress_polarity(mut self, suppress: bool) -> Self {
+        self.suppress_polarity = suppress;
+        self
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    /// Writes the command, unless it is a no-op in a suppressed category.
+    pub fn write_command(&mut self, command: &Command) -> GerberResult<()> {
+        if self.is_redundant(command) {
+            return Ok(());
+        }
+        command.serialize(&mut self.inner)
+    }
+
+    pub fn write_commands(&mut self, commands: &[Command]) -> GerberResult<()> {
+        for command in commands {
+            self.write_command(command)?;
+        }
+        Ok(())
+    }
+
+    /// Updates the tracked state and returns whether the command can be left out.
+    fn is_redundant(&mut self, command: &Command) -> bool {
+        fn update<T: PartialEq + Copy>(current: &mut Option<T>, value: T, suppress: bool) -> bool {
+            let redundant = suppress && *current == Some(value);
+            *current = Some(value);
+            redundant
+        }
+
+        match command {
+            Command::FunctionCode(FunctionCode::GCode(GCode::InterpolationMode(mode))) => update(
+                &mut self.interpolation_mode,
+                *mode,
+                self.suppress_interpolation_mode,
+            ),
+            Command::FunctionCode(FunctionCode::GCode(GCode::QuadrantMode(mode))) => {
+                update(&mut self.quadrant_mode, *mode, self.suppress_quadrant_mode)
+            }
+            Command::FunctionCode(FunctionCode::DCode(DCode::SelectAperture(code))) => {
+                update(&mut self.aperture, *code, self.suppress_aperture)
+            }
+            Command::ExtendedCode(ExtendedCode::LoadPolarity(polarity)) => {
+                update(&mut self.polarity, *polarity, self.suppress_polarity)
+            }
+            Command::ExtendedCode(ExtendedCode::ApertureBlock(
+                ApertureBlock::Open { .. } | ApertureBlock::Close,
+            ))
+            | Command::ExtendedCode(ExtendedCode::ApertureBlockDefinition(_)) => {
+                self.interpolation_mode = None;
+                self.quadrant_mode = None;
+                self.aperture = None;
+                self.polarity = None;
+                false
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::traits::GerberCode;
+    use crate::{
+        CoordinateFormat, CoordinateMode, Coordinates, Net, ObjectAttribute, Operation,
+        ZeroOmission,
+    };
+
+    fn net_attribute(count: usize) -> ExtendedCode {
+        ExtendedCode::ObjectAttribute(ObjectAttribute::Net(Net::Connected(
+            (0..count).map(|i| format!("NET{}", i)).collect(),
+        )))
+    }
+
+    #[test]
+    fn test_short_lines_are_written() {
+        let mut writer = LineLengthWriter::new(Vec::new(), DEFAULT_MAX_LINE_LENGTH);
+        net_attribute(2).serialize(&mut writer).unwrap();
+        assert!(writer.violations().is_empty());
+        let code = String::from_utf8(writer.into_inner()).unwrap();
+        assert_eq!(code, "%TO.N,NET0,NET1*%\n");
+    }
+
+    #[test]
+    fn test_long_net_attribute_is_rejected() {
+        let mut writer = LineLengthWriter::new(Vec::new(), DEFAULT_MAX_LINE_LENGTH);
+        let result = net_attribute(100).serialize(&mut writer);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_long_net_attribute_warn_only() {
+        let mut writer = LineLengthWriter::new(Vec::new(), DEFAULT_MAX_LINE_LENGTH).warn_only();
+        net_attribute(1).serialize(&mut writer).unwrap();
+        net_attribute(100).serialize(&mut writer).unwrap();
+        net_attribute(1).serialize(&mut writer).unwrap();
+
+        assert_eq!(writer.violations().len(), 1);
+        let violation = writer.violations()[0];
+        assert_eq!(violation.line, 2);
+        assert!(violation.length > DEFAULT_MAX_LINE_LENGTH);
+        assert_eq!(
+            writer.into_inner().iter().filter(|b| **b == b'\n').count(),
+            3
+        );
+    }
+
+    #[test]
+    fn test_line_length_across_writes() {
+        let mut writer = LineLengthWriter::new(Vec::new(), 4);
+        writer.write_all(b"ab").unwrap();
+        writer.write_all(b"cd\n").unwrap();
+        assert!(writer.write_all(b"e").is_ok());
+        assert!(writer.write_all(b"fghi").is_err());
+        // the rejected data is not written
+        assert_eq!(writer.into_inner(), b"abcd\ne");
+    }
+
+    fn modal_commands() -> Vec<Command> {
+        let cf = CoordinateFormat::new(ZeroOmission::Leading, CoordinateMode::Absolute, 2, 4);
+        let draw = |x: i32| -> Command {
+            DCode::Operation(Operation::Interpolate(
+                Some(Coordinates::new(x, 0, cf)),
+                None,
+            ))
+            .into()
+        };
+        vec![
+            ExtendedCode::LoadPolarity(Polarity::Dark).into(),
+            GCode::QuadrantMode(QuadrantMode::Multi).into(),
+            DCode::SelectAperture(10).into(),
+            GCode::InterpolationMode(InterpolationMode::Linear).into(),
+            draw(1),
+            ExtendedCode::LoadPolarity(Polarity::Dark).into(),
+            GCode::QuadrantMode(QuadrantMode::Multi).into(),
+            DCode::SelectAperture(10).into(),
+            GCode::InterpolationMode(InterpolationMode::Linear).into(),
+            draw(2),
+            DCode::SelectAperture(11).into(),
+            draw(3),
+            DCode::SelectAperture(10).into(),
+            draw(4),
+        ]
+    }
+
+    #[test]
+    fn test_modal_writer_suppresses_redundant_commands() {
+        let mut writer = ModalWriter::new(Vec::new());
+        writer.write_commands(&modal_commands()).unwrap();
+        let code = String::from_utf8(writer.into_inner()).unwrap();
+        assert_eq!(
+            code,
+            "%LPD*%\nG75*\nD10*\nG01*\nX10000Y0D01*\nX20000Y0D01*\nD11*\nX30000Y0D01*\n\
+             D10*\nX40000Y0D01*\n"
+        );
+    }
+
+    #[test]
+    fn test_modal_writer_verbatim_categories() {
+        let mut writer = ModalWriter::new(Vec::new())
+            .suppress_interpolation_mode(false)
+            .suppress_quadrant_mode(false)
+            .suppress_aperture(false)
+            .suppress_polarity(false);
+        writer.write_commands(&modal_commands()).unwrap();
+        let mut verbatim = Vec::new();
+        modal_commands().serialize(&mut verbatim).unwrap();
+        assert_eq!(writer.into_inner(), verbatim);
+
+        let mut writer = ModalWriter::new(Vec::new()).suppress_aperture(false);
+        writer.write_commands(&modal_commands()).unwrap();
+        let code = String::from_utf8(writer.into_inner()).unwrap();
+        assert_eq!(code.matches("D10*").count(), 3);
+        assert_eq!(code.matches("G01*").count(), 1);
+    }
+
+    #[test]
+    fn test_modal_writer_forgets_state_in_aperture_blocks() {
+        let mut writer = ModalWriter::new(Vec::new());
+        writer
+            .write_commands(&[
+                DCode::SelectAperture(10).into(),
+                ExtendedCode::ApertureBlock(ApertureBlock::Open { code: 20 }).into(),
+                DCode::SelectAperture(10).into(),
+                ExtendedCode::ApertureBlock(ApertureBlock::Close).into(),
+                DCode::SelectAperture(10).into(),
+            ])
+            .unwrap();
+        let code = String::from_utf8(writer.into_inner()).unwrap();
+        assert_eq!(code, "D10*\n%AB20*%\nD10*\n%AB*%\nD10*\n");
+    }
+}